@@ -1,17 +1,14 @@
 use actix_web::web;
 use dotenv::dotenv;
 use lsp_proxy::{
-    config::{Lang, LsArgs},
-    language_server::server_runners::start_lang_server,
+    config::{Lang, LsArgs, ReplayArgs},
+    language_server::{replay::replay, server_runners::start_lang_server},
+    logging,
 };
 use lsp_proxy::{run, AppState};
-use std::{
-    net::TcpListener,
-    path::Path,
-    sync::{atomic::AtomicBool, Arc},
-};
+use std::{env, net::TcpListener, path::Path, sync::Arc};
 use structopt::StructOpt;
-use tokio::sync::Mutex;
+use tracing::info;
 
 fn get_tcp_listener(port: i32) -> TcpListener {
     TcpListener::bind(format!("127.0.0.1:{}", port)).expect("failed to bind port to {}")
@@ -20,27 +17,31 @@ fn get_tcp_listener(port: i32) -> TcpListener {
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    if env::args().nth(1).as_deref() == Some("replay") {
+        let args = ReplayArgs::from_iter(env::args().skip(1));
+        logging::init(args.log_level.as_deref(), args.log_json);
+        let report = replay(&args).await?;
+        print!("{}", report);
+        if !report.mismatches.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     let tmp_dir = tempfile::Builder::new().prefix("lsp-proxy").tempdir()?;
     let args = LsArgs::from_args();
+    logging::init(args.log_level.as_deref(), args.log_json);
+    info!(config = ?args, "Starting proxy");
     if !Path::new(&args.codebase_path).exists() {
         panic!("Directory does not exist!")
     }
-    let path: String = args.codebase_path;
-
+    let port = args.port;
     let child =
         start_lang_server(Lang::Java, tmp_dir.path()).expect("Couldn't start language server!");
-    println!("Listening on {} ... 🚀", args.port);
-
-    let state = web::Data::new(AppState {
-        ws_session_started: AtomicBool::from(false),
-        lang: args.language,
-        workspace_dir: path,
-        program_input: Mutex::new(Vec::new()),
-        user_program: Arc::new(Mutex::new(None)),
-    });
+    info!(port, "Listening");
+    let state = web::Data::new(AppState::new(args, tmp_dir.path().join("build"))?);
 
     run(
-        get_tcp_listener(args.port),
+        get_tcp_listener(port),
         Arc::new(std::sync::Mutex::new(child)),
         state,
     )?
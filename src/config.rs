@@ -1,4 +1,6 @@
+use crate::language_server::uri_mapping::UriPrefix;
 use clap::arg_enum;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -18,6 +20,90 @@ pub struct LsArgs {
 
     #[structopt(short = "d", long, env, required_if("language", "Custom"))]
     pub custom_lang_server_cmd: Option<String>,
+
+    /// Local repository that Maven and Gradle builds resolve dependencies from, as builds are run offline
+    #[structopt(long, env)]
+    pub build_repo_path: Option<String>,
+
+    /// JUnit Platform console launcher jar, for running Java tests outside of Maven and Gradle projects
+    #[structopt(long, env)]
+    pub junit_path: Option<String>,
+
+    /// Command that runs the codebase's tests, writing JUnit XML reports to `$TEST_REPORTS_DIR` if it can
+    #[structopt(long, env)]
+    pub test_cmd: Option<String>,
+
+    /// Command that starts a Debug Adapter Protocol server on stdio, run from the codebase directory
+    #[structopt(long, env)]
+    pub debug_adapter_cmd: Option<String>,
+
+    /// Command that builds a file before it is run, for languages other than Java, with `{file}`, `{out}` and `{root}` filled in
+    #[structopt(long, env)]
+    pub build_template: Option<String>,
+
+    /// Command that runs a file, for languages other than Java, with the same replacements as the build template
+    #[structopt(long, env)]
+    pub run_template: Option<String>,
+
+    /// Seconds that programs can run for once built, before they are stopped
+    #[structopt(long, env)]
+    pub run_timeout: Option<u64>,
+
+    /// Bytes of output a program can send before the rest of its output is dropped
+    #[structopt(long, env)]
+    pub max_output_bytes: Option<usize>,
+
+    /// Programs each client session can run at once, with further runs rejected until one finishes
+    #[structopt(long, default_value = "4", env)]
+    pub max_runs: usize,
+
+    /// Directory to record each language server session's messages in, for `lsp_proxy replay`
+    #[structopt(long, env)]
+    pub record_lsp_dir: Option<String>,
+
+    /// Milliseconds a language server request can take to be answered before it is logged as slow
+    #[structopt(long, default_value = "5000", env)]
+    pub slow_request_ms: u64,
+
+    /// Milliseconds the language server has to answer the requests made through the `/lsp` REST endpoints
+    #[structopt(long, default_value = "10000", env)]
+    pub lsp_request_timeout_ms: u64,
+
+    /// JSON file of `initializationOptions` to merge over the client's
+    #[structopt(long, env)]
+    pub initialization_options: Option<String>,
+
+    /// JSON file of rules for which LSP messages the proxy passes on, by method and direction
+    #[structopt(long, env)]
+    pub message_policy: Option<String>,
+
+    /// Client URI prefix and the language server's in its place, as `<client prefix>=<server prefix>`. Can be repeated
+    #[structopt(long, env, use_delimiter = true)]
+    pub uri_map: Vec<UriPrefix>,
+
+    /// Answer server requests that clients often don't implement, such as `workspace/configuration`
+    #[structopt(long)]
+    pub answer_server_requests: bool,
+
+    /// JSON file of settings to answer `workspace/configuration` requests from
+    #[structopt(long, env)]
+    pub client_settings: Option<String>,
+
+    /// What to log, as a level or `RUST_LOG` style directives. Defaults to `RUST_LOG`, then `info`
+    #[structopt(long, env)]
+    pub log_level: Option<String>,
+
+    /// Log as JSON lines rather than plain text
+    #[structopt(long)]
+    pub log_json: bool,
+
+    /// Directory the proxy keeps its data in. Defaults to `.lsp-proxy` in the codebase
+    #[structopt(long, env)]
+    pub data_dir: Option<String>,
+
+    /// Runs kept in the run history, with the oldest deleted once there are more
+    #[structopt(long, default_value = "100", env)]
+    pub max_stored_runs: usize,
 }
 
 arg_enum! {
@@ -29,6 +115,34 @@ arg_enum! {
     }
 }
 
+/// Replays a language server session recorded with `--record-lsp-dir`
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "lsp_proxy replay",
+    about = "Replays a recorded LSP session against a language server, and compares its responses with the recorded ones"
+)]
+pub struct ReplayArgs {
+    /// Recording to replay
+    #[structopt(parse(from_os_str))]
+    pub recording: PathBuf,
+
+    /// Command that starts the language server, or a mock of one, on stdio
+    #[structopt(short = "s", long)]
+    pub server_cmd: String,
+
+    /// Seconds to wait for each response
+    #[structopt(long, default_value = "10")]
+    pub timeout: u64,
+
+    /// What to log, as a level or `RUST_LOG` style directives. Defaults to `RUST_LOG`, then `info`
+    #[structopt(long, env)]
+    pub log_level: Option<String>,
+
+    /// Log as JSON lines rather than plain text
+    #[structopt(long)]
+    pub log_json: bool,
+}
+
 pub fn get_ls_args() -> LsArgs {
     LsArgs::from_args()
 }
@@ -0,0 +1,20 @@
+use crate::AppState;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use actix_web_actors::ws;
+use session::{DebugError, DebugSession};
+
+pub mod session;
+
+/// Starts a debug adapter process for a Debug Adapter Protocol session over a WebSocket connection
+pub async fn to_debug_adapter(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let debug_adapter_cmd = state
+        .debug_adapter_cmd
+        .clone()
+        .ok_or(DebugError::NoDebugAdapter)?;
+    let session = DebugSession::start(&debug_adapter_cmd, state)?;
+    ws::start(session, &req, stream)
+}
@@ -0,0 +1,332 @@
+use actix::{Actor, ActorContext, ActorFuture, AsyncContext, StreamHandler, WrapFuture};
+use actix_web::{
+    dev::HttpResponseBuilder, error, http::header, http::StatusCode, web, HttpResponse,
+};
+use actix_web_actors::ws;
+use derive_more::{Display, Error};
+use serde_json::{json, Value};
+use std::{
+    collections::HashSet,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Arc,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    process::{Child, ChildStdin},
+    sync::{mpsc::UnboundedReceiver, Mutex},
+};
+use tracing::{debug, error, info, trace, Instrument, Span};
+
+use crate::{
+    config,
+    language_server::server::{lsp_messages, wrap_lsp_message},
+    logging::{next_session_id, session_span},
+    metrics::ActiveSession,
+    program::{
+        code_runner::RunOptions,
+        program_handle::{ProgramEvent, ProgramHandle, RunLimits},
+        runners::{plan_java_prog, shell_command},
+        user_program::UserProgramError,
+    },
+    AppState,
+};
+
+/// Printed by the JVM's debug agent once it is waiting for a debugger to attach
+const JDWP_LISTENING: &str = "Listening for transport dt_socket at address:";
+
+/// WebSocket bridging Debug Adapter Protocol messages between a client and a debug adapter process
+pub struct DebugSession {
+    /// Debug adapter process, which is killed when the session is dropped
+    _adapter: Child,
+    stdin: Arc<Mutex<ChildStdin>>,
+    messages: Option<UnboundedReceiver<String>>,
+    state: web::Data<AppState>,
+    debuggee: Option<ProgramHandle>,
+    /// `attach` request to send once the debuggee is ready for it
+    pending_attach: Option<Value>,
+    /// Sequence numbers of `launch` requests that were passed on as `attach` requests
+    launches: HashSet<u64>,
+    span: Span,
+    _session: ActiveSession,
+}
+
+impl DebugSession {
+    /// Starts the debug adapter in the workspace, talking DAP over its stdio
+    pub fn start(debug_adapter_cmd: &str, state: web::Data<AppState>) -> Result<Self, DebugError> {
+        let mut adapter = shell_command(debug_adapter_cmd)
+            .current_dir(&state.workspace_dir)
+            .kill_on_drop(true)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|_| DebugError::FailedAdapterStart)?;
+        let stdin = adapter.stdin.take().ok_or(DebugError::FailedAdapterStart)?;
+        let stdout = adapter
+            .stdout
+            .take()
+            .ok_or(DebugError::FailedAdapterStart)?;
+
+        Ok(DebugSession {
+            _adapter: adapter,
+            stdin: Arc::new(Mutex::new(stdin)),
+            messages: Some(lsp_messages(stdout)),
+            _session: state.metrics.session("debug_adapter"),
+            state,
+            debuggee: None,
+            pending_attach: None,
+            launches: HashSet::new(),
+            span: session_span("debug_adapter", next_session_id()),
+        })
+    }
+
+    fn send_to_adapter(&self, message: String, ctx: &mut ws::WebsocketContext<Self>) {
+        let stdin = self.stdin.clone();
+        let write = async move {
+            let mut stdin = stdin.lock().await;
+            if let Err(err) = stdin.write_all(wrap_lsp_message(&message).as_bytes()).await {
+                error!(error = %err, "Error writing to debug adapter");
+            }
+        };
+        ctx.spawn(actix::fut::wrap_future(write.instrument(self.span.clone())));
+    }
+
+    fn launch(&mut self, request: Value, ctx: &mut ws::WebsocketContext<Self>) {
+        let state = self.state.clone();
+        let arguments = request["arguments"].clone();
+        let start =
+            async move { start_debuggee(&state, &arguments).await }.instrument(self.span.clone());
+
+        ctx.spawn(
+            start
+                .into_actor(self)
+                .map(move |started, session, ctx| match started {
+                    Ok((debuggee, events, port)) => {
+                        session.debuggee = Some(debuggee);
+                        session.pending_attach = Some(attach_request(request, port));
+                        ctx.add_stream(events);
+                    }
+                    Err(err) => ctx.text(launch_failed(&request["seq"], &err)),
+                }),
+        );
+    }
+
+    fn stop_debuggee(&mut self) {
+        if let Some(debuggee) = self.debuggee.take() {
+            let stop = async move {
+                if let Err(err) = debuggee.stop().await {
+                    error!(error = %err, "Error stopping debuggee");
+                }
+            };
+            actix::spawn(stop.instrument(self.span.clone()));
+        }
+    }
+}
+
+impl Actor for DebugSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.span.in_scope(|| info!("Debug session started"));
+        /* Send the debug adapter's messages to ctx, the websocket closes when the adapter exits */
+        if let Some(messages) = self.messages.take() {
+            ctx.add_stream(messages);
+        }
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        /* Make sure the debuggee doesn't outlive its debug session */
+        self.stop_debuggee();
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for DebugSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Text(text)) => {
+                let request = serde_json::from_str::<Value>(&text).unwrap_or_default();
+                self.span.in_scope(|| {
+                    debug!(
+                        seq = %request["seq"],
+                        command = request["command"].as_str().unwrap_or_default(),
+                        "Client message"
+                    );
+                    trace!(body = %text, "Client message body");
+                });
+                if request["type"] != "request" {
+                    return self.send_to_adapter(text, ctx);
+                }
+                match request["command"].as_str() {
+                    Some("launch") => self.launch(request, ctx),
+                    Some("disconnect") if request["arguments"]["terminateDebuggee"] == false => {
+                        self.send_to_adapter(text, ctx)
+                    }
+                    Some("disconnect") | Some("terminate") => {
+                        self.stop_debuggee();
+                        self.send_to_adapter(text, ctx);
+                    }
+                    _ => self.send_to_adapter(text, ctx),
+                }
+            }
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Messages from the debug adapter
+impl StreamHandler<String> for DebugSession {
+    fn handle(&mut self, message: String, ctx: &mut Self::Context) {
+        let mut response = serde_json::from_str::<Value>(&message).unwrap_or_default();
+        self.span
+            .in_scope(|| trace!(body = %message, "Debug adapter message"));
+        let launched = response["type"] == "response"
+            && response["request_seq"]
+                .as_u64()
+                .map(|seq| self.launches.remove(&seq))
+                .unwrap_or(false);
+        if launched {
+            // the client asked to launch, so it expects a launch response
+            response["command"] = json!("launch");
+            ctx.text(response.to_string());
+        } else {
+            ctx.text(message);
+        }
+    }
+}
+
+/// Output and lifecycle of the debuggee, sent to the client as DAP events
+impl StreamHandler<ProgramEvent> for DebugSession {
+    fn handle(&mut self, event: ProgramEvent, ctx: &mut Self::Context) {
+        match event {
+            ProgramEvent::Stdout(line) if line.starts_with(JDWP_LISTENING) => {
+                if let Some(attach) = self.pending_attach.take() {
+                    if let Some(seq) = attach["seq"].as_u64() {
+                        self.launches.insert(seq);
+                    }
+                    self.send_to_adapter(attach.to_string(), ctx);
+                }
+            }
+            ProgramEvent::Build(line) => ctx.text(output_event("console", line)),
+            ProgramEvent::Stdout(line) => ctx.text(output_event("stdout", line)),
+            ProgramEvent::Stderr(line) => ctx.text(output_event("stderr", line)),
+            ProgramEvent::Terminal(_) | ProgramEvent::Input(_) => (),
+            ProgramEvent::Failed(err) => match self.pending_attach.take() {
+                Some(attach) => ctx.text(launch_failed(&attach["seq"], &err)),
+                None => ctx.text(output_event("console", err.to_string())),
+            },
+            ProgramEvent::Exited(status) => {
+                if let Some(attach) = self.pending_attach.take() {
+                    ctx.text(launch_failed(&attach["seq"], &UserProgramError::FailedRun));
+                }
+                ctx.text(dap_event(
+                    "exited",
+                    json!({ "exitCode": status.exit_code.unwrap_or_default() }),
+                ));
+                ctx.text(dap_event("terminated", json!({})));
+            }
+        }
+    }
+
+    fn finished(&mut self, _ctx: &mut Self::Context) {
+        /* The session outlives its debuggee, so the client can launch it again */
+    }
+}
+
+/// Builds and starts a `launch` request's program suspended, returning it with its debug agent's port
+async fn start_debuggee(
+    state: &AppState,
+    arguments: &Value,
+) -> Result<(ProgramHandle, UnboundedReceiver<ProgramEvent>, u16), UserProgramError> {
+    let path: PathBuf = arguments["program"]
+        .as_str()
+        .ok_or(UserProgramError::NoProgram)?
+        .into();
+    let file_path = Path::new(&state.workspace_dir).join(&path);
+    if !file_path.exists() {
+        return Err(UserProgramError::NoProgram);
+    }
+
+    // the port is free once the listener is dropped, for the debug agent to take
+    let port = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map_err(|_| UserProgramError::FailedRun)?
+        .port();
+    let options = RunOptions {
+        main: arguments["mainClass"].as_str().map(String::from),
+        debug_port: Some(port),
+        ..RunOptions::default()
+    };
+
+    let plan = match state.lang {
+        config::Lang::Java => plan_java_prog(state, &file_path, &path, &options).await?,
+        config::Lang::C | config::Lang::Custom => {
+            return Err(UserProgramError::UnsupportedLanguage)
+        }
+    };
+    // the debuggee spends time suspended at breakpoints, so only its output is limited
+    let limits = RunLimits {
+        timeout: None,
+        ..state.run_limits
+    };
+    let (debuggee, events) = state.runs.start(None, plan, limits)?;
+    Ok((debuggee, events, port))
+}
+
+/// Turns a `launch` request into an `attach` request for the debuggee's debug agent
+fn attach_request(mut launch: Value, port: u16) -> Value {
+    if !launch["arguments"].is_object() {
+        launch["arguments"] = json!({});
+    }
+    launch["command"] = json!("attach");
+    launch["arguments"]["hostName"] = json!("127.0.0.1");
+    launch["arguments"]["port"] = json!(port);
+    launch
+}
+
+fn launch_failed(request_seq: &Value, err: &UserProgramError) -> String {
+    json!({
+        "seq": 0,
+        "type": "response",
+        "request_seq": request_seq,
+        "command": "launch",
+        "success": false,
+        "message": err.to_string(),
+    })
+    .to_string()
+}
+
+fn output_event(category: &str, line: String) -> String {
+    dap_event(
+        "output",
+        json!({ "category": category, "output": line + "\n" }),
+    )
+}
+
+fn dap_event(event: &str, body: Value) -> String {
+    json!({ "seq": 0, "type": "event", "event": event, "body": body }).to_string()
+}
+
+#[derive(Debug, Display, Error)]
+pub enum DebugError {
+    #[display(fmt = "No debug adapter is configured")]
+    NoDebugAdapter,
+    #[display(fmt = "Debug adapter failed to start")]
+    FailedAdapterStart,
+}
+
+impl error::ResponseError for DebugError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponseBuilder::new(self.status_code())
+            .set_header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(self.to_string())
+    }
+
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
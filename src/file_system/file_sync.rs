@@ -1,8 +1,13 @@
 use super::{
     file_sync_command::{map_io_err, FileSyncCommand, FileSyncError, FileSyncType},
     files::{build_file_tree, FileNode},
+    text_edits::apply_text_edits,
+};
+use crate::{
+    config::get_ls_args,
+    language_server::initialize::{root_uri, workspace_uri},
+    AppState,
 };
-use crate::{config::get_ls_args, AppState};
 use actix_files::NamedFile;
 use actix_web::{
     http::ContentEncoding,
@@ -13,8 +18,10 @@ use std::path::{Path, PathBuf};
 use tokio::{fs::OpenOptions, io::AsyncWriteExt};
 
 pub async fn get_root_uri(state: web::Data<AppState>) -> impl Responder {
-    let uri = format!("file:///{}", &state.workspace_dir);
-    HttpResponse::Ok().body(uri)
+    match workspace_uri(&state.workspace_dir) {
+        Some(uri) => HttpResponse::Ok().body(root_uri(&uri)),
+        None => HttpResponse::NotFound().body("Workspace directory does not exist"),
+    }
 }
 
 pub async fn get_file(req: HttpRequest) -> Result<NamedFile> {
@@ -40,7 +47,11 @@ pub async fn handle_file_sync(
         FileSyncType::New => {
             if path.is_dir() {
                 let path = path.join(&command.name);
-                let _file = tokio::fs::File::create(&path).await.map_err(map_io_err)?;
+                let mut file = tokio::fs::File::create(&path).await.map_err(map_io_err)?;
+                if let Some(text) = command.text {
+                    file.write_all(text.as_bytes()).await.map_err(map_io_err)?;
+                    file.flush().await.map_err(map_io_err)?;
+                }
             } else {
                 return Err(FileSyncError::BadClientData {
                     cause: "Cannot create new file in non-directory.".to_string(),
@@ -48,7 +59,7 @@ pub async fn handle_file_sync(
             }
         }
         FileSyncType::Update => {
-            if command.text.is_some() {
+            if let Some(text) = command.text {
                 let mut options = OpenOptions::new();
                 let mut file = options
                     .write(true)
@@ -56,11 +67,16 @@ pub async fn handle_file_sync(
                     .open(path)
                     .await
                     .map_err(map_io_err)?;
-                file.write_all(command.text.unwrap().as_bytes())
-                    .await
-                    .map_err(map_io_err)?;
+                file.write_all(text.as_bytes()).await.map_err(map_io_err)?;
+                file.flush().await.map_err(map_io_err)?;
             }
         }
+        FileSyncType::Edit(edits) => {
+            let text = tokio::fs::read_to_string(&path).await.map_err(map_io_err)?;
+            tokio::fs::write(&path, apply_text_edits(&text, &edits))
+                .await
+                .map_err(map_io_err)?;
+        }
         FileSyncType::Delete => {
             if path.exists() {
                 tokio::fs::remove_file(path).await.map_err(map_io_err)?
@@ -1,10 +1,13 @@
 use actix_web::{dev::HttpResponseBuilder, error, http::header, http::StatusCode, HttpResponse};
 use derive_more::{Display, Error};
+use lsp_types::TextEdit;
 
 pub enum FileSyncType {
     New,
     Update,
     Delete,
+    /// Applies LSP text edits to the file's contents on disk
+    Edit(Vec<TextEdit>),
 }
 pub struct FileSyncCommand {
     pub reason: FileSyncType,
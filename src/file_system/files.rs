@@ -49,7 +49,7 @@ pub fn build_file_tree(path: &str, depth: usize) -> FileNode {
 
     for entry in WalkDir::new(path)
         .into_iter()
-        .filter_entry(|e| !is_ignored(&e))
+        .filter_entry(|e| !is_ignored(e))
         .filter_map(|e| e.ok())
         .skip(1)
     {
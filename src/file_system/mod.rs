@@ -1,3 +1,5 @@
 pub mod file_sync;
 pub mod file_sync_command;
 pub mod files;
+pub mod sync_queue;
+pub mod text_edits;
@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use tracing::{debug, warn, Instrument, Span};
+
+use super::{
+    file_sync::handle_file_sync,
+    file_sync_command::{FileSyncCommand, FileSyncError, FileSyncType},
+};
+use crate::metrics::Metrics;
+
+/// A file sync command waiting its turn, with the path `handle_file_sync` takes for it
+struct QueuedSync {
+    path: PathBuf,
+    command: FileSyncCommand,
+    span: Span,
+    /// Told the outcome once the command has been applied
+    done: oneshot::Sender<Result<(), FileSyncError>>,
+}
+
+type Documents = Arc<Mutex<HashMap<PathBuf, UnboundedSender<QueuedSync>>>>;
+
+/// Applies file sync commands one at a time per document, in the order they were queued
+pub struct FileSyncQueue {
+    /// Queues of the documents with commands waiting, keyed by file path
+    documents: Documents,
+    metrics: Arc<Metrics>,
+}
+
+impl FileSyncQueue {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        FileSyncQueue {
+            documents: Arc::new(Mutex::new(HashMap::new())),
+            metrics,
+        }
+    }
+
+    /// Queues a command behind the earlier commands for its document straight away,
+    /// returning a future of its outcome once it has been applied
+    pub fn apply(
+        &self,
+        path: PathBuf,
+        command: FileSyncCommand,
+    ) -> impl Future<Output = Result<(), FileSyncError>> {
+        let (done, outcome) = oneshot::channel();
+        self.enqueue(path, command, done);
+        async {
+            outcome.await.unwrap_or_else(|_| {
+                Err(FileSyncError::InternalError {
+                    cause: "file sync queue stopped".to_string(),
+                })
+            })
+        }
+    }
+
+    fn enqueue(
+        &self,
+        path: PathBuf,
+        command: FileSyncCommand,
+        done: oneshot::Sender<Result<(), FileSyncError>>,
+    ) {
+        let document = match command.reason {
+            FileSyncType::New => path.join(&command.name),
+            FileSyncType::Update | FileSyncType::Edit(_) | FileSyncType::Delete => path.clone(),
+        };
+        let mut sync = QueuedSync {
+            path,
+            command,
+            span: Span::current(),
+            done,
+        };
+        let mut documents = self
+            .documents
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(queue) = documents.get(&document) {
+            match queue.send(sync) {
+                Ok(()) => return,
+                // the document's task has gone, so it gets a new one
+                Err(mpsc::error::SendError(unsent)) => sync = unsent,
+            }
+        }
+        let queue = self.start(document.clone());
+        if queue.send(sync).is_ok() {
+            documents.insert(document, queue);
+        }
+    }
+
+    /// Starts the task that applies a document's commands, which ends once it has run out of them
+    fn start(&self, document: PathBuf) -> UnboundedSender<QueuedSync> {
+        let (queue, mut commands) = mpsc::unbounded_channel::<QueuedSync>();
+        let metrics = self.metrics.clone();
+        let documents = self.documents.clone();
+        actix::spawn(async move {
+            while let Some(QueuedSync {
+                path,
+                command,
+                span,
+                done,
+            }) = next_command(&mut commands, &documents, &document)
+            {
+                let name = command.name.clone();
+                let result = handle_file_sync(path.clone(), command)
+                    .instrument(span.clone())
+                    .await;
+                span.in_scope(|| match &result {
+                    Ok(()) => debug!(?document, "Synced file"),
+                    Err(err) => {
+                        metrics.file_sync_error(err);
+                        warn!(?path, name = %name, error = %err, "Could not sync file");
+                    }
+                });
+                // nothing may be waiting for the outcome any more
+                let _ = done.send(result);
+            }
+        });
+        queue
+    }
+}
+
+/// Takes a document's next command, or removes the document's queue if it has none left.
+/// Commands are queued with the documents locked, so none can be queued as the queue is removed.
+fn next_command(
+    commands: &mut UnboundedReceiver<QueuedSync>,
+    documents: &Documents,
+    document: &Path,
+) -> Option<QueuedSync> {
+    if let Ok(sync) = commands.try_recv() {
+        return Some(sync);
+    }
+    let mut documents = documents.lock().unwrap_or_else(PoisonError::into_inner);
+    match commands.try_recv() {
+        Ok(sync) => Some(sync),
+        Err(_) => {
+            documents.remove(document);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileSyncQueue;
+    use crate::{
+        file_system::file_sync_command::{FileSyncCommand, FileSyncType},
+        metrics::Metrics,
+    };
+    use std::sync::Arc;
+
+    #[actix_rt::test]
+    async fn changes_to_a_document_are_applied_in_order() {
+        let dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let file = dir.path().join("Hello.java");
+        std::fs::write(&file, "").unwrap();
+        let queue = FileSyncQueue::new(Arc::new(Metrics::new()));
+
+        // long changes first, which take longer to write than the short ones after them
+        let outcomes: Vec<_> = (0..50)
+            .rev()
+            .map(|version: usize| {
+                queue.apply(
+                    file.clone(),
+                    FileSyncCommand {
+                        reason: FileSyncType::Update,
+                        name: "Hello.java".to_string(),
+                        text: Some(version.to_string().repeat(version * 1000 + 1)),
+                    },
+                )
+            })
+            .collect();
+
+        for outcome in outcomes {
+            outcome.await.expect("change wasn't applied");
+        }
+        // an earlier change applied late would have overwritten the last one
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "0");
+        assert!(queue.documents.lock().unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn commands_for_a_file_share_its_queue() {
+        let dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let queue = FileSyncQueue::new(Arc::new(Metrics::new()));
+        let command = |reason| FileSyncCommand {
+            reason,
+            name: "Hello.java".to_string(),
+            text: Some("class Hello {}".to_string()),
+        };
+
+        let created = queue.apply(dir.path().to_path_buf(), command(FileSyncType::New));
+        let updated = queue.apply(dir.path().join("Hello.java"), command(FileSyncType::Update));
+        let deleted = queue.apply(dir.path().join("Hello.java"), command(FileSyncType::Delete));
+        assert_eq!(queue.documents.lock().unwrap().len(), 1);
+        created.await.expect("file wasn't created");
+        updated.await.expect("file wasn't updated");
+        deleted.await.expect("file wasn't deleted");
+
+        assert!(!dir.path().join("Hello.java").exists());
+        assert!(queue.documents.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+use lsp_types::{Position, TextEdit};
+
+/// Applies LSP text edits, whose ranges refer to the text before any of them are applied
+pub fn apply_text_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut edits: Vec<_> = edits
+        .iter()
+        .map(|edit| {
+            let start = offset(text, edit.range.start);
+            let end = offset(text, edit.range.end).max(start);
+            (start..end, edit.new_text.as_str())
+        })
+        .collect();
+    // stable, so edits at the same position keep their order
+    edits.sort_by_key(|(range, _)| range.start);
+
+    let mut edited = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (range, new_text) in edits {
+        if range.start > copied {
+            edited.push_str(&text[copied..range.start]);
+        }
+        edited.push_str(new_text);
+        copied = copied.max(range.end);
+    }
+    edited.push_str(&text[copied..]);
+    edited
+}
+
+/// Byte offset of a position counted in UTF-16 code units, clamped to the end of its line and of the text
+fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(end) => line_start += end + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let mut units = 0;
+    for (index, character) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + index;
+        }
+        units += character.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::apply_text_edits;
+    use lsp_types::{Position, Range, TextEdit};
+
+    fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range {
+                start: Position::new(start.0, start.1),
+                end: Position::new(end.0, end.1),
+            },
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn edits_apply_to_the_original_text() {
+        let text = "import java.util.List;\nclass Hello {\n    String s = \"😀\";\n}\n";
+
+        let edited = apply_text_edits(
+            text,
+            &[
+                // after the emoji, which is two UTF-16 code units
+                edit((2, 18), (2, 18), "!"),
+                edit((0, 0), (1, 0), ""),
+                edit((1, 6), (1, 6), "Hello"),
+                edit((4, 0), (4, 0), "// end\n"),
+                edit((1, 6), (1, 11), "World"),
+            ],
+        );
+
+        assert_eq!(
+            edited,
+            "class HelloWorld {\n    String s = \"😀!\";\n}\n// end\n"
+        );
+    }
+}
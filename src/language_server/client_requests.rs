@@ -0,0 +1,234 @@
+use lsp_types::{
+    ApplyWorkspaceEditParams, DocumentChangeOperation, DocumentChanges, OneOf, ResourceOp,
+    TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+};
+use serde_json::{json, Value};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{debug, warn};
+
+use super::interceptor::{InterceptFuture, Intercepted, Interceptor};
+use crate::file_system::{
+    file_sync_command::{FileSyncCommand, FileSyncType},
+    sync_queue::FileSyncQueue,
+};
+
+/// Answers server requests that thin clients often don't implement
+pub struct ClientRequests {
+    /// Settings tree that `workspace/configuration` sections are looked up in
+    settings: Value,
+    file_sync: Arc<FileSyncQueue>,
+}
+
+impl ClientRequests {
+    pub fn new(settings: Value, file_sync: Arc<FileSyncQueue>) -> Self {
+        ClientRequests {
+            settings,
+            file_sync,
+        }
+    }
+
+    /// Result of a `workspace/configuration` request, with `null` for sections that aren't set
+    fn configuration(&self, params: &Value) -> Value {
+        let items = params["items"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        items
+            .iter()
+            .map(|item| match item["section"].as_str() {
+                Some(section) => section
+                    .split('.')
+                    .try_fold(&self.settings, |settings, key| settings.get(key))
+                    .cloned()
+                    .unwrap_or(Value::Null),
+                None => self.settings.clone(),
+            })
+            .collect()
+    }
+
+    /// Result of a `workspace/applyEdit` request, once the edit has been written to disk or failed to be
+    async fn apply_edit(&self, params: &Value) -> Value {
+        let commands = serde_json::from_value::<ApplyWorkspaceEditParams>(params.clone())
+            .map_err(|err| err.to_string())
+            .and_then(|params| file_sync_commands(params.edit));
+        let commands = match commands {
+            Ok(commands) => commands,
+            Err(reason) => return json!({"applied": false, "failureReason": reason}),
+        };
+        for (path, command) in commands {
+            if let Err(err) = self.file_sync.apply(path, command).await {
+                warn!(error = %err, "Could not apply workspace edit");
+                return json!({"applied": false, "failureReason": err.to_string()});
+            }
+        }
+        debug!("Applied workspace edit");
+        json!({"applied": true})
+    }
+}
+
+impl Interceptor for ClientRequests {
+    fn server_message<'a>(&'a self, message: &'a Value) -> InterceptFuture<'a> {
+        Box::pin(async move {
+            let id = match message.get("id") {
+                Some(id) => id,
+                None => return Intercepted::Pass,
+            };
+            let params = &message["params"];
+            let result = match message["method"].as_str() {
+                Some("workspace/configuration") => self.configuration(params),
+                Some("window/workDoneProgress/create")
+                | Some("client/registerCapability")
+                | Some("client/unregisterCapability") => Value::Null,
+                Some("workspace/applyEdit") => self.apply_edit(params).await,
+                _ => return Intercepted::Pass,
+            };
+            Intercepted::Answer(json!({"jsonrpc": "2.0", "id": id, "result": result}))
+        })
+    }
+}
+
+/// Reads the settings tree that `workspace/configuration` requests are answered from
+pub fn read_settings(path: &Path) -> std::io::Result<Value> {
+    let settings = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&settings)?)
+}
+
+/// File sync commands that make a workspace edit's changes, in the order they are to be made
+fn file_sync_commands(edit: WorkspaceEdit) -> Result<Vec<(PathBuf, FileSyncCommand)>, String> {
+    let mut commands = Vec::new();
+    for (uri, edits) in edit.changes.unwrap_or_default() {
+        commands.push(edit_command(&uri, edits)?);
+    }
+    let operations = match edit.document_changes {
+        Some(DocumentChanges::Edits(edits)) => edits
+            .into_iter()
+            .map(DocumentChangeOperation::Edit)
+            .collect(),
+        Some(DocumentChanges::Operations(operations)) => operations,
+        None => Vec::new(),
+    };
+    for operation in operations {
+        let command = match operation {
+            DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document,
+                edits,
+            }) => {
+                let edits = edits
+                    .into_iter()
+                    .map(|edit| match edit {
+                        OneOf::Left(edit) => edit,
+                        OneOf::Right(annotated) => annotated.text_edit,
+                    })
+                    .collect();
+                edit_command(&text_document.uri, edits)?
+            }
+            DocumentChangeOperation::Op(ResourceOp::Create(create)) => {
+                let (mut path, name) = file(&create.uri)?;
+                path.pop();
+                (path, command(FileSyncType::New, name))
+            }
+            DocumentChangeOperation::Op(ResourceOp::Delete(delete)) => {
+                let (path, name) = file(&delete.uri)?;
+                (path, command(FileSyncType::Delete, name))
+            }
+            DocumentChangeOperation::Op(ResourceOp::Rename(rename)) => {
+                return Err(format!("Can't rename {}", rename.old_uri))
+            }
+        };
+        commands.push(command);
+    }
+    Ok(commands)
+}
+
+fn edit_command(uri: &Url, edits: Vec<TextEdit>) -> Result<(PathBuf, FileSyncCommand), String> {
+    let (path, name) = file(uri)?;
+    Ok((path, command(FileSyncType::Edit(edits), name)))
+}
+
+fn command(reason: FileSyncType, name: String) -> FileSyncCommand {
+    FileSyncCommand {
+        reason,
+        name,
+        text: None,
+    }
+}
+
+/// Path and name of the file a URI is for
+fn file(uri: &Url) -> Result<(PathBuf, String), String> {
+    let path = uri
+        .to_file_path()
+        .map_err(|_| format!("{} isn't a file on the proxy", uri))?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| format!("{} isn't a file", uri))?;
+    Ok((path, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientRequests;
+    use crate::{
+        file_system::sync_queue::FileSyncQueue,
+        language_server::{
+            interceptor::{Intercepted, InterceptorChain},
+            recorder::Direction,
+        },
+        metrics::Metrics,
+    };
+    use lsp_types::Url;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[actix_rt::test]
+    async fn configuration_is_answered_from_settings() {
+        let settings = json!({"java": {"format": {"enabled": false}}});
+        let chain = InterceptorChain::new().with(ClientRequests::new(
+            settings,
+            Arc::new(FileSyncQueue::new(Arc::new(Metrics::new()))),
+        ));
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "workspace/configuration",
+            "params": {"items": [{"section": "java.format"}, {"section": "java.home"}, {}]},
+        });
+
+        assert_eq!(
+            chain.intercept(Direction::ServerToClient, &request).await,
+            Intercepted::Answer(json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "result": [{"enabled": false}, null, {"java": {"format": {"enabled": false}}}],
+            }))
+        );
+        let progress =
+            json!({"id": 4, "method": "window/workDoneProgress/create", "params": {"token": "t"}});
+        assert_eq!(
+            chain.intercept(Direction::ServerToClient, &progress).await,
+            Intercepted::Answer(json!({"jsonrpc": "2.0", "id": 4, "result": null}))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn workspace_edits_are_applied_to_disk() {
+        let dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let file = dir.path().join("Hello.java");
+        std::fs::write(&file, "class Hello {}\n").unwrap();
+        let uri = Url::from_file_path(&file).unwrap();
+        let requests = ClientRequests::new(
+            json!({}),
+            Arc::new(FileSyncQueue::new(Arc::new(Metrics::new()))),
+        );
+
+        let result = requests
+            .apply_edit(&json!({"edit": {"documentChanges": [{
+                "textDocument": {"uri": uri, "version": 2},
+                "edits": [{"range": {"start": {"line": 0, "character": 6}, "end": {"line": 0, "character": 11}}, "newText": "World"}],
+            }]}}))
+            .await;
+
+        assert_eq!(result, json!({"applied": true}));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "class World {}\n");
+    }
+}
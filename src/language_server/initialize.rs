@@ -0,0 +1,121 @@
+use lsp_types::Url;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// URI of the workspace directory, as the language server should see it
+pub fn workspace_uri(workspace_dir: &str) -> Option<Url> {
+    let path = Path::new(workspace_dir).canonicalize().ok()?;
+    Url::from_directory_path(path).ok()
+}
+
+/// [`workspace_uri`] without its trailing slash, as clients send the workspace root
+pub fn root_uri(workspace_uri: &Url) -> String {
+    workspace_uri.as_str().trim_end_matches('/').to_string()
+}
+
+/// Reads the `initializationOptions` given to the proxy for its language server
+pub fn read_initialization_options(path: &Path) -> std::io::Result<Value> {
+    let options = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&options)?)
+}
+
+/// Points an `initialize` request at the proxy's workspace and merges in the proxy's `initializationOptions`
+pub fn rewrite_initialize(message: &mut Value, workspace_dir: &str, options: Option<&Value>) {
+    let params = match message.get_mut("params").and_then(Value::as_object_mut) {
+        Some(params) => params,
+        None => return,
+    };
+    if let Some(uri) = workspace_uri(workspace_dir) {
+        let root_uri = root_uri(&uri);
+        let path = uri.to_file_path().unwrap_or_default();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        params.insert("rootPath".to_string(), json!(path));
+        params.insert(
+            "workspaceFolders".to_string(),
+            json!([{"uri": root_uri, "name": name}]),
+        );
+        params.insert("rootUri".to_string(), json!(root_uri));
+    }
+    if let Some(options) = options {
+        let merged = params.entry("initializationOptions").or_insert(Value::Null);
+        merge(merged, options);
+    }
+}
+
+/// Response to an `initialize` request, from the result the language server gave an earlier session
+pub fn initialize_response(id: &Value, result: &Value) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": result,
+    })
+    .to_string()
+}
+
+/// Merges `overrides` into `base`, replacing everything but objects, which are merged key by key
+pub(super) fn merge(base: &mut Value, overrides: &Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, rewrite_initialize, workspace_uri};
+    use serde_json::json;
+
+    #[test]
+    fn initialize_points_at_the_workspace() {
+        let dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let workspace_dir = dir.path().join("my workspace");
+        std::fs::create_dir(&workspace_dir).unwrap();
+        let workspace_dir = workspace_dir.to_string_lossy().to_string();
+        let mut initialize = json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": "initialize",
+            "params": {
+                "rootUri": "file:////tmp/elsewhere",
+                "capabilities": {},
+                "initializationOptions": {"settings": {"java": {"format": {"enabled": true}}}},
+            },
+        });
+        let options = json!({"settings": {"java": {"import": {"gradle": {"enabled": false}}}}});
+
+        rewrite_initialize(&mut initialize, &workspace_dir, Some(&options));
+
+        let uri = workspace_uri(&workspace_dir).unwrap();
+        let root_uri = initialize["params"]["rootUri"].as_str().unwrap();
+        assert!(root_uri.starts_with("file:///") && !root_uri.starts_with("file:////"));
+        assert!(root_uri.ends_with("/my%20workspace"));
+        assert_eq!(format!("{}/", root_uri), uri.as_str());
+        assert_eq!(
+            initialize["params"]["workspaceFolders"],
+            json!([{"uri": root_uri, "name": "my workspace"}])
+        );
+        assert_eq!(
+            initialize["params"]["initializationOptions"],
+            json!({"settings": {"java": {
+                "format": {"enabled": true},
+                "import": {"gradle": {"enabled": false}},
+            }}})
+        );
+    }
+
+    #[test]
+    fn proxy_options_take_precedence() {
+        let mut options = json!({"a": 1, "b": {"c": [1, 2]}});
+
+        merge(&mut options, &json!({"b": {"c": [3], "d": null}}));
+
+        assert_eq!(options, json!({"a": 1, "b": {"c": [3], "d": null}}));
+    }
+}
@@ -1,76 +1,109 @@
-use std::path::Path;
+use std::{path::PathBuf, sync::Arc};
 
 use lsp_types::{CreateFilesParams, DeleteFilesParams, DidChangeTextDocumentParams, Url};
 use serde_json::Value;
+use tracing::warn;
 
+use super::interceptor::{InterceptFuture, Intercepted, Interceptor};
 use crate::file_system::{
-    file_sync::handle_file_sync,
     file_sync_command::{FileSyncCommand, FileSyncType},
+    sync_queue::FileSyncQueue,
 };
 
 type SerializerError = serde_json::error::Error;
 
-pub async fn intercept_notification(msg: Value) -> Result<(), SerializerError> {
+/// Writes the file changes the client notifies the server of to disk, before they are passed on
+pub struct FileSync {
+    queue: Arc<FileSyncQueue>,
+}
+
+impl FileSync {
+    pub fn new(queue: Arc<FileSyncQueue>) -> Self {
+        FileSync { queue }
+    }
+}
+
+impl Interceptor for FileSync {
+    fn client_message<'a>(&'a self, message: &'a Value) -> InterceptFuture<'a> {
+        let outcomes: Vec<_> = match intercept_notification(message) {
+            Ok(commands) => commands
+                .into_iter()
+                .map(|(path, command)| self.queue.apply(path, command))
+                .collect(),
+            Err(err) => {
+                warn!(error = %err, "Error intercepting client message");
+                Vec::new()
+            }
+        };
+        Box::pin(async {
+            for outcome in outcomes {
+                // the queue logs changes it couldn't write, which the server is still told of
+                let _ = outcome.await;
+            }
+            Intercepted::Pass
+        })
+    }
+}
+
+type SyncCommands = Vec<(PathBuf, FileSyncCommand)>;
+
+fn intercept_notification(msg: &Value) -> Result<SyncCommands, SerializerError> {
     if let Value::String(method) = &msg["method"] {
         if method.starts_with("textDocument/didChange") || method.starts_with("workspace/did") {
-            intercept_text_sync(&msg, method).await?;
+            return Ok(intercept_text_sync(msg, method)?.unwrap_or_default());
         }
     }
-    Ok(())
+    Ok(Vec::new())
 }
 
-async fn intercept_text_sync(msg: &Value, method: &str) -> Result<bool, SerializerError> {
+/// The file sync commands for a notification, or `None` if its method isn't one to sync
+fn intercept_text_sync(msg: &Value, method: &str) -> Result<Option<SyncCommands>, SerializerError> {
+    let mut commands = Vec::new();
     if let Value::Object(_) = &msg["params"] {
         match method {
             "textDocument/didChange" => {
                 let did_update: DidChangeTextDocumentParams =
                     serde_json::from_value(msg["params"].clone())?;
-                intercept_did_update(did_update).await;
+                intercept_did_update(did_update, &mut commands);
             }
             "workspace/didCreateFiles" => {
                 let did_create: CreateFilesParams = serde_json::from_value(msg["params"].clone())?;
-                intercept_did_create(did_create).await;
+                intercept_did_create(did_create, &mut commands);
             }
             "workspace/didDeleteFiles" => {
                 let did_delete: DeleteFilesParams = serde_json::from_value(msg["params"].clone())?;
-                intercept_did_delete(did_delete).await;
+                intercept_did_delete(did_delete, &mut commands);
             }
-            _unrecognized => return Ok(false),
+            _unrecognized => return Ok(None),
         }
     }
-    Ok(true)
+    Ok(Some(commands))
 }
 
-async fn intercept_did_create(params: CreateFilesParams) {
-    let file_creates = params.files;
-    for creation in file_creates.iter() {
-        let uri = Url::parse(&creation.uri);
-        if let Ok(url) = uri {
-            let mut path = Path::new(url.as_str()).to_path_buf();
-            let file_name = url.path_segments().map(|s| s.last()).unwrap_or_default();
-            if let Some(name) = file_name {
+fn intercept_did_create(params: CreateFilesParams, commands: &mut SyncCommands) {
+    for creation in params.files.iter() {
+        match file_path(&creation.uri) {
+            Some((mut path, name)) => {
                 let file_sync_msg = FileSyncCommand {
                     reason: FileSyncType::New,
-                    name: name.to_string(),
+                    name,
                     text: None,
                 };
                 path.pop();
-                if let Err(err) = handle_file_sync(path.clone(), file_sync_msg).await {
-                    println!("could not update! {}", err);
-                } else {
-                    println!("Created file: {:?}{:?}", path, file_name);
-                }
-            } else {
-                println!("could not create path:{:?},{:?}", path, file_name);
+                commands.push((path, file_sync_msg));
             }
+            None => warn!(uri = %creation.uri, "Could not create file without a path"),
         }
     }
 }
 
-async fn intercept_did_update(params: DidChangeTextDocumentParams) {
+fn intercept_did_update(params: DidChangeTextDocumentParams, commands: &mut SyncCommands) {
     let uri = params.text_document.uri;
     let path = uri.to_file_path();
-    let file_name = uri.path_segments().map(|s| s.last()).unwrap_or_default();
+    let file_name = uri
+        .path_segments()
+        .map(|mut s| s.next_back())
+        .unwrap_or_default();
     if let (Ok(path), Some(name)) = (path, file_name) {
         for change in params.content_changes.iter() {
             let file_sync_msg = FileSyncCommand {
@@ -78,53 +111,72 @@ async fn intercept_did_update(params: DidChangeTextDocumentParams) {
                 name: name.to_string(),
                 text: Some(change.text.clone()),
             };
-            if let Err(err) = handle_file_sync(path.clone(), file_sync_msg).await {
-                println!("{}", err)
-            }
+            commands.push((path.clone(), file_sync_msg));
         }
     }
 }
 
-async fn intercept_did_delete(params: DeleteFilesParams) {
-    let file_deletes = params.files;
-    for deletion in file_deletes.iter() {
-        let uri = Url::parse(&deletion.uri);
-        if let Ok(url) = uri {
-            let mut path = Path::new(url.as_str()).to_path_buf();
-            let file_name = url.path_segments().map(|s| s.last()).unwrap_or_default();
-            if let Some(name) = file_name {
-                let file_sync_msg = FileSyncCommand {
-                    reason: FileSyncType::Delete,
-                    name: name.to_string(),
-                    text: None,
-                };
-                path.pop();
-                if let Err(err) = handle_file_sync(path.clone(), file_sync_msg).await {
-                    println!("Could not delete! {}", err);
-                } else {
-                    println!("Deleted file: {:?}{:?}", path, file_name);
-                }
-            }
+fn intercept_did_delete(params: DeleteFilesParams, commands: &mut SyncCommands) {
+    for deletion in params.files.iter() {
+        if let Some((path, name)) = file_path(&deletion.uri) {
+            let file_sync_msg = FileSyncCommand {
+                reason: FileSyncType::Delete,
+                name,
+                text: None,
+            };
+            commands.push((path, file_sync_msg));
         }
     }
 }
 
+/// Path and name of the file a `file:` URI points to
+fn file_path(uri: &str) -> Option<(PathBuf, String)> {
+    let path = Url::parse(uri).ok()?.to_file_path().ok()?;
+    let name = path.file_name()?.to_string_lossy().to_string();
+    Some((path, name))
+}
+
 #[cfg(test)]
 mod tests {
-    use super::intercept_text_sync;
+    use super::{intercept_text_sync, FileSync};
+    use crate::{
+        file_system::sync_queue::FileSyncQueue,
+        language_server::interceptor::{Intercepted, Interceptor},
+        metrics::Metrics,
+    };
+    use lsp_types::Url;
     use serde_json::json;
+    use std::sync::Arc;
 
     #[actix_rt::test]
     async fn intercept_detects_correct_method() {
-        let methods = vec![
+        let methods = [
             json!({"method" : "textDocument/didChange"}),
             json!({"method" : "workspace/didCreateFiles"}),
         ];
 
         for method in methods.iter() {
-            assert!(intercept_text_sync(&method, "")
-                .await
-                .expect("serializer error"));
+            assert!(intercept_text_sync(method, "")
+                .expect("serializer error")
+                .is_some());
         }
     }
+
+    #[actix_rt::test]
+    async fn created_and_deleted_files_are_synced_at_their_paths() {
+        let dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let file = dir.path().join("Hello.java");
+        let uri = Url::from_file_path(&file).unwrap().to_string();
+        let file_sync = FileSync::new(Arc::new(FileSyncQueue::new(Arc::new(Metrics::new()))));
+
+        let params = json!({"files": [{"uri": uri}]});
+        let created = json!({"method": "workspace/didCreateFiles", "params": params});
+        // the message is only passed on once the file has been written
+        assert_eq!(file_sync.client_message(&created).await, Intercepted::Pass);
+        assert!(file.exists());
+
+        let deleted = json!({"method": "workspace/didDeleteFiles", "params": params});
+        assert_eq!(file_sync.client_message(&deleted).await, Intercepted::Pass);
+        assert!(!file.exists());
+    }
 }
@@ -0,0 +1,138 @@
+use serde_json::Value;
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use super::recorder::Direction;
+
+/// What an interceptor makes of a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum Intercepted {
+    /// Pass the message on as it is
+    Pass,
+    /// Pass this on in the message's place
+    Modify(Value),
+    /// Drop the message
+    Swallow,
+    /// Drop the message, and send this back to the side it came from
+    Answer(Value),
+}
+
+pub type InterceptFuture<'a> = Pin<Box<dyn Future<Output = Intercepted> + 'a>>;
+
+/// Hook to observe, modify, swallow or answer a session's messages
+pub trait Interceptor: Send + Sync {
+    /// Called with each message from the client before it is written to the language server
+    fn client_message<'a>(&'a self, _message: &'a Value) -> InterceptFuture<'a> {
+        Box::pin(async { Intercepted::Pass })
+    }
+
+    /// Called with each message from the language server before it is sent to the client
+    fn server_message<'a>(&'a self, _message: &'a Value) -> InterceptFuture<'a> {
+        Box::pin(async { Intercepted::Pass })
+    }
+}
+
+/// Interceptors run in order on every message, until one swallows or answers it
+#[derive(Clone, Default)]
+pub struct InterceptorChain {
+    interceptors: Vec<Arc<dyn Interceptor>>,
+}
+
+impl InterceptorChain {
+    pub fn new() -> Self {
+        InterceptorChain::default()
+    }
+
+    /// Adds an interceptor to the end of the chain
+    pub fn with(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Runs a message through the chain in the given direction
+    pub async fn intercept(&self, direction: Direction, message: &Value) -> Intercepted {
+        let mut modified = None;
+        for interceptor in &self.interceptors {
+            let current = modified.as_ref().unwrap_or(message);
+            let intercepted = match direction {
+                Direction::ClientToServer => interceptor.client_message(current).await,
+                Direction::ServerToClient => interceptor.server_message(current).await,
+            };
+            match intercepted {
+                Intercepted::Pass => (),
+                Intercepted::Modify(message) => modified = Some(message),
+                intercepted => return intercepted,
+            }
+        }
+        modified.map_or(Intercepted::Pass, Intercepted::Modify)
+    }
+}
+
+impl fmt::Debug for InterceptorChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterceptorChain")
+            .field("interceptors", &self.interceptors.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InterceptFuture, Intercepted, Interceptor, InterceptorChain};
+    use crate::language_server::recorder::Direction;
+    use serde_json::{json, Value};
+
+    /// Tags every client message with its place in the chain
+    struct Tag(u64);
+
+    impl Interceptor for Tag {
+        fn client_message<'a>(&'a self, message: &'a Value) -> InterceptFuture<'a> {
+            let mut message = message.clone();
+            let mut tags: Vec<u64> =
+                serde_json::from_value(message["params"]["tags"].take()).unwrap_or_default();
+            tags.push(self.0);
+            message["params"]["tags"] = json!(tags);
+            Box::pin(async { Intercepted::Modify(message) })
+        }
+    }
+
+    /// Answers a custom command itself
+    struct Command;
+
+    impl Interceptor for Command {
+        fn client_message<'a>(&'a self, message: &'a Value) -> InterceptFuture<'a> {
+            Box::pin(async move {
+                match message["method"].as_str() {
+                    Some("custom/ping") => {
+                        Intercepted::Answer(json!({"id": message["id"], "result": "pong"}))
+                    }
+                    _ => Intercepted::Pass,
+                }
+            })
+        }
+    }
+
+    #[actix_rt::test]
+    async fn interceptors_run_in_order() {
+        let chain = InterceptorChain::new()
+            .with(Tag(1))
+            .with(Command)
+            .with(Tag(2));
+
+        let hover = json!({"id": 1, "method": "textDocument/hover", "params": {}});
+        assert_eq!(
+            chain.intercept(Direction::ClientToServer, &hover).await,
+            Intercepted::Modify(
+                json!({"id": 1, "method": "textDocument/hover", "params": {"tags": [1, 2]}})
+            )
+        );
+        let ping = json!({"id": 2, "method": "custom/ping"});
+        assert_eq!(
+            chain.intercept(Direction::ClientToServer, &ping).await,
+            Intercepted::Answer(json!({"id": 2, "result": "pong"}))
+        );
+        assert_eq!(
+            chain.intercept(Direction::ServerToClient, &ping).await,
+            Intercepted::Pass
+        );
+    }
+}
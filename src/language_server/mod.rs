@@ -1,31 +1,83 @@
 use crate::AppState;
-use actix_web::{error::ErrorBadRequest, web, Error, HttpRequest, HttpResponse};
+use actix_web::{error::ErrorBadRequest, web, web::Json, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
-use server::LangServer;
-use std::sync::{atomic::Ordering, Arc};
-use tokio::process::Child;
+use request_tracker::InFlightRequest;
+use server::{forward_output, GetInFlightRequests, LangServer};
+use std::sync::{atomic::Ordering, Arc, PoisonError};
+use tokio::{
+    process::{Child, ChildStdin},
+    sync::Mutex,
+};
 
+pub mod client_requests;
+pub mod initialize;
 pub mod intercept;
+pub mod interceptor;
+pub mod policy;
+pub mod readiness;
+pub mod recorder;
+pub mod replay;
+pub mod request_tracker;
+pub mod rest;
 pub mod server;
 pub mod server_runners;
+pub mod uri_mapping;
 
-/// Starts language server process as a WebSocket connection
+/// Connects a WebSocket session to the language server, one session at a time
 pub async fn to_language_server(
     req: HttpRequest,
     stream: web::Payload,
     process: web::Data<Arc<std::sync::Mutex<Child>>>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let session_already_started = state.ws_session_started.load(Ordering::Relaxed);
-    if !session_already_started {
-        let lang_server = LangServer::new(process.as_ref().to_owned());
-        let session = ws::start(lang_server, &req, stream);
-        println!("Language Server started\n{:?}", session);
-        state.ws_session_started.store(true, Ordering::Relaxed);
-        session
-    } else {
-        Err(ErrorBadRequest(
+    if state.ws_session_started.swap(true, Ordering::Relaxed) {
+        return Err(ErrorBadRequest(
             "Language server WebSocket session has already been started.",
-        ))
+        ));
     }
+    let lang_server = LangServer::new(server_stdin(&process, &state), state.clone());
+    let (client, session) = match ws::start_with_addr(lang_server, &req, stream) {
+        Ok(started) => started,
+        Err(err) => {
+            state.ws_session_started.store(false, Ordering::Relaxed);
+            return Err(err);
+        }
+    };
+    *state
+        .ls_client
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(client);
+    Ok(session)
+}
+
+/// The language server's stdin, taken from its process along with stdout when first needed
+fn server_stdin(
+    process: &Arc<std::sync::Mutex<Child>>,
+    state: &web::Data<AppState>,
+) -> Arc<Mutex<ChildStdin>> {
+    state
+        .ls_stdin
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_or_insert_with(|| {
+            let mut child = process.lock().unwrap_or_else(PoisonError::into_inner);
+            forward_output(child.stdout.take().unwrap(), state.clone());
+            Arc::new(Mutex::new(child.stdin.take().unwrap()))
+        })
+        .clone()
+}
+
+/// Lists the session's requests waiting for a response, the longest waiting first
+pub async fn in_flight_requests(state: web::Data<AppState>) -> Json<Vec<InFlightRequest>> {
+    let client = state
+        .ls_client
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let requests = match client {
+        // a closed session has nothing left in flight
+        Some(client) => client.send(GetInFlightRequests).await.unwrap_or_default(),
+        None => Vec::new(),
+    };
+    Json(requests)
 }
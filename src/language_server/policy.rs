@@ -0,0 +1,286 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+use tracing::warn;
+
+use super::{initialize::merge, recorder::Direction};
+
+/// Error code of the responses to denied requests, LSP's `RequestFailed`
+const DENIED_CODE: i64 = -32803;
+
+/// Server capabilities that advertise support for a client request, by the request's method
+const CAPABILITIES: [(&str, &str); 28] = [
+    ("textDocument/hover", "hoverProvider"),
+    ("textDocument/completion", "completionProvider"),
+    ("textDocument/signatureHelp", "signatureHelpProvider"),
+    ("textDocument/declaration", "declarationProvider"),
+    ("textDocument/definition", "definitionProvider"),
+    ("textDocument/typeDefinition", "typeDefinitionProvider"),
+    ("textDocument/implementation", "implementationProvider"),
+    ("textDocument/references", "referencesProvider"),
+    (
+        "textDocument/documentHighlight",
+        "documentHighlightProvider",
+    ),
+    ("textDocument/documentSymbol", "documentSymbolProvider"),
+    ("textDocument/codeAction", "codeActionProvider"),
+    ("textDocument/codeLens", "codeLensProvider"),
+    ("textDocument/documentLink", "documentLinkProvider"),
+    ("textDocument/documentColor", "colorProvider"),
+    ("textDocument/formatting", "documentFormattingProvider"),
+    (
+        "textDocument/rangeFormatting",
+        "documentRangeFormattingProvider",
+    ),
+    (
+        "textDocument/onTypeFormatting",
+        "documentOnTypeFormattingProvider",
+    ),
+    ("textDocument/rename", "renameProvider"),
+    ("textDocument/foldingRange", "foldingRangeProvider"),
+    ("textDocument/selectionRange", "selectionRangeProvider"),
+    ("textDocument/prepareCallHierarchy", "callHierarchyProvider"),
+    ("textDocument/semanticTokens/full", "semanticTokensProvider"),
+    (
+        "textDocument/linkedEditingRange",
+        "linkedEditingRangeProvider",
+    ),
+    ("textDocument/moniker", "monikerProvider"),
+    ("textDocument/prepareTypeHierarchy", "typeHierarchyProvider"),
+    ("textDocument/inlayHint", "inlayHintProvider"),
+    ("workspace/symbol", "workspaceSymbolProvider"),
+    ("workspace/executeCommand", "executeCommandProvider"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    #[default]
+    Allow,
+    Deny,
+    /// Merge the rule's `params` into the message's
+    Rewrite,
+}
+
+/// A rule of the message policy, for the messages matching its method, direction and command
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyRule {
+    /// Method the rule is for, or every method starting with it if it ends in `*`
+    pub method: String,
+    /// Side the message comes from, either if not set
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    /// Command of a `workspace/executeCommand` request the rule is for, any if not set
+    #[serde(default)]
+    pub command: Option<String>,
+    pub action: Action,
+    /// Merged into the message's params by `rewrite` rules, taking precedence over the message's own
+    #[serde(default)]
+    pub params: Value,
+}
+
+impl PolicyRule {
+    fn matches(&self, direction: Direction, method: &str, command: Option<&str>) -> bool {
+        let method_matches = match self.method.strip_suffix('*') {
+            Some(prefix) => method.starts_with(prefix),
+            None => method == self.method,
+        };
+        method_matches
+            && self.direction.is_none_or(|rule| rule == direction)
+            && self
+                .command
+                .as_deref()
+                .is_none_or(|rule| Some(rule) == command)
+    }
+}
+
+/// Which LSP messages the proxy passes on, by method and direction, the first matching rule deciding
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MessagePolicy {
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+    #[serde(default)]
+    pub default: Action,
+}
+
+/// What the policy makes of a message
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Rewritten,
+    /// The message isn't passed on, with the error response to send back if it is a request
+    Deny(Option<String>),
+}
+
+impl MessagePolicy {
+    /// Reads a policy from a JSON file
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let policy = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&policy)?)
+    }
+
+    /// Applies the policy to a message going in the given direction, rewriting it if a rule says to
+    pub fn apply(&self, direction: Direction, message: &mut Value) -> Verdict {
+        let method = match message["method"].as_str() {
+            Some(method) => method.to_string(),
+            None => return Verdict::Pass,
+        };
+        let rule = self.rule(direction, &method, command(&method, message));
+        match (rule.map_or(self.default, |rule| rule.action), rule) {
+            (Action::Rewrite, Some(rule)) => {
+                merge(&mut message["params"], &rule.params);
+                Verdict::Rewritten
+            }
+            (Action::Deny, _) => {
+                warn!(method = %method, ?direction, "Message denied by policy");
+                Verdict::Deny(message.get("id").map(|id| denied_response(id, &method)))
+            }
+            _ => Verdict::Pass,
+        }
+    }
+
+    /// Removes the capabilities the client may not use from an `initialize` result, returning whether any were removed
+    pub fn trim_capabilities(&self, result: &mut Value) -> bool {
+        let capabilities = match result
+            .get_mut("capabilities")
+            .and_then(Value::as_object_mut)
+        {
+            Some(capabilities) => capabilities,
+            None => return false,
+        };
+        let mut trimmed = self.trim_commands(capabilities);
+        for (method, capability) in CAPABILITIES.iter() {
+            if self.denied(method, None) {
+                trimmed |= capabilities.remove(*capability).is_some();
+            }
+        }
+        trimmed
+    }
+
+    /// Removes the commands the client may not execute from `executeCommandProvider`
+    fn trim_commands(&self, capabilities: &mut Map<String, Value>) -> bool {
+        let commands = match capabilities
+            .get_mut("executeCommandProvider")
+            .and_then(|provider| provider.get_mut("commands"))
+            .and_then(Value::as_array_mut)
+        {
+            Some(commands) => commands,
+            None => return false,
+        };
+        let before = commands.len();
+        commands.retain(|command| !self.denied("workspace/executeCommand", command.as_str()));
+        commands.len() != before
+    }
+
+    fn denied(&self, method: &str, command: Option<&str>) -> bool {
+        let rule = self.rule(Direction::ClientToServer, method, command);
+        rule.map_or(self.default, |rule| rule.action) == Action::Deny
+    }
+
+    fn rule(
+        &self,
+        direction: Direction,
+        method: &str,
+        command: Option<&str>,
+    ) -> Option<&PolicyRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(direction, method, command))
+    }
+}
+
+/// Command of a `workspace/executeCommand` request
+fn command<'a>(method: &str, message: &'a Value) -> Option<&'a str> {
+    if method != "workspace/executeCommand" {
+        return None;
+    }
+    message["params"]["command"].as_str()
+}
+
+fn denied_response(id: &Value, method: &str) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": DENIED_CODE,
+            "message": format!("{} is not allowed by the proxy", method),
+        },
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessagePolicy, Verdict};
+    use crate::language_server::recorder::Direction;
+    use serde_json::{json, Value};
+
+    fn policy() -> MessagePolicy {
+        serde_json::from_value(json!({
+            "rules": [
+                {"method": "workspace/executeCommand", "command": "java.project.import", "action": "deny"},
+                {"method": "textDocument/hover", "action": "allow"},
+                {"method": "textDocument/*", "direction": "client_to_server", "action": "deny"},
+                {"method": "textDocument/didOpen", "action": "allow"},
+                {"method": "window/showMessage", "action": "rewrite", "params": {"type": 3}},
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let policy = policy();
+        let mut import = json!({
+            "id": 4,
+            "method": "workspace/executeCommand",
+            "params": {"command": "java.project.import", "arguments": []},
+        });
+        let mut hover = json!({"id": 5, "method": "textDocument/hover", "params": {}});
+        let mut did_open = json!({"method": "textDocument/didOpen", "params": {}});
+        let mut show_message =
+            json!({"method": "window/showMessage", "params": {"type": 1, "message": "hi"}});
+
+        let response = match policy.apply(Direction::ClientToServer, &mut import) {
+            Verdict::Deny(Some(response)) => serde_json::from_str::<Value>(&response).unwrap(),
+            verdict => panic!("request wasn't denied: {:?}", verdict),
+        };
+        assert_eq!(response["id"], 4);
+        assert_eq!(response["error"]["code"], -32803);
+        assert_eq!(
+            policy.apply(Direction::ClientToServer, &mut hover),
+            Verdict::Pass
+        );
+        // the rule for didOpen comes too late
+        assert_eq!(
+            policy.apply(Direction::ClientToServer, &mut did_open),
+            Verdict::Deny(None)
+        );
+        assert_eq!(
+            policy.apply(Direction::ServerToClient, &mut show_message),
+            Verdict::Rewritten
+        );
+        assert_eq!(show_message["params"], json!({"type": 3, "message": "hi"}));
+    }
+
+    #[test]
+    fn denied_capabilities_are_trimmed() {
+        let mut result = json!({"capabilities": {
+            "hoverProvider": true,
+            "definitionProvider": true,
+            "workspaceSymbolProvider": true,
+            "executeCommandProvider": {"commands": ["java.project.import", "java.edit.organizeImports"]},
+        }});
+
+        assert!(policy().trim_capabilities(&mut result));
+
+        assert_eq!(
+            result,
+            json!({"capabilities": {
+                "hoverProvider": true,
+                "workspaceSymbolProvider": true,
+                "executeCommandProvider": {"commands": ["java.edit.organizeImports"]},
+            }})
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use actix_web::{web, HttpResponse};
+use lsp_types::{InitializeResult, ServerCapabilities, ServerInfo};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+use super::server::send_from_proxy;
+use crate::AppState;
+
+/// Id of the proxy's probe request, which no client would use
+pub const PROBE_ID: &str = "lsp-proxy/ready-probe";
+
+/// Method of the probe request, which servers answer with an error
+const PROBE_METHOD: &str = "lsp-proxy/probe";
+
+/// How long the language server can be quiet before it is probed again
+const STALE_OUTPUT: Duration = Duration::from_secs(30);
+
+/// How long the language server has to answer a probe before it is no longer ready
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the proxy has seen of the language server, to tell whether it is ready for requests
+#[derive(Debug, Default)]
+pub struct Readiness {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Id of the client's `initialize` request, while it is waiting for a response
+    initialize_id: Option<Value>,
+    /// As the server sent it, to answer later sessions' `initialize` requests with
+    initialize_result: Option<Value>,
+    probe_answered: bool,
+    /// When the probe waiting for an answer was sent
+    probe_sent: Option<Instant>,
+    last_output: Option<Instant>,
+}
+
+/// The language server's state, as reported by the readiness endpoint
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub initialized: bool,
+    pub probe_answered: bool,
+    pub capabilities: Option<ServerCapabilities>,
+    pub server_info: Option<ServerInfo>,
+    /// Milliseconds since the language server last wrote to stdout, not set if it hasn't yet
+    pub last_output_ms: Option<u128>,
+    pub program_running: bool,
+}
+
+impl Readiness {
+    /// Notes a client message, returning the probe to send once the client has sent `initialized`
+    pub fn client_message(&self, message: &Value) -> Option<String> {
+        match message["method"].as_str() {
+            Some("initialize") => {
+                self.lock().initialize_id = message.get("id").cloned();
+                None
+            }
+            Some("initialized") => {
+                self.lock().probe_sent = Some(Instant::now());
+                Some(probe_request())
+            }
+            _ => None,
+        }
+    }
+
+    /// The probe to send if the server is initialised, quiet and not already being probed
+    pub fn stale_probe(&self) -> Option<String> {
+        let mut inner = self.lock();
+        let stale = inner
+            .last_output
+            .is_none_or(|output| output.elapsed() > STALE_OUTPUT);
+        if inner.initialize_result.is_none() || inner.probe_sent.is_some() || !stale {
+            return None;
+        }
+        inner.probe_sent = Some(Instant::now());
+        Some(probe_request())
+    }
+
+    /// Notes a server message, returning whether it answers the probe
+    pub fn server_message(&self, message: &Value) -> bool {
+        let mut inner = self.lock();
+        if message.get("method").is_some() {
+            return false;
+        }
+        let id = message.get("id");
+        if id.and_then(Value::as_str) == Some(PROBE_ID) {
+            inner.probe_answered = true;
+            inner.probe_sent = None;
+            return true;
+        }
+        if id.is_some() && id == inner.initialize_id.as_ref() {
+            inner.initialize_id = None;
+            inner.initialize_result = message.get("result").cloned();
+        }
+        false
+    }
+
+    /// Notes that the language server wrote to stdout
+    pub fn output(&self) {
+        self.lock().last_output = Some(Instant::now());
+    }
+
+    /// Forgets the server's initialisation once it has closed its output, as it won't answer anything else
+    pub fn closed(&self) {
+        let mut inner = self.lock();
+        inner.initialize_id = None;
+        inner.initialize_result = None;
+        inner.probe_answered = false;
+        inner.probe_sent = None;
+    }
+
+    /// Result of the language server's `initialize` response, once it has been initialised
+    pub fn initialize_result(&self) -> Option<Value> {
+        self.lock().initialize_result.clone()
+    }
+
+    pub fn report(&self, program_running: bool) -> ReadinessReport {
+        let inner = self.lock();
+        let initialized = inner.initialize_result.is_some();
+        let result = inner
+            .initialize_result
+            .clone()
+            .and_then(|result| serde_json::from_value::<InitializeResult>(result).ok());
+        let answering = inner
+            .probe_sent
+            .is_none_or(|sent| sent.elapsed() < PROBE_TIMEOUT);
+        ReadinessReport {
+            ready: initialized && inner.probe_answered && answering,
+            initialized,
+            probe_answered: inner.probe_answered,
+            capabilities: result.as_ref().map(|result| result.capabilities.clone()),
+            server_info: result.and_then(|result| result.server_info),
+            last_output_ms: inner.last_output.map(|output| output.elapsed().as_millis()),
+            program_running,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn probe_request() -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": PROBE_ID,
+        "method": PROBE_METHOD,
+        "params": {},
+    })
+    .to_string()
+}
+
+/// Returns the language server's state, with a 503 response until it is ready
+pub async fn readiness_check(state: web::Data<AppState>) -> HttpResponse {
+    if let Some(probe) = state.ls_readiness.stale_probe() {
+        debug!("Probing quiet language server");
+        send_from_proxy(&state, &probe).await;
+    }
+    let report = state.ls_readiness.report(!state.runs.running().is_empty());
+    if report.ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Readiness, PROBE_ID};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn ready_once_initialized_and_probe_is_answered() {
+        let readiness = Readiness::default();
+
+        assert_eq!(
+            readiness.client_message(&json!({"id": 0, "method": "initialize", "params": {}})),
+            None
+        );
+        assert!(!readiness.report(false).ready);
+        assert!(!readiness.server_message(&json!({
+            "id": 0,
+            "result": {"capabilities": {"hoverProvider": true}, "serverInfo": {"name": "mock"}},
+        })));
+        let report = readiness.report(false);
+        assert!(report.initialized);
+        assert!(!report.ready);
+        assert_eq!(
+            report.server_info.map(|info| info.name),
+            Some("mock".to_string())
+        );
+
+        let probe = readiness
+            .client_message(&json!({"method": "initialized", "params": {}}))
+            .expect("no probe sent after initialized");
+        let probe: Value = serde_json::from_str(&probe).unwrap();
+        assert_eq!(probe["id"], PROBE_ID);
+        assert!(readiness.server_message(&json!({
+            "id": PROBE_ID,
+            "error": {"code": -32601, "message": "Method not found"},
+        })));
+        assert!(readiness.report(false).ready);
+    }
+
+    #[test]
+    fn quiet_server_is_probed_again_and_closing_resets_it() {
+        let readiness = Readiness::default();
+        assert_eq!(readiness.stale_probe(), None);
+        readiness.client_message(&json!({"id": 0, "method": "initialize", "params": {}}));
+        readiness.server_message(&json!({"id": 0, "result": {"capabilities": {}}}));
+        readiness.client_message(&json!({"method": "initialized", "params": {}}));
+        assert_eq!(readiness.stale_probe(), None);
+        readiness.server_message(&json!({"id": PROBE_ID, "result": null}));
+        assert!(readiness.report(false).ready);
+
+        // nothing has been written since, so the server is probed once until it answers
+        assert!(readiness.stale_probe().is_some());
+        assert_eq!(readiness.stale_probe(), None);
+        readiness.server_message(&json!({"id": PROBE_ID, "result": null}));
+        readiness.output();
+        assert_eq!(readiness.stale_probe(), None);
+
+        readiness.closed();
+        let report = readiness.report(false);
+        assert!(!report.ready);
+        assert!(!report.initialized);
+        assert_eq!(readiness.initialize_result(), None);
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
+    sync::mpsc::{self, UnboundedSender},
+};
+use tracing::{error, info, Instrument, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl Direction {
+    /// Direction of the other side's reply
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer,
+        }
+    }
+}
+
+/// A message to or from the language server, as the server sent or received it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub direction: Direction,
+    pub message: String,
+}
+
+/// Writes a session's language server messages to a JSON Lines file named by the session id
+#[derive(Debug, Clone)]
+pub struct LspRecorder {
+    messages: UnboundedSender<RecordedMessage>,
+}
+
+impl LspRecorder {
+    /// Starts recording the session into `dir`. Failing to record doesn't affect the session itself.
+    pub fn start(dir: &Path, session_id: u64, span: &Span) -> Self {
+        let (messages, mut messages_rx) = mpsc::unbounded_channel::<RecordedMessage>();
+        let path = dir.join(format!("session-{}-{}.jsonl", session_id, now() / 1000));
+        let dir = dir.to_path_buf();
+        let record = async move {
+            let mut recording = match create(&dir, &path).await {
+                Ok(recording) => recording,
+                Err(err) => {
+                    error!(path = %path.display(), error = %err, "Error creating LSP recording");
+                    return;
+                }
+            };
+            info!(path = %path.display(), "Recording LSP session");
+            while let Some(message) = messages_rx.recv().await {
+                let mut line = match serde_json::to_string(&message) {
+                    Ok(line) => line,
+                    Err(_) => continue,
+                };
+                line.push('\n');
+                // flushed straight away, so the recording is complete even if the proxy is killed
+                let written = recording.write_all(line.as_bytes()).await;
+                if let Err(err) = written.and(recording.flush().await) {
+                    error!(error = %err, "Error writing LSP recording");
+                }
+            }
+        };
+        actix::spawn(record.instrument(span.clone()));
+        LspRecorder { messages }
+    }
+
+    pub fn record(&self, direction: Direction, message: &str) {
+        let _ = self.messages.send(RecordedMessage {
+            timestamp: now(),
+            direction,
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Reads a recording written by the recorder
+pub fn read_recording(path: &Path) -> std::io::Result<Vec<RecordedMessage>> {
+    let contents = std::fs::read_to_string(path)?;
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(std::io::Error::from))
+        .collect()
+}
+
+async fn create(dir: &Path, path: &Path) -> std::io::Result<File> {
+    tokio::fs::create_dir_all(dir).await?;
+    File::create(path).await
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_recording, Direction, LspRecorder};
+    use std::time::Duration;
+    use tracing::Span;
+
+    #[actix_rt::test]
+    async fn messages_are_recorded_in_order() {
+        let dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let recorder = LspRecorder::start(&dir.path().join("lsp"), 3, &Span::none());
+
+        recorder.record(
+            Direction::ClientToServer,
+            r#"{"id":1,"method":"initialize"}"#,
+        );
+        recorder.record(Direction::ServerToClient, r#"{"id":1,"result":{}}"#);
+
+        let mut recorded = Vec::new();
+        for _ in 0..50 {
+            tokio::time::delay_for(Duration::from_millis(20)).await;
+            let recordings: Vec<_> = std::fs::read_dir(dir.path().join("lsp"))
+                .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+                .unwrap_or_default();
+            if let Some(recording) = recordings.first() {
+                assert!(recording.to_string_lossy().contains("session-3-"));
+                recorded = read_recording(recording).unwrap();
+                if recorded.len() == 2 {
+                    break;
+                }
+            }
+        }
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].direction, Direction::ClientToServer);
+        assert_eq!(recorded[0].message, r#"{"id":1,"method":"initialize"}"#);
+        assert_eq!(recorded[1].direction, Direction::ServerToClient);
+        assert!(recorded[0].timestamp <= recorded[1].timestamp);
+    }
+}
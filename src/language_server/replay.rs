@@ -0,0 +1,199 @@
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, io, process::Stdio, time::Duration};
+use tokio::io::AsyncWriteExt;
+
+use super::{
+    recorder::{read_recording, Direction},
+    server::{json_messages, lsp_messages, wrap_lsp_message},
+};
+use crate::{config::ReplayArgs, program::runners::shell_command};
+
+/// A response from the replayed server that differs from the recorded one
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    /// Request id the response is for
+    pub id: String,
+    pub recorded: Value,
+    /// Not set if the server didn't respond in time
+    pub replayed: Option<Value>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ReplayReport {
+    /// Responses in the recording
+    pub responses: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for mismatch in &self.mismatches {
+            writeln!(f, "Response to request {} differs", mismatch.id)?;
+            writeln!(f, "- {}", mismatch.recorded)?;
+            match &mismatch.replayed {
+                Some(replayed) => writeln!(f, "+ {}", replayed)?,
+                None => writeln!(f, "+ no response")?,
+            }
+        }
+        writeln!(
+            f,
+            "{} of {} responses matched",
+            self.responses - self.mismatches.len(),
+            self.responses
+        )
+    }
+}
+
+/// Replays a recording's client messages to a new language server and compares the responses
+pub async fn replay(args: &ReplayArgs) -> io::Result<ReplayReport> {
+    let recording = read_recording(&args.recording)?;
+    let recorded: BTreeMap<String, Value> = recording
+        .iter()
+        .filter(|entry| entry.direction == Direction::ServerToClient)
+        .flat_map(|entry| json_messages(&entry.message))
+        .filter_map(|message| Some((response_id(&message)?, message)))
+        .collect();
+
+    let mut server = shell_command(&args.server_cmd)
+        .kill_on_drop(true)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = server.stdin.take().ok_or(io::ErrorKind::BrokenPipe)?;
+    let mut messages = lsp_messages(server.stdout.take().ok_or(io::ErrorKind::BrokenPipe)?);
+    let timeout = Duration::from_secs(args.timeout);
+
+    let mut replayed = BTreeMap::new();
+    for entry in recording
+        .iter()
+        .filter(|entry| entry.direction == Direction::ClientToServer)
+    {
+        stdin
+            .write_all(wrap_lsp_message(&entry.message).as_bytes())
+            .await?;
+        let request_id = serde_json::from_str::<Value>(&entry.message)
+            .ok()
+            .and_then(|message| request_id(&message))
+            .filter(|id| recorded.contains_key(id));
+        if let Some(id) = request_id {
+            let response = async {
+                while !replayed.contains_key(&id) {
+                    let message = match messages.recv().await {
+                        Some(message) => message,
+                        None => break,
+                    };
+                    for message in json_messages(&message) {
+                        if let Some(id) = response_id(&message) {
+                            replayed.insert(id, message);
+                        }
+                    }
+                }
+            };
+            // a request the server never answers is reported as a mismatch, rather than holding up the rest of the replay
+            let _ = tokio::time::timeout(timeout, response).await;
+        }
+    }
+
+    Ok(ReplayReport {
+        responses: recorded.len(),
+        mismatches: compare(recorded, &replayed),
+    })
+}
+
+fn compare(recorded: BTreeMap<String, Value>, replayed: &BTreeMap<String, Value>) -> Vec<Mismatch> {
+    recorded
+        .into_iter()
+        .filter_map(|(id, recorded)| {
+            let replayed = replayed.get(&id).cloned();
+            if replayed.as_ref() == Some(&recorded) {
+                return None;
+            }
+            Some(Mismatch {
+                id,
+                recorded,
+                replayed,
+            })
+        })
+        .collect()
+}
+
+/// Id of a request, from either side
+fn request_id(message: &Value) -> Option<String> {
+    message.get("method")?;
+    message.get("id").map(Value::to_string)
+}
+
+fn response_id(message: &Value) -> Option<String> {
+    if message.get("method").is_some() {
+        return None;
+    }
+    message.get("id").map(Value::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::replay;
+    use crate::{
+        config::ReplayArgs,
+        language_server::{
+            recorder::{Direction, RecordedMessage},
+            server::wrap_lsp_message,
+        },
+    };
+
+    #[cfg(unix)]
+    #[actix_rt::test]
+    async fn replayed_responses_are_compared_with_recorded_ones() {
+        let dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let recording = dir.path().join("session.jsonl");
+        let entry = |direction, message: &str| {
+            serde_json::to_string(&RecordedMessage {
+                timestamp: 0,
+                direction,
+                message: message.to_string(),
+            })
+            .unwrap()
+        };
+        let recorded = [
+            entry(
+                Direction::ClientToServer,
+                r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            ),
+            entry(Direction::ServerToClient, "Content-Length: 36\r"),
+            entry(Direction::ServerToClient, "\r"),
+            entry(
+                Direction::ServerToClient,
+                r#"{"jsonrpc":"2.0","id":1,"result":{}}"#,
+            ),
+            entry(
+                Direction::ClientToServer,
+                r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+            ),
+            entry(
+                Direction::ServerToClient,
+                r#"{"jsonrpc":"2.0","id":2,"result":null}"#,
+            ),
+        ];
+        std::fs::write(&recording, recorded.join("\n")).unwrap();
+
+        // a mock server that answers the first request, and nothing else
+        let response = wrap_lsp_message(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#);
+        let args = ReplayArgs {
+            recording,
+            server_cmd: format!(
+                "printf '{}'; cat > /dev/null",
+                response.replace("\r\n", "\\r\\n")
+            ),
+            timeout: 1,
+            log_level: None,
+            log_json: false,
+        };
+
+        let report = replay(&args).await.unwrap();
+
+        assert_eq!(report.responses, 2);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].id, "2");
+        assert_eq!(report.mismatches[0].replayed, None);
+    }
+}
@@ -0,0 +1,274 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+use super::recorder::Direction;
+
+/// Start of the ids sessions' requests are sent to the language server with
+const SESSION_ID_PREFIX: &str = "lsp-proxy/session-";
+
+/// JSON-RPC error code for a request that was cancelled
+const REQUEST_CANCELLED: i64 = -32800;
+
+/// A JSON-RPC request passed on by the proxy that hasn't been answered yet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InFlightRequest {
+    pub id: Value,
+    pub method: String,
+    /// Side the request came from, the other side being the one to answer it
+    pub direction: Direction,
+    /// Milliseconds since the request passed through the proxy
+    pub elapsed_ms: u128,
+}
+
+/// A request whose response has passed through the proxy
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletedRequest {
+    pub method: String,
+    pub direction: Direction,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug)]
+struct PendingRequest {
+    id: Value,
+    method: String,
+    sent: Instant,
+}
+
+/// Pairs a session's requests with their responses in both directions, logging slow ones
+#[derive(Debug)]
+pub struct RequestTracker {
+    /// Keyed by the side the request came from and its id
+    pending: HashMap<(Direction, String), PendingRequest>,
+    slow_threshold: Duration,
+    /// Prefix of this session's request ids as the language server sees them
+    id_prefix: String,
+}
+
+impl RequestTracker {
+    pub fn new(slow_threshold: Duration, session_id: u64) -> Self {
+        RequestTracker {
+            pending: HashMap::new(),
+            slow_threshold,
+            id_prefix: format!("{}{}/", SESSION_ID_PREFIX, session_id),
+        }
+    }
+
+    /// Prefixes the id of a client request or of the request it cancels, returning whether it changed
+    pub fn rewrite_client_ids(&self, message: &mut Value) -> bool {
+        if message.get("method").is_none() {
+            return false;
+        }
+        let mut rewritten = false;
+        if let Some(id) = message.get_mut("id") {
+            *id = self.server_id(id);
+            rewritten = true;
+        }
+        if message["method"] == "$/cancelRequest" {
+            if let Some(id) = message["params"].get_mut("id") {
+                *id = self.server_id(id);
+                rewritten = true;
+            }
+        }
+        rewritten
+    }
+
+    /// Restores the client's id on a response, returning false if the request came from another session
+    pub fn restore_client_id(&self, message: &mut Value) -> bool {
+        if message.get("method").is_some() {
+            return true;
+        }
+        let client_id = message
+            .get("id")
+            .and_then(Value::as_str)
+            .and_then(|id| id.strip_prefix(&self.id_prefix))
+            .and_then(|id| serde_json::from_str(id).ok());
+        match client_id {
+            Some(id) => {
+                message["id"] = id;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn server_id(&self, id: &Value) -> Value {
+        json!(format!("{}{}", self.id_prefix, id))
+    }
+
+    /// Notes a message passing through the proxy, returning the request it answers if it is a response
+    pub fn message(&mut self, direction: Direction, message: &Value) -> Option<CompletedRequest> {
+        let id = message.get("id")?;
+        if let Some(method) = message["method"].as_str() {
+            let request = PendingRequest {
+                id: id.clone(),
+                method: method.to_string(),
+                sent: Instant::now(),
+            };
+            self.pending.insert((direction, id.to_string()), request);
+            return None;
+        }
+
+        let request = self
+            .pending
+            .remove(&(direction.reverse(), id.to_string()))?;
+        let elapsed = request.sent.elapsed();
+        if elapsed >= self.slow_threshold {
+            warn!(
+                id = %request.id,
+                method = %request.method,
+                ?direction,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "Slow request"
+            );
+        }
+        Some(CompletedRequest {
+            method: request.method,
+            direction: direction.reverse(),
+            elapsed,
+        })
+    }
+
+    /// Requests waiting for a response, the longest waiting first
+    pub fn in_flight(&self) -> Vec<InFlightRequest> {
+        let mut requests: Vec<_> = self
+            .pending
+            .iter()
+            .map(|((direction, _), request)| InFlightRequest {
+                id: request.id.clone(),
+                method: request.method.clone(),
+                direction: *direction,
+                elapsed_ms: request.sent.elapsed().as_millis(),
+            })
+            .collect();
+        requests.sort_by_key(|request| Reverse(request.elapsed_ms));
+        requests
+    }
+
+    /// Messages ending the session's pending requests, cancelling the client's and failing the server's
+    pub fn end_requests(&mut self) -> Vec<String> {
+        let mut messages = Vec::new();
+        let pending: Vec<_> = self.pending.drain().collect();
+        for ((direction, _), request) in pending {
+            let message = match direction {
+                Direction::ClientToServer => json!({
+                    "jsonrpc": "2.0",
+                    "method": "$/cancelRequest",
+                    "params": {"id": self.server_id(&request.id)},
+                }),
+                Direction::ServerToClient => cancelled_response(&request.id),
+            };
+            messages.push(message.to_string());
+        }
+        messages
+    }
+}
+
+/// Error answering a language server request that no client will answer
+pub fn cancelled_response(id: &Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": REQUEST_CANCELLED, "message": "No client is connected to answer the request"},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestTracker;
+    use crate::language_server::recorder::Direction;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    #[test]
+    fn responses_are_paired_with_requests_in_both_directions() {
+        let mut tracker = RequestTracker::new(Duration::from_secs(5), 1);
+
+        tracker.message(
+            Direction::ClientToServer,
+            &json!({"id": 1, "method": "textDocument/hover"}),
+        );
+        tracker.message(
+            Direction::ServerToClient,
+            &json!({"id": 1, "method": "workspace/configuration"}),
+        );
+        tracker.message(
+            Direction::ClientToServer,
+            &json!({"method": "textDocument/didOpen"}),
+        );
+        assert_eq!(tracker.in_flight().len(), 2);
+
+        let completed = tracker
+            .message(Direction::ServerToClient, &json!({"id": 1, "result": null}))
+            .expect("response wasn't paired with its request");
+        assert_eq!(completed.method, "textDocument/hover");
+        assert_eq!(completed.direction, Direction::ClientToServer);
+
+        let in_flight = tracker.in_flight();
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!(in_flight[0].method, "workspace/configuration");
+        assert_eq!(in_flight[0].direction, Direction::ServerToClient);
+        assert_eq!(
+            tracker.message(Direction::ServerToClient, &json!({"id": 2, "result": null})),
+            None
+        );
+    }
+
+    #[test]
+    fn pending_requests_are_ended_in_both_directions() {
+        let mut tracker = RequestTracker::new(Duration::from_secs(5), 1);
+        tracker.message(
+            Direction::ClientToServer,
+            &json!({"id": "a", "method": "textDocument/completion"}),
+        );
+        tracker.message(
+            Direction::ServerToClient,
+            &json!({"id": 3, "method": "window/workDoneProgress/create"}),
+        );
+
+        let mut ended: Vec<Value> = tracker
+            .end_requests()
+            .iter()
+            .map(|message| serde_json::from_str(message).unwrap())
+            .collect();
+        ended.sort_by_key(|message| message.get("method").is_none());
+
+        assert_eq!(
+            ended[0],
+            json!({"jsonrpc": "2.0", "method": "$/cancelRequest", "params": {"id": "lsp-proxy/session-1/\"a\""}})
+        );
+        assert_eq!(ended[1]["id"], 3);
+        assert_eq!(ended[1]["error"]["code"], -32800);
+        assert!(tracker.in_flight().is_empty());
+    }
+
+    #[test]
+    fn client_ids_are_kept_apart_from_other_sessions() {
+        let first = RequestTracker::new(Duration::from_secs(5), 1);
+        let second = RequestTracker::new(Duration::from_secs(5), 2);
+
+        let mut request = json!({"id": 5, "method": "textDocument/hover"});
+        assert!(first.rewrite_client_ids(&mut request));
+        let mut cancel = json!({"method": "$/cancelRequest", "params": {"id": 5}});
+        assert!(first.rewrite_client_ids(&mut cancel));
+        assert_eq!(cancel["params"]["id"], request["id"]);
+        let mut answer = json!({"id": 9, "result": null});
+        assert!(!first.rewrite_client_ids(&mut answer));
+        assert_eq!(answer["id"], 9);
+
+        let response = json!({"id": request["id"], "result": null});
+        assert!(!second.restore_client_id(&mut response.clone()));
+        let mut response = response;
+        assert!(first.restore_client_id(&mut response));
+        assert_eq!(response["id"], 5);
+        assert!(
+            second.restore_client_id(&mut json!({"id": 4, "method": "workspace/configuration"}))
+        );
+    }
+}
@@ -0,0 +1,670 @@
+use actix_web::{
+    dev::HttpResponseBuilder, error, http::header, http::StatusCode, web, web::Json, HttpResponse,
+};
+use derive_more::{Display, Error};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Initialized, Notification,
+    },
+    request::{DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Request},
+    DocumentSymbolParams, GotoDefinitionParams, HoverParams, Position, ReferenceContext,
+    ReferenceParams, TextDocumentIdentifier, TextDocumentPositionParams, Url,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    path::{Component, Path},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+use tokio::{
+    io::AsyncWriteExt,
+    process::{Child, ChildStdin},
+    sync::oneshot,
+};
+use tracing::{debug, debug_span, warn, Instrument};
+
+use super::{
+    initialize::rewrite_initialize,
+    interceptor::Intercepted,
+    policy::Verdict,
+    recorder::Direction,
+    server::{record, wrap_lsp_message},
+    server_stdin,
+};
+use crate::{config::Lang, AppState};
+
+/// Prefix of the ids of the proxy's own requests for the REST endpoints, which no client would use
+const REQUEST_ID_PREFIX: &str = "lsp-proxy/rest-";
+
+/// The proxy's own requests waiting for responses, and the documents open on the language server
+#[derive(Debug, Default)]
+pub struct ProxyRequests {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<String, oneshot::Sender<Value>>>,
+    /// Who has each document open on the language server
+    open_documents: Mutex<HashMap<Url, OpenDocument>>,
+    /// Held while the proxy initialises the language server, so it only does once and a session's
+    /// `initialize` can wait to be answered from the result
+    initializing: tokio::sync::Mutex<()>,
+}
+
+/// Who has a document open on the language server
+#[derive(Debug, Default)]
+struct OpenDocument {
+    /// Whether the connected session's client opened it
+    by_client: bool,
+    /// How many of the proxy's requests have it open
+    requests: usize,
+}
+
+impl ProxyRequests {
+    /// Notes the documents a client opens and closes, from the messages as the language server gets them.
+    /// The server already has the documents the proxy's requests opened, so a client opening one
+    /// replaces its text instead, and a client closing one leaves it to the requests to close.
+    pub fn client_message(&self, message: &Value) -> Intercepted {
+        let document = &message["params"]["textDocument"];
+        let uri = match serde_json::from_value::<Url>(document["uri"].clone()) {
+            Ok(uri) => uri,
+            Err(_) => return Intercepted::Pass,
+        };
+        let mut open_documents = lock(&self.open_documents);
+        match message["method"].as_str() {
+            Some(DidOpenTextDocument::METHOD) => {
+                let opened = open_documents.entry(uri).or_default();
+                opened.by_client = true;
+                if opened.requests == 0 {
+                    return Intercepted::Pass;
+                }
+                Intercepted::Modify(json!({
+                    "jsonrpc": "2.0",
+                    "method": DidChangeTextDocument::METHOD,
+                    "params": {
+                        "textDocument": {"uri": document["uri"], "version": document["version"]},
+                        "contentChanges": [{"text": document["text"]}],
+                    },
+                }))
+            }
+            Some(DidCloseTextDocument::METHOD) => match open_documents.get_mut(&uri) {
+                Some(opened) if opened.requests > 0 => {
+                    opened.by_client = false;
+                    Intercepted::Swallow
+                }
+                _ => {
+                    open_documents.remove(&uri);
+                    Intercepted::Pass
+                }
+            },
+            _ => Intercepted::Pass,
+        }
+    }
+
+    /// Forgets the documents the client of a closed session had open, returning the notifications
+    /// that close those no request has open on the language server
+    pub fn end_session(&self) -> Vec<String> {
+        let mut closed = Vec::new();
+        lock(&self.open_documents).retain(|uri, opened| {
+            if opened.by_client && opened.requests == 0 {
+                let params = json!({"textDocument": {"uri": uri}});
+                let method = DidCloseTextDocument::METHOD;
+                closed.push(
+                    json!({"jsonrpc": "2.0", "method": method, "params": params}).to_string(),
+                );
+                return false;
+            }
+            opened.by_client = false;
+            true
+        });
+        closed
+    }
+
+    /// Forgets the open documents once the language server has closed its output
+    pub fn closed(&self) {
+        lock(&self.open_documents).clear();
+    }
+
+    /// Waits for the proxy to finish initialising the language server, if it is
+    pub async fn initialized(&self) {
+        drop(self.initializing.lock().await);
+    }
+
+    /// Hands a response to the proxy request waiting for it, returning whether there was one
+    pub fn server_message(&self, message: &Value) -> bool {
+        if message.get("method").is_some() {
+            return false;
+        }
+        let id = match message["id"].as_str() {
+            Some(id) if id.starts_with(REQUEST_ID_PREFIX) => id,
+            _ => return false,
+        };
+        if let Some(waiting) = lock(&self.pending).remove(id) {
+            let _ = waiting.send(message.clone());
+        }
+        true
+    }
+
+    /// Takes an id for a request, with the receiver its response is handed to
+    fn start(&self) -> (String, oneshot::Receiver<Value>) {
+        let id = format!(
+            "{}{}",
+            REQUEST_ID_PREFIX,
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+        let (waiting, response) = oneshot::channel();
+        lock(&self.pending).insert(id.clone(), waiting);
+        (id, response)
+    }
+
+    /// Stops waiting for a request's response
+    fn forget(&self, id: &str) {
+        lock(&self.pending).remove(id);
+    }
+
+    /// Notes a request opening a document, returning whether it wasn't open yet
+    fn open(&self, uri: &Url) -> bool {
+        let mut open_documents = lock(&self.open_documents);
+        let opened = open_documents.entry(uri.clone()).or_default();
+        opened.requests += 1;
+        opened.requests == 1 && !opened.by_client
+    }
+
+    /// Notes a request closing a document, returning whether nothing has it open any more
+    fn close(&self, uri: &Url) -> bool {
+        let mut open_documents = lock(&self.open_documents);
+        let opened = match open_documents.get_mut(uri) {
+            Some(opened) if opened.requests > 0 => opened,
+            _ => return false,
+        };
+        opened.requests -= 1;
+        if opened.requests > 0 || opened.by_client {
+            return false;
+        }
+        open_documents.remove(uri);
+        true
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[derive(Debug, Display, Error)]
+pub enum LspRequestError {
+    #[display(fmt = "Invalid document path: {}", cause)]
+    InvalidPath { cause: String },
+    #[display(fmt = "Document not found")]
+    NotFound,
+    #[display(fmt = "{} is not allowed by the proxy", method)]
+    Denied { method: String },
+    #[display(fmt = "The language server is being initialised by the connected session")]
+    NotInitialized,
+    #[display(fmt = "The language server did not answer in time")]
+    Timeout,
+    #[display(fmt = "The language server failed the request: {}", cause)]
+    ServerError { cause: String },
+    #[display(fmt = "Could not write to the language server: {}", cause)]
+    Unavailable { cause: String },
+}
+
+impl error::ResponseError for LspRequestError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponseBuilder::new(self.status_code())
+            .set_header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(self.to_string())
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LspRequestError::InvalidPath { .. } => StatusCode::BAD_REQUEST,
+            LspRequestError::NotFound => StatusCode::NOT_FOUND,
+            LspRequestError::Denied { .. } => StatusCode::FORBIDDEN,
+            LspRequestError::NotInitialized => StatusCode::SERVICE_UNAVAILABLE,
+            LspRequestError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            LspRequestError::ServerError { .. } | LspRequestError::Unavailable { .. } => {
+                StatusCode::BAD_GATEWAY
+            }
+        }
+    }
+}
+
+/// Query parameters of the endpoints for a position in a document
+#[derive(Debug, Deserialize)]
+pub struct PositionQuery {
+    /// Document's path within the workspace
+    pub path: String,
+    /// Zero based, as in LSP
+    pub line: u32,
+    /// Zero based, in UTF-16 code units as in LSP
+    pub col: u32,
+}
+
+/// Query parameters of the endpoints for a whole document
+#[derive(Debug, Deserialize)]
+pub struct DocumentQuery {
+    /// Document's path within the workspace
+    pub path: String,
+}
+
+/// Hover information at a position in a document
+pub async fn hover(
+    process: web::Data<Arc<std::sync::Mutex<Child>>>,
+    state: web::Data<AppState>,
+    query: web::Query<PositionQuery>,
+) -> Result<Json<<HoverRequest as Request>::Result>, LspRequestError> {
+    let session = ProxySession::connect(&process, &state);
+    let result = session
+        .document_request::<HoverRequest, _>(&query.path, |text_document| HoverParams {
+            text_document_position_params: position_params(text_document, &query),
+            work_done_progress_params: Default::default(),
+        })
+        .await?;
+    Ok(Json(result))
+}
+
+/// Where the symbol at a position in a document is defined
+pub async fn definition(
+    process: web::Data<Arc<std::sync::Mutex<Child>>>,
+    state: web::Data<AppState>,
+    query: web::Query<PositionQuery>,
+) -> Result<Json<<GotoDefinition as Request>::Result>, LspRequestError> {
+    let session = ProxySession::connect(&process, &state);
+    let result = session
+        .document_request::<GotoDefinition, _>(&query.path, |text_document| GotoDefinitionParams {
+            text_document_position_params: position_params(text_document, &query),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await?;
+    Ok(Json(result))
+}
+
+/// References to the symbol at a position in a document, its declaration included
+pub async fn references(
+    process: web::Data<Arc<std::sync::Mutex<Child>>>,
+    state: web::Data<AppState>,
+    query: web::Query<PositionQuery>,
+) -> Result<Json<<References as Request>::Result>, LspRequestError> {
+    let session = ProxySession::connect(&process, &state);
+    let result = session
+        .document_request::<References, _>(&query.path, |text_document| ReferenceParams {
+            text_document_position: position_params(text_document, &query),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+        })
+        .await?;
+    Ok(Json(result))
+}
+
+/// The symbols in a document, as the language server's `textDocument/documentSymbol` response
+pub async fn symbols(
+    process: web::Data<Arc<std::sync::Mutex<Child>>>,
+    state: web::Data<AppState>,
+    query: web::Query<DocumentQuery>,
+) -> Result<Json<<DocumentSymbolRequest as Request>::Result>, LspRequestError> {
+    let session = ProxySession::connect(&process, &state);
+    let result = session
+        .document_request::<DocumentSymbolRequest, _>(&query.path, |text_document| {
+            DocumentSymbolParams {
+                text_document,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            }
+        })
+        .await?;
+    Ok(Json(result))
+}
+
+fn position_params(
+    text_document: TextDocumentIdentifier,
+    query: &PositionQuery,
+) -> TextDocumentPositionParams {
+    TextDocumentPositionParams {
+        text_document,
+        position: Position::new(query.line, query.col),
+    }
+}
+
+/// The proxy's own connection to the language server for a REST request
+struct ProxySession<'a> {
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    state: &'a AppState,
+}
+
+impl<'a> ProxySession<'a> {
+    /// Connects to the language server, which is initialised once a request has been checked
+    fn connect(
+        process: &Arc<std::sync::Mutex<Child>>,
+        state: &'a web::Data<AppState>,
+    ) -> ProxySession<'a> {
+        ProxySession {
+            stdin: server_stdin(process, state),
+            state,
+        }
+    }
+
+    /// Initialises the language server for the workspace, unless a websocket session is connected to do it
+    async fn initialize(&self) -> Result<(), LspRequestError> {
+        let _initializing = self.state.proxy_requests.initializing.lock().await;
+        if self.state.ls_readiness.initialize_result().is_some() {
+            return Ok(());
+        }
+        if lock(&self.state.ls_client).is_some() {
+            return Err(LspRequestError::NotInitialized);
+        }
+        debug!("Initialising language server for REST requests");
+        let mut initialize = json!({"params": {
+            "processId": std::process::id(),
+            "rootUri": null,
+            "capabilities": client_capabilities(),
+        }});
+        rewrite_initialize(
+            &mut initialize,
+            &self.state.workspace_dir,
+            self.state.initialization_options.as_ref(),
+        );
+        let request = self.request("initialize", initialize["params"].take())?;
+        self.send(request).await?;
+        self.notify(Initialized::METHOD, json!({})).await
+    }
+
+    /// Makes a request about a document, opening it on the language server if it isn't open
+    async fn document_request<R: Request, F>(
+        &self,
+        path: &str,
+        params: F,
+    ) -> Result<R::Result, LspRequestError>
+    where
+        F: FnOnce(TextDocumentIdentifier) -> R::Params,
+    {
+        let uri = self.document_uri(path)?;
+        let request = self.request(
+            R::METHOD,
+            json!(params(TextDocumentIdentifier::new(uri.clone()))),
+        )?;
+        let span = debug_span!("rest_request", method = R::METHOD, uri = %uri);
+        async {
+            if self.state.ls_readiness.initialize_result().is_none() {
+                self.initialize().await?;
+            }
+            if self.state.proxy_requests.open(&uri) {
+                if let Err(err) = self.open(&uri).await {
+                    self.state.proxy_requests.close(&uri);
+                    return Err(err);
+                }
+            }
+            let result = self.send(request).await;
+            if self.state.proxy_requests.close(&uri) {
+                let params = json!({"textDocument": {"uri": uri}});
+                self.notify(DidCloseTextDocument::METHOD, params).await?;
+            }
+            serde_json::from_value(result?).map_err(|err| LspRequestError::ServerError {
+                cause: err.to_string(),
+            })
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// URI of a document in the workspace, from its path within it
+    fn document_uri(&self, path: &str) -> Result<Url, LspRequestError> {
+        let path = Path::new(path);
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(LspRequestError::InvalidPath {
+                cause: "paths must be relative to the workspace, without `..`".to_string(),
+            });
+        }
+        let path = Path::new(&self.state.workspace_dir)
+            .canonicalize()
+            .map_err(|_| LspRequestError::NotFound)?
+            .join(path);
+        if !path.is_file() {
+            return Err(LspRequestError::NotFound);
+        }
+        Url::from_file_path(&path).map_err(|_| LspRequestError::NotFound)
+    }
+
+    /// A request as the message policy leaves it, failing with [`LspRequestError::Denied`] if the
+    /// policy doesn't let the client make it. Its id is only taken once it is sent
+    fn request(&self, method: &str, params: Value) -> Result<Value, LspRequestError> {
+        let mut request = json!({"jsonrpc": "2.0", "id": 0, "method": method, "params": params});
+        match self
+            .state
+            .message_policy
+            .apply(Direction::ClientToServer, &mut request)
+        {
+            Verdict::Deny(_) => Err(LspRequestError::Denied {
+                method: method.to_string(),
+            }),
+            _ => Ok(request),
+        }
+    }
+
+    /// Opens a document on the language server with its contents on disk
+    async fn open(&self, uri: &Url) -> Result<(), LspRequestError> {
+        let path = uri.to_file_path().map_err(|_| LspRequestError::NotFound)?;
+        let text = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|_| LspRequestError::NotFound)?;
+        let language_id = match self.state.lang {
+            Lang::Java => "java".to_string(),
+            Lang::C => "c".to_string(),
+            Lang::Custom => path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+        let params = json!({"textDocument": {
+            "uri": uri,
+            "languageId": language_id,
+            "version": 0,
+            "text": text,
+        }});
+        self.notify(DidOpenTextDocument::METHOD, params).await
+    }
+
+    /// Sends a request from [`ProxySession::request`], and waits for its result until the proxy's timeout
+    async fn send(&self, mut request: Value) -> Result<Value, LspRequestError> {
+        let (id, response) = self.state.proxy_requests.start();
+        request["id"] = json!(id);
+        self.state.ls_readiness.client_message(&request);
+        if let Err(err) = self.write(&request).await {
+            self.state.proxy_requests.forget(&id);
+            return Err(err);
+        }
+
+        let response = match tokio::time::timeout(self.state.lsp_request_timeout, response).await {
+            Ok(Ok(response)) => response,
+            _ => {
+                warn!(
+                    method = request["method"].as_str(),
+                    "Language server did not answer in time"
+                );
+                self.state.proxy_requests.forget(&id);
+                let cancel =
+                    json!({"jsonrpc": "2.0", "method": "$/cancelRequest", "params": {"id": id}});
+                self.write(&cancel).await?;
+                return Err(LspRequestError::Timeout);
+            }
+        };
+        match response.get("error") {
+            Some(error) => Err(LspRequestError::ServerError {
+                cause: error["message"].as_str().unwrap_or_default().to_string(),
+            }),
+            None => Ok(response["result"].clone()),
+        }
+    }
+
+    /// Sends a notification, subject to the message policy
+    async fn notify(&self, method: &str, params: Value) -> Result<(), LspRequestError> {
+        let mut notification = json!({"jsonrpc": "2.0", "method": method, "params": params});
+        if let Verdict::Deny(_) = self
+            .state
+            .message_policy
+            .apply(Direction::ClientToServer, &mut notification)
+        {
+            return Err(LspRequestError::Denied {
+                method: method.to_string(),
+            });
+        }
+        self.write(&notification).await?;
+        match self.state.ls_readiness.client_message(&notification) {
+            Some(probe) => self.write_raw(&probe).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn write(&self, message: &Value) -> Result<(), LspRequestError> {
+        self.write_raw(&message.to_string()).await
+    }
+
+    async fn write_raw(&self, message: &str) -> Result<(), LspRequestError> {
+        let mut stdin = self.stdin.lock().await;
+        record(self.state, Direction::ClientToServer, message);
+        stdin
+            .write_all(wrap_lsp_message(message).as_bytes())
+            .await
+            .map_err(|err| LspRequestError::Unavailable {
+                cause: err.to_string(),
+            })
+    }
+}
+
+/// What the proxy supports as a client, for the requests of the REST endpoints and the server requests it can answer
+fn client_capabilities() -> Value {
+    let symbol_kinds: Vec<u8> = (1..=26).collect();
+    json!({
+        "workspace": {
+            "applyEdit": true,
+            "workspaceEdit": {"documentChanges": true, "resourceOperations": ["create", "delete"]},
+            "configuration": true,
+            "workspaceFolders": false,
+        },
+        "textDocument": {
+            "synchronization": {"dynamicRegistration": false, "willSave": false, "willSaveWaitUntil": false, "didSave": false},
+            "hover": {"dynamicRegistration": false, "contentFormat": ["markdown", "plaintext"]},
+            "definition": {"dynamicRegistration": false, "linkSupport": true},
+            "references": {"dynamicRegistration": false},
+            "documentSymbol": {
+                "dynamicRegistration": false,
+                "symbolKind": {"valueSet": symbol_kinds},
+                "hierarchicalDocumentSymbolSupport": true,
+            },
+            "publishDiagnostics": {"relatedInformation": false},
+        },
+        "window": {"workDoneProgress": true},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProxyRequests;
+    use crate::language_server::interceptor::Intercepted;
+    use lsp_types::Url;
+    use serde_json::{json, Value};
+
+    #[actix_rt::test]
+    async fn responses_go_to_the_waiting_request() {
+        let requests = ProxyRequests::default();
+        let (id, response) = requests.start();
+
+        assert!(!requests.server_message(&json!({"id": 1, "result": null})));
+        assert!(!requests
+            .server_message(&json!({"id": id, "method": "window/workDoneProgress/create"})));
+        assert!(requests.server_message(&json!({"id": id, "result": {"contents": "int"}})));
+        assert_eq!(
+            response.await.unwrap(),
+            json!({"id": id, "result": {"contents": "int"}})
+        );
+        // late responses to forgotten requests are still only meant for the proxy
+        let (id, _) = requests.start();
+        requests.forget(&id);
+        assert!(requests.server_message(&json!({"id": id, "result": null})));
+    }
+
+    #[test]
+    fn documents_stay_open_until_closed_by_all() {
+        let requests = ProxyRequests::default();
+        let uri = Url::parse("file:///srv/ws/Hello.java").unwrap();
+
+        assert_eq!(requests.client_message(&did_open(&uri)), Intercepted::Pass);
+        assert!(!requests.open(&uri));
+        assert!(!requests.close(&uri));
+        assert!(!requests.open(&uri));
+        // the request still has it open, so the server keeps it
+        assert_eq!(
+            requests.client_message(&did_close(&uri)),
+            Intercepted::Swallow
+        );
+        assert!(requests.close(&uri));
+        assert!(requests.open(&uri));
+    }
+
+    #[test]
+    fn client_opening_a_document_a_request_has_open_replaces_its_text() {
+        let requests = ProxyRequests::default();
+        let uri = Url::parse("file:///srv/ws/Hello.java").unwrap();
+
+        assert!(requests.open(&uri));
+        assert_eq!(
+            requests.client_message(&did_open(&uri)),
+            Intercepted::Modify(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": {"uri": uri, "version": 1},
+                    "contentChanges": [{"text": "class Hello {}"}],
+                },
+            }))
+        );
+        assert!(!requests.close(&uri));
+        assert_eq!(requests.client_message(&did_close(&uri)), Intercepted::Pass);
+    }
+
+    #[test]
+    fn documents_of_an_ended_session_are_closed() {
+        let requests = ProxyRequests::default();
+        let left_open = Url::parse("file:///srv/ws/Hello.java").unwrap();
+        let requested = Url::parse("file:///srv/ws/World.java").unwrap();
+        requests.client_message(&did_open(&left_open));
+        requests.client_message(&did_open(&requested));
+        requests.open(&requested);
+
+        let closed: Vec<Value> = requests
+            .end_session()
+            .iter()
+            .map(|message| serde_json::from_str(message).unwrap())
+            .collect();
+
+        assert_eq!(
+            closed,
+            vec![json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didClose",
+                "params": {"textDocument": {"uri": left_open}},
+            })]
+        );
+        // the request closes the other once it is done, as the client no longer has it open
+        assert!(requests.close(&requested));
+        assert!(requests.open(&left_open));
+    }
+
+    fn did_open(uri: &Url) -> Value {
+        json!({
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": uri, "languageId": "java", "version": 1, "text": "class Hello {}"}},
+        })
+    }
+
+    fn did_close(uri: &Url) -> Value {
+        json!({"method": "textDocument/didClose", "params": {"textDocument": {"uri": uri}}})
+    }
+}
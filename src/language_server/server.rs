@@ -1,98 +1,662 @@
-use crate::Line;
+use crate::{metrics::LspSessionMetrics, AppState, Line};
 
-use super::intercept::intercept_notification;
-use actix::{Actor, AsyncContext, StreamHandler};
+use super::{
+    initialize::{initialize_response, rewrite_initialize},
+    interceptor::Intercepted,
+    policy::Verdict,
+    recorder::{Direction, LspRecorder},
+    request_tracker::{cancelled_response, CompletedRequest, InFlightRequest, RequestTracker},
+};
+use crate::logging::{next_session_id, session_span};
+use actix::{
+    fut::ActorFuture, Actor, AsyncContext, Handler, Message, MessageResult, StreamHandler,
+};
+use actix_web::web;
 use actix_web_actors::ws;
-use serde_json::Value;
-use std::sync::Arc;
+use serde_json::{json, Deserializer, Value};
+use std::{
+    ops::Range,
+    sync::{atomic::Ordering, Arc, PoisonError},
+};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::{Child, ChildStdin, ChildStdout},
-    stream::StreamExt,
-    sync::Mutex,
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::{ChildStdin, ChildStdout},
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        Mutex,
+    },
 };
+use tracing::{debug, debug_span, error, field, info, trace, warn, Instrument, Span};
 
+/// A websocket session with the language server, which stays initialised between sessions
 pub struct LangServer {
-    stdin: Arc<Mutex<ChildStdin>>,
-    stdout: Option<ChildStdout>,
+    /// Batches of messages for the language server, written in the order they were sent
+    to_server: UnboundedSender<Vec<String>>,
+    state: web::Data<AppState>,
+    span: Span,
+    /// Shared with the server writer and [`forward_output`]
+    recorder: Option<LspRecorder>,
+    session_metrics: LspSessionMetrics,
+    requests: RequestTracker,
+    /// Whether `initialize` was answered from an earlier session, so `initialized` isn't passed on
+    reinitialized: bool,
 }
+
+/// What a session does with a message from its client
+enum Lifecycle {
+    Forward,
+    /// Forward the message as the proxy has changed it
+    Rewritten,
+    /// Answer the client without passing the message on
+    Answer(String),
+    Drop,
+}
+
 impl LangServer {
-    pub fn new(child: Arc<std::sync::Mutex<Child>>) -> Self {
-        let mut child = child.lock().unwrap();
+    /// Connects a session to the language server's stdin, recording its messages if set up to
+    pub fn new(stdin: Arc<Mutex<ChildStdin>>, state: web::Data<AppState>) -> Self {
+        let session_id = next_session_id();
+        let span = session_span("language_server", session_id);
+        let recorder = state
+            .lsp_record_dir
+            .as_deref()
+            .map(|dir| LspRecorder::start(dir, session_id, &span));
         LangServer {
-            stdin: Arc::new(Mutex::new(child.stdin.take().unwrap())),
-            stdout: child.stdout.take(),
+            to_server: server_writer(stdin, recorder.clone(), span.clone()),
+            recorder,
+            span,
+            session_metrics: LspSessionMetrics::new(&state.metrics),
+            requests: RequestTracker::new(state.slow_request_threshold, session_id),
+            reinitialized: false,
+            state,
+        }
+    }
+
+    /// Handles the messages that start and end a session, as the server outlives sessions
+    fn lifecycle(&mut self, message: &mut Value) -> Lifecycle {
+        match message["method"].as_str() {
+            Some("initialize") => match self.state.ls_readiness.initialize_result() {
+                Some(mut result) => {
+                    debug!("Answering initialize from an earlier session");
+                    self.reinitialized = true;
+                    self.state.message_policy.trim_capabilities(&mut result);
+                    self.state
+                        .uri_mapping
+                        .rewrite(Direction::ServerToClient, &mut result);
+                    Lifecycle::Answer(initialize_response(&message["id"], &result))
+                }
+                None => {
+                    rewrite_initialize(
+                        message,
+                        &self.state.workspace_dir,
+                        self.state.initialization_options.as_ref(),
+                    );
+                    Lifecycle::Rewritten
+                }
+            },
+            Some("initialized") if self.reinitialized => Lifecycle::Drop,
+            Some("shutdown") => Lifecycle::Answer(
+                json!({"jsonrpc": "2.0", "id": message["id"], "result": null}).to_string(),
+            ),
+            Some("exit") => Lifecycle::Drop,
+            _ => Lifecycle::Forward,
+        }
+    }
+
+    /// Passes a client message on to the language server once the session's interceptors have seen it
+    fn client_message(
+        &mut self,
+        mut msg: Value,
+        text: String,
+        mut rewritten: bool,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        match self.lifecycle(&mut msg) {
+            Lifecycle::Forward => (),
+            Lifecycle::Rewritten => rewritten = true,
+            Lifecycle::Answer(response) => {
+                ctx.text(response);
+                return;
+            }
+            Lifecycle::Drop => return,
+        }
+        let span = message_span(&msg);
+        span.in_scope(|| {
+            debug!("Client message");
+            trace!(body = %text, "Client message body");
+        });
+
+        let state = self.state.clone();
+        let intercept = async move {
+            let intercepted = state
+                .interceptors
+                .intercept(Direction::ClientToServer, &msg)
+                .await;
+            (msg, intercepted)
+        };
+        // later messages wait, to reach the language server in order
+        ctx.wait(
+            actix::fut::wrap_future(intercept.instrument(span.clone())).map(
+                move |(mut msg, intercepted), server: &mut Self, ctx| {
+                    let _span = span.enter();
+                    match intercepted {
+                        Intercepted::Pass => (),
+                        Intercepted::Modify(modified) => {
+                            msg = modified;
+                            rewritten = true;
+                        }
+                        Intercepted::Swallow => return,
+                        Intercepted::Answer(mut answer) => {
+                            server
+                                .state
+                                .uri_mapping
+                                .rewrite(Direction::ServerToClient, &mut answer);
+                            ctx.text(answer.to_string());
+                            return;
+                        }
+                    }
+                    server.track(Direction::ClientToServer, &msg);
+                    rewritten |= server.requests.rewrite_client_ids(&mut msg);
+                    match server.state.proxy_requests.client_message(&msg) {
+                        Intercepted::Pass => (),
+                        Intercepted::Modify(modified) => {
+                            msg = modified;
+                            rewritten = true;
+                        }
+                        Intercepted::Swallow | Intercepted::Answer(_) => return,
+                    }
+                    let mut messages = vec![if rewritten { msg.to_string() } else { text }];
+                    if let Some(probe) = server.state.ls_readiness.client_message(&msg) {
+                        debug!("Probing language server");
+                        messages.push(probe);
+                    }
+                    server.send_to_server(messages);
+                },
+            ),
+        );
+    }
+
+    /// Pairs a message with the request it answers, and counts it in the metrics
+    fn track(&mut self, direction: Direction, message: &Value) -> Option<CompletedRequest> {
+        let completed = self.requests.message(direction, message);
+        self.session_metrics
+            .message(direction, message, completed.as_ref());
+        completed
+    }
+
+    /// Writes messages from the proxy itself to the language server
+    fn send_to_server(&self, messages: Vec<String>) {
+        if self.to_server.send(messages).is_err() {
+            error!("Language server writer has stopped");
         }
     }
+
+    /// Sends the language server's output to the client once it has been intercepted
+    fn send_line(
+        &mut self,
+        mut line: String,
+        messages: Vec<(ServerMessage, Intercepted)>,
+        ctx: &mut <Self as Actor>::Context,
+    ) {
+        let mut replacements = Vec::new();
+        for (message, intercepted) in messages {
+            let (mut value, mut rewritten) = match (message.message, intercepted) {
+                (Some(value), Intercepted::Pass) => (value, message.rewritten),
+                (Some(_), Intercepted::Modify(value)) => (value, true),
+                (_, Intercepted::Answer(answer)) => {
+                    // answered in the client's place, so the server's request is done with
+                    self.track(Direction::ClientToServer, &answer);
+                    self.send_to_server(vec![answer.to_string()]);
+                    replacements.push((message.span, String::new()));
+                    continue;
+                }
+                _ => {
+                    replacements.push((message.span, String::new()));
+                    continue;
+                }
+            };
+            rewritten |= self
+                .state
+                .uri_mapping
+                .rewrite(Direction::ServerToClient, &mut value);
+            if rewritten {
+                replacements.push((message.span, value.to_string()));
+            }
+        }
+        if !replacements.is_empty() {
+            for (span, replacement) in replacements.into_iter().rev() {
+                line.replace_range(span, &replacement);
+            }
+            if line.is_empty() {
+                return;
+            }
+        }
+        ctx.text(line)
+    }
 }
 
-impl StreamHandler<Result<Line, ws::ProtocolError>> for LangServer {
-    fn handle(&mut self, msg: Result<Line, ws::ProtocolError>, ctx: &mut Self::Context) {
-        if let Ok(line) = msg {
-            ctx.text(line.0)
+/// A message for the client from the proxy itself, such as build diagnostics
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ClientMessage(pub String);
+
+impl Handler<ClientMessage> for LangServer {
+    type Result = ();
+
+    fn handle(&mut self, ClientMessage(mut text): ClientMessage, ctx: &mut Self::Context) {
+        if let Ok(mut message) = serde_json::from_str::<Value>(&text) {
+            if self
+                .state
+                .uri_mapping
+                .rewrite(Direction::ServerToClient, &mut message)
+            {
+                text = message.to_string();
+            }
         }
+        ctx.text(text)
+    }
+}
+
+/// Asks the session for the requests that are waiting for a response, in either direction
+#[derive(Message)]
+#[rtype(result = "Vec<InFlightRequest>")]
+pub struct GetInFlightRequests;
+
+impl Handler<GetInFlightRequests> for LangServer {
+    type Result = MessageResult<GetInFlightRequests>;
+
+    fn handle(&mut self, _msg: GetInFlightRequests, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.requests.in_flight())
+    }
+}
+
+/// A message found in a line of the language server's output, on its way to the client
+struct ServerMessage {
+    span: Range<usize>,
+    /// Not set if the message isn't passed on
+    message: Option<Value>,
+    rewritten: bool,
+}
+
+impl Handler<Line> for LangServer {
+    type Result = ();
+
+    fn handle(&mut self, Line(line): Line, ctx: &mut Self::Context) {
+        let session = self.span.clone();
+        let _session = session.enter();
+        trace!(body = %line, "Server line");
+        let mut messages = Vec::new();
+        for (span, mut message) in json_message_spans(&line) {
+            let restored = self.requests.restore_client_id(&mut message);
+            if !restored {
+                trace!(id = %message["id"], "Dropping response to another session");
+                messages.push(ServerMessage {
+                    span,
+                    message: None,
+                    rewritten: false,
+                });
+                continue;
+            }
+            let mut rewritten = message.get("method").is_none();
+            rewritten |= match self
+                .state
+                .message_policy
+                .apply(Direction::ServerToClient, &mut message)
+            {
+                Verdict::Pass => false,
+                Verdict::Rewritten => true,
+                Verdict::Deny(response) => {
+                    self.send_to_server(response.into_iter().collect());
+                    messages.push(ServerMessage {
+                        span,
+                        message: None,
+                        rewritten: false,
+                    });
+                    continue;
+                }
+            };
+            message_span(&message).in_scope(|| debug!("Server message"));
+            let completed = self.track(Direction::ServerToClient, &message);
+            if completed.is_some_and(|request| request.method == "initialize") {
+                rewritten |= self
+                    .state
+                    .message_policy
+                    .trim_capabilities(&mut message["result"]);
+            }
+            messages.push(ServerMessage {
+                span,
+                message: Some(message),
+                rewritten,
+            });
+        }
+        if messages.is_empty() {
+            self.send_line(line, Vec::new(), ctx);
+            return;
+        }
+
+        let state = self.state.clone();
+        let intercept = async move {
+            let mut intercepted = Vec::new();
+            for message in messages {
+                let outcome = match &message.message {
+                    Some(value) => {
+                        state
+                            .interceptors
+                            .intercept(Direction::ServerToClient, value)
+                            .await
+                    }
+                    None => Intercepted::Swallow,
+                };
+                intercepted.push((message, outcome));
+            }
+            intercepted
+        };
+        // later lines wait, to reach the client in order
+        ctx.wait(
+            actix::fut::wrap_future(intercept.instrument(session.clone())).map(
+                |intercepted, server: &mut Self, ctx| server.send_line(line, intercepted, ctx),
+            ),
+        );
     }
 }
 
 impl Actor for LangServer {
     type Context = ws::WebsocketContext<Self>;
 
-    fn started(&mut self, ctx: &mut Self::Context) {
-        /* Send the bytes received from stdout to ctx */
-        let stdout = self.stdout.take().unwrap();
-        let reader = BufReader::new(stdout).lines();
-        ctx.add_stream(reader.map(|l| {
-            if let Ok(l) = l {
-                println!("{}", &l);
-                Ok(Line(l))
-            } else {
-                Ok(Line("Failed to read from lanuage server".to_string()))
-            }
-        }));
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        self.span
+            .in_scope(|| info!("Language server session started"));
+        *self
+            .state
+            .ls_recorder
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = self.recorder.clone();
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        /* Make way for the next session */
+        *self
+            .state
+            .ls_client
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+        *self
+            .state
+            .ls_recorder
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+        self.state
+            .ws_session_started
+            .store(false, Ordering::Relaxed);
+
+        /* The server would otherwise carry on working on requests nobody is waiting for, or wait on answers that won't come */
+        let mut ended = self.requests.end_requests();
+        let requests = ended.len();
+        /* The next session's client opens the documents it wants afresh */
+        ended.extend(self.state.proxy_requests.end_session());
+        if ended.is_empty() {
+            return;
+        }
+        self.span.in_scope(|| {
+            debug!(
+                requests,
+                documents = ended.len() - requests,
+                "Ending requests and closing documents of closed session"
+            )
+        });
+        self.send_to_server(ended);
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for LangServer {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         if let Ok(ws::Message::Text(text)) = msg {
-            let stdin = self.stdin.clone();
-
-            let msg = serde_json::from_str::<Value>(&text);
-
-            // debug print client messages
-            println!("\nStartClient\n{}\nEndClient\n", &text);
-
-            let intercept_future = async move {
-                if let Ok(msg) = msg {
-                    if let Err(err) = intercept_notification(msg).await {
-                        println!("err: {}", err);
-                    };
+            let mut msg = match serde_json::from_str::<Value>(&text) {
+                Ok(msg) => msg,
+                Err(err) => {
+                    let span = debug_span!(parent: &self.span, "lsp_message");
+                    span.in_scope(|| warn!(error = %err, "Client message is not JSON"));
+                    self.send_to_server(vec![text]);
+                    return;
                 }
             };
-            let lang_server_future = async move {
-                let mut stdin = stdin.lock().await;
-                let text = wrap_lsp_message(&text);
-                if let Err(er) = stdin.write_all(&text.as_bytes()).await {
-                    eprintln!("Error writing to language server! {:?}", er);
+
+            let mut rewritten = match self
+                .state
+                .message_policy
+                .apply(Direction::ClientToServer, &mut msg)
+            {
+                Verdict::Pass => false,
+                Verdict::Rewritten => true,
+                Verdict::Deny(response) => {
+                    if let Some(response) = response {
+                        ctx.text(response);
+                    }
+                    return;
                 }
-                stdin.flush();
             };
+            rewritten |= self
+                .state
+                .uri_mapping
+                .rewrite(Direction::ClientToServer, &mut msg);
+            if msg["method"] == "initialize" {
+                // a REST request may be initialising the server, in which case the client is answered from its result
+                let state = self.state.clone();
+                let initialized = async move { state.proxy_requests.initialized().await };
+                ctx.wait(actix::fut::wrap_future(initialized).map(
+                    move |(), server: &mut Self, ctx| {
+                        server.client_message(msg, text, rewritten, ctx)
+                    },
+                ));
+                return;
+            }
+            self.client_message(msg, text, rewritten, ctx);
+        }
+    }
+}
+
+/// Writes a session's messages to the language server in order on a task of its own
+fn server_writer(
+    stdin: Arc<Mutex<ChildStdin>>,
+    recorder: Option<LspRecorder>,
+    span: Span,
+) -> UnboundedSender<Vec<String>> {
+    let (writer, mut batches) = mpsc::unbounded_channel::<Vec<String>>();
+    let write = async move {
+        while let Some(messages) = batches.recv().await {
+            let mut stdin = stdin.lock().await;
+            for message in messages {
+                if let Some(recorder) = &recorder {
+                    recorder.record(Direction::ClientToServer, &message);
+                }
+                let message = wrap_lsp_message(&message);
+                if let Err(er) = stdin.write_all(message.as_bytes()).await {
+                    error!(error = %er, "Error writing to language server");
+                    break;
+                }
+            }
+        }
+    };
+    actix::spawn(write.instrument(span));
+    writer
+}
+
+/// Passes the language server's messages to the connected session for as long as the server runs
+pub fn forward_output(stdout: ChildStdout, state: web::Data<AppState>) {
+    actix::spawn(async move {
+        let mut messages = lsp_messages(stdout);
+        while let Some(message) = messages.recv().await {
+            state.ls_readiness.output();
+            record(&state, Direction::ServerToClient, &message);
+            if for_proxy(&message, &state) {
+                continue;
+            }
+            let client = state
+                .ls_client
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            match client {
+                Some(client) => client.do_send(Line(message)),
+                None => answer_without_client(state.clone(), &message),
+            }
+        }
+        warn!("Language server closed its output");
+        state.ls_readiness.closed();
+        state.proxy_requests.closed();
+    });
+}
+
+/// Runs a language server message through the interceptors while no session is connected, refusing requests they don't answer
+fn answer_without_client(state: web::Data<AppState>, message: &str) {
+    let message = match serde_json::from_str::<Value>(message) {
+        Ok(message) if message.get("method").is_some() => message,
+        _ => return,
+    };
+    actix::spawn(async move {
+        let answer = match state
+            .interceptors
+            .intercept(Direction::ServerToClient, &message)
+            .await
+        {
+            Intercepted::Answer(answer) => answer,
+            _ => match message.get("id") {
+                Some(id) => {
+                    debug!(%id, "Refusing request while no session is connected");
+                    cancelled_response(id)
+                }
+                None => return,
+            },
+        };
+        send_from_proxy(&state, &answer.to_string()).await;
+    });
+}
+
+/// Writes a message from the proxy itself to the language server, outside of any session
+pub async fn send_from_proxy(state: &AppState, message: &str) {
+    let stdin = state
+        .ls_stdin
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if let Some(stdin) = stdin {
+        let mut stdin = stdin.lock().await;
+        record(state, Direction::ClientToServer, message);
+        if let Err(err) = stdin.write_all(wrap_lsp_message(message).as_bytes()).await {
+            warn!(error = %err, "Error writing to language server");
+        }
+    }
+}
+
+/// Records a message in the connected session's recording, if there is one
+pub fn record(state: &AppState, direction: Direction, message: &str) {
+    let recorder = state
+        .ls_recorder
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(recorder) = recorder.as_ref() {
+        recorder.record(direction, message);
+    }
+}
 
-            let lang_server_fut = actix::fut::wrap_future(lang_server_future);
-            let intercept_fut = actix::fut::wrap_future(intercept_future);
-            ctx.spawn(intercept_fut);
-            ctx.spawn(lang_server_fut);
+/// Whether a message from the language server answers one of the proxy's own requests
+fn for_proxy(message: &str, state: &AppState) -> bool {
+    match serde_json::from_str::<Value>(message) {
+        Ok(message) => {
+            state.ls_readiness.server_message(&message)
+                || state.proxy_requests.server_message(&message)
         }
+        Err(_) => false,
     }
 }
 
-fn wrap_lsp_message(msg: &str) -> String {
+/// Span for an LSP message within the current session, carrying the message's request id if it has one
+fn message_span(message: &Value) -> Span {
+    let span = debug_span!(
+        "lsp_message",
+        id = field::Empty,
+        method = message["method"].as_str().unwrap_or_default()
+    );
+    if let Some(id) = message.get("id") {
+        span.record("id", &field::display(id));
+    }
+    span
+}
+
+/// Finds the JSON messages in text, skipping any `Content-Length` headers
+pub fn json_messages(text: &str) -> Vec<Value> {
+    json_message_spans(text)
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect()
+}
+
+/// Finds the JSON messages in text along with where each one is in the text
+fn json_message_spans(text: &str) -> Vec<(Range<usize>, Value)> {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find('{').map(|start| offset + start) {
+        let mut stream = Deserializer::from_str(&text[start..]).into_iter::<Value>();
+        match stream.next() {
+            Some(Ok(message)) => {
+                offset = start + stream.byte_offset();
+                messages.push((start..offset, message));
+            }
+            _ => offset = start + 1,
+        }
+    }
+    messages
+}
+
+/// Adds the `Content-Length` header that LSP and DAP messages are framed with
+pub fn wrap_lsp_message(msg: &str) -> String {
     format!("Content-Length: {}\r\n\r\n{}", msg.len(), msg)
 }
 
+/// Reads the body of the next `Content-Length` framed message, or `None` once the reader is closed
+pub async fn read_lsp_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> std::io::Result<Option<String>> {
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            // headers end with an empty line, stray blank lines before them are skipped
+            if content_length.is_some() {
+                break;
+            }
+        } else if let Some(length) = header.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse().ok();
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or_default()];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(String::from_utf8_lossy(&body).to_string()))
+}
+
+/// Reads messages framed with `Content-Length` headers on a task of their own, until the reader closes
+pub fn lsp_messages<R: AsyncRead + Unpin + 'static>(reader: R) -> UnboundedReceiver<String> {
+    let (messages, messages_rx) = mpsc::unbounded_channel();
+    actix::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        while let Ok(Some(message)) = read_lsp_message(&mut reader).await {
+            if messages.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    messages_rx
+}
+
 #[cfg(test)]
 mod tests {
-    use super::wrap_lsp_message;
+    use super::{json_messages, read_lsp_message, wrap_lsp_message};
+    use serde_json::json;
 
     #[test]
     fn content_length_wrap_is_correct() {
@@ -101,4 +665,32 @@ mod tests {
         let expected = format!("Content-Length: {}", message.len());
         assert!(actual.contains(&expected))
     }
+
+    #[tokio::test]
+    async fn framed_messages_are_read_in_order() {
+        let stream = format!(
+            "{}\r\n{}",
+            wrap_lsp_message(r#"{"seq":1,"body":"héllo"}"#),
+            wrap_lsp_message(r#"{"seq":2}"#)
+        );
+        let mut reader = stream.as_bytes();
+
+        let first = read_lsp_message(&mut reader).await.unwrap();
+        let second = read_lsp_message(&mut reader).await.unwrap();
+
+        assert_eq!(first.as_deref(), Some(r#"{"seq":1,"body":"héllo"}"#));
+        assert_eq!(second.as_deref(), Some(r#"{"seq":2}"#));
+        assert_eq!(read_lsp_message(&mut reader).await.unwrap(), None);
+    }
+
+    #[test]
+    fn messages_are_found_among_headers() {
+        let text = "{\"id\":1,\"result\":{\"a\":\"}\"}}Content-Length: 20\r";
+
+        assert_eq!(
+            json_messages(text),
+            vec![json!({"id": 1, "result": {"a": "}"}})]
+        );
+        assert!(json_messages("Content-Length: 20\r").is_empty());
+    }
 }
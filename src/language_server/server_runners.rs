@@ -6,7 +6,7 @@ use tokio::process::{Child, Command};
 /// Runs a language server child process, according to the language passed into the function
 pub fn start_lang_server(lang: Lang, temp_files_path: &Path) -> Option<Child> {
     match lang {
-        Lang::Java => java_server(&temp_files_path),
+        Lang::Java => java_server(temp_files_path),
         Lang::C => None,
         Lang::Custom => custom_config_server(),
     }
@@ -0,0 +1,222 @@
+use derive_more::{Display, Error};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+use super::recorder::Direction;
+
+/// Fields of LSP messages that hold a URI, wherever they are in a message
+const URI_FIELDS: [&str; 8] = [
+    "uri",
+    "rootUri",
+    "targetUri",
+    "target",
+    "scopeUri",
+    "baseUri",
+    "oldUri",
+    "newUri",
+];
+
+/// A URI prefix the client uses, and the one the language server sees in its place
+#[derive(Debug, Clone, PartialEq)]
+pub struct UriPrefix {
+    pub client: String,
+    pub server: String,
+}
+
+#[derive(Debug, Display, Error)]
+#[display(
+    fmt = "URI mappings are given as <client prefix>=<server prefix>, not {}",
+    mapping
+)]
+pub struct InvalidUriPrefix {
+    mapping: String,
+}
+
+impl FromStr for UriPrefix {
+    type Err = InvalidUriPrefix;
+
+    fn from_str(mapping: &str) -> Result<Self, Self::Err> {
+        match mapping.split_once('=') {
+            Some((client, server)) if !client.is_empty() && !server.is_empty() => Ok(UriPrefix {
+                client: client.to_string(),
+                server: server.to_string(),
+            }),
+            _ => Err(InvalidUriPrefix {
+                mapping: mapping.to_string(),
+            }),
+        }
+    }
+}
+
+/// Rewrites URI prefixes in LSP messages between the client's and the language server's
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UriMapping {
+    prefixes: Vec<UriPrefix>,
+}
+
+impl UriMapping {
+    pub fn new(prefixes: Vec<UriPrefix>) -> Self {
+        UriMapping { prefixes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// Rewrites the URIs in a message going in the given direction, returning whether any were changed
+    pub fn rewrite(&self, direction: Direction, message: &mut Value) -> bool {
+        !self.is_empty() && self.rewrite_value(direction, message)
+    }
+
+    /// Rewrites a single URI going in the given direction, if it starts with a mapped prefix
+    pub fn map_uri(&self, direction: Direction, uri: &str) -> Option<String> {
+        self.prefixes.iter().find_map(|prefix| {
+            let (from, to) = match direction {
+                Direction::ClientToServer => (&prefix.client, &prefix.server),
+                Direction::ServerToClient => (&prefix.server, &prefix.client),
+            };
+            uri.strip_prefix(from.as_str())
+                .map(|rest| format!("{}{}", to, rest))
+        })
+    }
+
+    fn rewrite_value(&self, direction: Direction, value: &mut Value) -> bool {
+        match value {
+            Value::Object(object) => {
+                let mut changed = false;
+                for (key, value) in object.iter_mut() {
+                    changed |= match value {
+                        Value::String(uri) if URI_FIELDS.contains(&key.as_str()) => {
+                            match self.map_uri(direction, uri) {
+                                Some(mapped) => {
+                                    *uri = mapped;
+                                    true
+                                }
+                                None => false,
+                            }
+                        }
+                        value => self.rewrite_value(direction, value),
+                    };
+                }
+                if let Some(Value::Object(changes)) = object.get_mut("changes") {
+                    changed |= self.rewrite_keys(direction, changes);
+                }
+                changed
+            }
+            Value::Array(values) => {
+                let mut changed = false;
+                for value in values {
+                    changed |= self.rewrite_value(direction, value);
+                }
+                changed
+            }
+            _ => false,
+        }
+    }
+
+    /// Rewrites the URIs a `WorkspaceEdit`'s `changes` are keyed by
+    fn rewrite_keys(&self, direction: Direction, changes: &mut Map<String, Value>) -> bool {
+        let mut changed = false;
+        *changes = std::mem::take(changes)
+            .into_iter()
+            .map(|(uri, edits)| match self.map_uri(direction, &uri) {
+                Some(mapped) => {
+                    changed = true;
+                    (mapped, edits)
+                }
+                None => (uri, edits),
+            })
+            .collect();
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UriMapping, UriPrefix};
+    use crate::language_server::recorder::Direction;
+    use serde_json::json;
+
+    fn mapping() -> UriMapping {
+        UriMapping::new(vec!["inmemory://model/=file:///srv/ws/".parse().unwrap()])
+    }
+
+    #[test]
+    fn uris_are_mapped_both_ways() {
+        let mapping = mapping();
+        let mut request = json!({
+            "id": 1,
+            "method": "textDocument/definition",
+            "params": {
+                "textDocument": {"uri": "inmemory://model/src/Hello.java"},
+                "position": {"line": 1, "character": 2},
+            },
+        });
+        let mut response = json!({
+            "id": 1,
+            "result": [
+                {"targetUri": "file:///srv/ws/src/Terro.java", "targetRange": {}},
+                {"uri": "file:///usr/lib/jvm/String.java", "range": {}},
+            ],
+        });
+
+        assert!(mapping.rewrite(Direction::ClientToServer, &mut request));
+        assert!(mapping.rewrite(Direction::ServerToClient, &mut response));
+
+        assert_eq!(
+            request["params"]["textDocument"]["uri"],
+            "file:///srv/ws/src/Hello.java"
+        );
+        assert_eq!(
+            response["result"],
+            json!([
+                {"targetUri": "inmemory://model/src/Terro.java", "targetRange": {}},
+                {"uri": "file:///usr/lib/jvm/String.java", "range": {}},
+            ])
+        );
+    }
+
+    #[test]
+    fn workspace_edits_and_document_links_are_mapped() {
+        let mapping = mapping();
+        let mut apply_edit = json!({
+            "id": 2,
+            "method": "workspace/applyEdit",
+            "params": {"edit": {
+                "changes": {"file:///srv/ws/src/Hello.java": [{"range": {}, "newText": "x"}]},
+                "documentChanges": [
+                    {"kind": "rename", "oldUri": "file:///srv/ws/A.java", "newUri": "file:///srv/ws/B.java"},
+                ],
+            }},
+        });
+        let mut links =
+            json!({"id": 3, "result": [{"range": {}, "target": "file:///srv/ws/README.md"}]});
+
+        mapping.rewrite(Direction::ServerToClient, &mut apply_edit);
+        mapping.rewrite(Direction::ServerToClient, &mut links);
+
+        assert_eq!(
+            apply_edit["params"]["edit"],
+            json!({
+                "changes": {"inmemory://model/src/Hello.java": [{"range": {}, "newText": "x"}]},
+                "documentChanges": [
+                    {"kind": "rename", "oldUri": "inmemory://model/A.java", "newUri": "inmemory://model/B.java"},
+                ],
+            })
+        );
+        assert_eq!(links["result"][0]["target"], "inmemory://model/README.md");
+    }
+
+    #[test]
+    fn mappings_need_both_prefixes() {
+        assert!("inmemory://model/".parse::<UriPrefix>().is_err());
+        assert!("=file:///srv/ws/".parse::<UriPrefix>().is_err());
+        assert_eq!(
+            "a://b/=file:///c=d/".parse::<UriPrefix>().unwrap(),
+            UriPrefix {
+                client: "a://b/".to_string(),
+                server: "file:///c=d/".to_string(),
+            }
+        );
+    }
+}
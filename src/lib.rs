@@ -1,30 +1,186 @@
 use crate::config::LsArgs;
+use actix::Addr;
 use actix_web::{dev::Server, middleware::Logger, web::Data};
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use file_system::file_sync::{get_dir, get_file, get_root_uri};
-use language_server::to_language_server;
-use program::{code_runner::run_program_file, user_program::UserProgram};
+use debug_adapter::to_debug_adapter;
+use file_system::{
+    file_sync::{get_dir, get_file, get_root_uri},
+    sync_queue::FileSyncQueue,
+};
+use language_server::{
+    client_requests::{read_settings, ClientRequests},
+    in_flight_requests,
+    initialize::read_initialization_options,
+    intercept::FileSync,
+    interceptor::InterceptorChain,
+    policy::MessagePolicy,
+    readiness::{readiness_check, Readiness},
+    recorder::LspRecorder,
+    rest::{self, ProxyRequests},
+    server::LangServer,
+    to_language_server,
+    uri_mapping::UriMapping,
+};
+use metrics::{get_metrics, Metrics};
+use program::{
+    code_runner::{
+        build_program, exec_program, list_run_configurations, list_runs, observe_run,
+        program_status, run_history, run_program_file, run_status, run_tests, run_transcript,
+        save_run_configuration, stop_program, stop_run,
+    },
+    program_handle::RunLimits,
+    run_history::RunHistory,
+    run_registry::RunRegistry,
+    run_template::RunTemplate,
+    runners::shell_command,
+};
+use serde_json::{json, Value};
 use std::{
     net::TcpListener,
-    sync::{atomic::AtomicBool, Arc},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::Duration,
 };
-use structopt::StructOpt;
-use tokio::{process::Child, sync::Mutex};
+use tokio::process::{Child, ChildStdin};
 
 pub mod config;
+pub mod debug_adapter;
 pub mod file_system;
 pub mod language_server;
+pub mod logging;
+pub mod metrics;
 pub mod program;
-/// newtype for lines from websocket
-#[derive(Debug)]
+/// newtype for the language server's output, a message at a time
+#[derive(Debug, actix::Message)]
+#[rtype(result = "()")]
 struct Line(String);
 /// struct containing global state for the application
 pub struct AppState {
     pub ws_session_started: AtomicBool,
+    /// The language server websocket, for sending the client messages from the proxy itself
+    pub ls_client: Mutex<Option<Addr<LangServer>>>,
+    /// The language server's stdin, shared by every session
+    pub ls_stdin: Mutex<Option<Arc<tokio::sync::Mutex<ChildStdin>>>>,
+    /// Recording of the connected language server session
+    pub ls_recorder: Mutex<Option<LspRecorder>>,
+    /// What the proxy has seen of the language server, for the readiness endpoint
+    pub ls_readiness: Arc<Readiness>,
     pub lang: config::Lang,
     pub workspace_dir: String,
-    pub program_input: Mutex<Vec<String>>,
-    pub user_program: Arc<Mutex<Option<UserProgram>>>,
+    /// Directory that programs are compiled into, kept out of the workspace
+    pub build_dir: PathBuf,
+    /// Local Maven repository or Gradle user home for offline builds
+    pub build_repo_path: Option<PathBuf>,
+    /// JUnit console launcher used to run Java tests outside of build tool projects
+    pub junit_path: Option<PathBuf>,
+    /// Command that runs the tests for languages without a built in test runner
+    pub test_cmd: Option<String>,
+    /// Command that starts a debug adapter for each debug session
+    pub debug_adapter_cmd: Option<String>,
+    /// Build and run steps for languages without a built in runner
+    pub run_template: Option<RunTemplate>,
+    /// Directory that language server sessions are recorded in, if they are recorded
+    pub lsp_record_dir: Option<PathBuf>,
+    /// Merged into the client's `initializationOptions` when initialising the language server
+    pub initialization_options: Option<Value>,
+    /// Which LSP messages are passed on
+    pub message_policy: MessagePolicy,
+    /// Hooks into the language server's messages, such as file sync
+    pub interceptors: InterceptorChain,
+    /// Rewrites URIs between the client's view of the workspace and the language server's
+    pub uri_mapping: UriMapping,
+    /// How long a language server request can take to be answered before it is logged as slow
+    pub slow_request_threshold: Duration,
+    /// Requests the proxy makes of the language server itself, for the REST endpoints
+    pub proxy_requests: ProxyRequests,
+    /// How long the language server has to answer the proxy's own requests
+    pub lsp_request_timeout: Duration,
+    /// Limits applied to every program run
+    pub run_limits: RunLimits,
+    /// Programs started from the workspace
+    pub runs: RunRegistry,
+    /// Held while run configurations are saved, so concurrent saves don't drop each other's changes
+    pub run_configurations_lock: tokio::sync::Mutex<()>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl AppState {
+    /// Builds the proxy's state from its configuration, with programs compiled into `build_dir`
+    pub fn new(args: LsArgs, build_dir: PathBuf) -> std::io::Result<Self> {
+        let run_template =
+            RunTemplate::for_lang(&args.language, args.build_template, args.run_template);
+        let metrics = Arc::new(Metrics::new());
+        let file_sync = Arc::new(FileSyncQueue::new(metrics.clone()));
+        let mut interceptors = InterceptorChain::new().with(FileSync::new(file_sync.clone()));
+        if args.answer_server_requests {
+            let settings = match args.client_settings {
+                Some(path) => read_settings(Path::new(&path))
+                    .map_err(|err| config_error("client settings", &path, err))?,
+                None => json!({}),
+            };
+            interceptors = interceptors.with(ClientRequests::new(settings, file_sync));
+        }
+        let data_dir = match args.data_dir {
+            Some(data_dir) => PathBuf::from(data_dir),
+            None => Path::new(&args.codebase_path).join(".lsp-proxy"),
+        };
+        Ok(AppState {
+            ws_session_started: AtomicBool::from(false),
+            ls_client: Mutex::new(None),
+            ls_stdin: Mutex::new(None),
+            ls_recorder: Mutex::new(None),
+            ls_readiness: Arc::new(Readiness::default()),
+            lang: args.language,
+            workspace_dir: args.codebase_path,
+            build_dir,
+            build_repo_path: args.build_repo_path.map(PathBuf::from),
+            junit_path: args.junit_path.map(PathBuf::from),
+            test_cmd: args.test_cmd,
+            debug_adapter_cmd: args.debug_adapter_cmd,
+            run_template,
+            lsp_record_dir: args.record_lsp_dir.map(PathBuf::from),
+            initialization_options: args
+                .initialization_options
+                .map(|path| {
+                    read_initialization_options(Path::new(&path))
+                        .map_err(|err| config_error("initialization options", &path, err))
+                })
+                .transpose()?,
+            message_policy: args
+                .message_policy
+                .map(|path| {
+                    MessagePolicy::read(Path::new(&path))
+                        .map_err(|err| config_error("message policy", &path, err))
+                })
+                .transpose()?
+                .unwrap_or_default(),
+            uri_mapping: UriMapping::new(args.uri_map),
+            interceptors,
+            slow_request_threshold: Duration::from_millis(args.slow_request_ms),
+            proxy_requests: ProxyRequests::default(),
+            lsp_request_timeout: Duration::from_millis(args.lsp_request_timeout_ms),
+            run_limits: RunLimits {
+                timeout: args.run_timeout.map(Duration::from_secs),
+                max_output: args.max_output_bytes,
+            },
+            runs: RunRegistry::new(
+                args.max_runs,
+                RunHistory::new(&data_dir, args.max_stored_runs),
+                metrics.clone(),
+            ),
+            run_configurations_lock: tokio::sync::Mutex::new(()),
+            metrics,
+        })
+    }
+}
+
+/// Error for a configuration file that couldn't be read, naming the file
+fn config_error(what: &str, path: &str, err: std::io::Error) -> std::io::Error {
+    std::io::Error::new(
+        err.kind(),
+        format!("Couldn't read {} from {}: {}", what, path, err),
+    )
 }
 
 /// Starts server
@@ -33,21 +189,17 @@ pub fn run(
     child: Arc<std::sync::Mutex<Child>>,
     state: Data<AppState>,
 ) -> Result<Server, std::io::Error> {
-    println!("Program config: {:?}", LsArgs::from_args());
-    std::env::set_var("RUST_LOG", "actix_web=info");
-    env_logger::init();
     let server = HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(state.clone())
-            .service(
-                web::scope("/code")
-                    .route("/file/{filename:.*}", web::get().to(get_file))
-                    .route("/directory", web::get().to(get_dir))
-                    .route("/directory/root", web::get().to(get_root_uri))
-                    .route("/run/{filename:.*}", web::get().to(run_program_file)),
-            )
+            .service(code_scope())
+            .route("/debug", web::get().to(to_debug_adapter))
             .route("/health", web::get().to(health_check))
+            .route("/ready", web::get().to(readiness_check))
+            .route("/admin/requests", web::get().to(in_flight_requests))
+            .route("/metrics", web::get().to(get_metrics))
+            .service(lsp_scope())
             .data(child.clone())
             .route("/ls", web::route().to(to_language_server))
     })
@@ -60,22 +212,76 @@ async fn health_check() -> impl Responder {
     HttpResponse::Ok()
 }
 
-/// Run the server for tests
-pub fn test_run(listener: TcpListener) -> Result<Server, std::io::Error> {
-    std::env::set_var("RUST_LOG", "actix_web=info");
-    let server = HttpServer::new(|| {
+/// Run the server for tests, serving the language server if a custom command is given
+pub fn test_run(listener: TcpListener, args: LsArgs) -> Result<Server, std::io::Error> {
+    let child = args
+        .custom_lang_server_cmd
+        .as_deref()
+        .map(|cmd| {
+            shell_command(cmd)
+                .current_dir(&args.codebase_path)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+        })
+        .transpose()?
+        .map(|child| Arc::new(std::sync::Mutex::new(child)));
+    let state = Data::new(AppState::new(args, tempfile::tempdir()?.into_path())?);
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
-            .service(
-                web::scope("/code")
-                    .route("/file/{filename:.*}", web::get().to(get_file))
-                    .route("/directory", web::get().to(get_dir))
-                    .route("/directory/root", web::get().to(get_root_uri))
-                    .route("/run/{filename:.*}", web::get().to(run_program_file)),
-            )
+            .app_data(state.clone())
+            .service(code_scope())
+            .route("/debug", web::get().to(to_debug_adapter))
             .route("/health", web::get().to(health_check))
+            .route("/ready", web::get().to(readiness_check))
+            .route("/admin/requests", web::get().to(in_flight_requests))
+            .route("/metrics", web::get().to(get_metrics))
+            .configure(|config| {
+                if let Some(child) = &child {
+                    config
+                        .data(child.clone())
+                        .route("/ls", web::route().to(to_language_server))
+                        .service(lsp_scope());
+                }
+            })
     })
     .listen(listener)?
     .run();
     Ok(server)
 }
+
+/// Endpoints for the codebase and the programs run from it
+fn code_scope() -> actix_web::Scope {
+    web::scope("/code")
+        .route("/file/{filename:.*}", web::get().to(get_file))
+        .route("/directory", web::get().to(get_dir))
+        .route("/directory/root", web::get().to(get_root_uri))
+        .route("/run/status", web::get().to(program_status))
+        .route("/run/stop", web::post().to(stop_program))
+        .route("/run/{filename:.*}", web::get().to(run_program_file))
+        .route("/runs", web::get().to(list_runs))
+        .route("/runs/history", web::get().to(run_history))
+        .route("/runs/{id}", web::get().to(run_status))
+        .route("/runs/{id}/transcript", web::get().to(run_transcript))
+        .route("/runs/{id}/stop", web::post().to(stop_run))
+        .route("/runs/{id}/observe", web::get().to(observe_run))
+        .route("/exec", web::post().to(exec_program))
+        .route("/build/{filename:.*}", web::post().to(build_program))
+        .route("/test/{filename:.*}", web::get().to(run_tests))
+        .service(
+            web::resource("/run-configurations/{filename:.*}")
+                .route(web::get().to(list_run_configurations))
+                .route(web::post().to(save_run_configuration)),
+        )
+}
+
+/// REST endpoints for asking the language server about the workspace
+fn lsp_scope() -> actix_web::Scope {
+    web::scope("/lsp")
+        .route("/hover", web::get().to(rest::hover))
+        .route("/definition", web::get().to(rest::definition))
+        .route("/references", web::get().to(rest::references))
+        .route("/symbols", web::get().to(rest::symbols))
+}
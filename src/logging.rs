@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{info_span, Span};
+use tracing_subscriber::EnvFilter;
+
+/// Level logged at when neither `--log-level` nor `RUST_LOG` is given
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Sets up logging, with `level` taking the same directives as `RUST_LOG`
+pub fn init(level: Option<&str>, json: bool) {
+    let filter = match level {
+        Some(level) => EnvFilter::new(level),
+        None => {
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_LEVEL))
+        }
+    };
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    let initialised = if json {
+        subscriber.json().try_init()
+    } else {
+        subscriber.try_init()
+    };
+    if let Err(err) = initialised {
+        eprintln!("Error setting up logging! {}", err);
+    }
+}
+
+/// Id for a websocket session, unique while the proxy is running
+pub fn next_session_id() -> u64 {
+    NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Span for a websocket session, that everything logged for the session is grouped under
+pub fn session_span(kind: &'static str, id: u64) -> Span {
+    info_span!("session", id, kind)
+}
@@ -0,0 +1,379 @@
+use actix_web::{web, HttpResponse};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use serde_json::Value;
+use std::{
+    fmt,
+    sync::{Arc, PoisonError},
+};
+use tokio::process::Child;
+
+use crate::{
+    file_system::file_sync_command::FileSyncError,
+    language_server::{recorder::Direction, request_tracker::CompletedRequest},
+    program::program_handle::ProgramState,
+    AppState,
+};
+
+/// Label for LSP responses to requests the proxy didn't see being sent
+const UNKNOWN_METHOD: &str = "response";
+
+/// Label for LSP methods outside of the specification
+const OTHER_METHOD: &str = "other";
+
+/// Methods of the LSP specification, which are the only ones given a label of their own
+const LSP_METHODS: &[&str] = &[
+    "$/cancelRequest",
+    "$/logTrace",
+    "$/progress",
+    "$/setTrace",
+    "callHierarchy/incomingCalls",
+    "callHierarchy/outgoingCalls",
+    "client/registerCapability",
+    "client/unregisterCapability",
+    "codeAction/resolve",
+    "codeLens/resolve",
+    "completionItem/resolve",
+    "documentLink/resolve",
+    "exit",
+    "initialize",
+    "initialized",
+    "shutdown",
+    "telemetry/event",
+    "textDocument/codeAction",
+    "textDocument/codeLens",
+    "textDocument/colorPresentation",
+    "textDocument/completion",
+    "textDocument/declaration",
+    "textDocument/definition",
+    "textDocument/didChange",
+    "textDocument/didClose",
+    "textDocument/didOpen",
+    "textDocument/didSave",
+    "textDocument/documentColor",
+    "textDocument/documentHighlight",
+    "textDocument/documentLink",
+    "textDocument/documentSymbol",
+    "textDocument/foldingRange",
+    "textDocument/formatting",
+    "textDocument/hover",
+    "textDocument/implementation",
+    "textDocument/linkedEditingRange",
+    "textDocument/moniker",
+    "textDocument/onTypeFormatting",
+    "textDocument/prepareCallHierarchy",
+    "textDocument/prepareRename",
+    "textDocument/publishDiagnostics",
+    "textDocument/rangeFormatting",
+    "textDocument/references",
+    "textDocument/rename",
+    "textDocument/selectionRange",
+    "textDocument/semanticTokens/full",
+    "textDocument/semanticTokens/full/delta",
+    "textDocument/semanticTokens/range",
+    "textDocument/signatureHelp",
+    "textDocument/typeDefinition",
+    "textDocument/willSave",
+    "textDocument/willSaveWaitUntil",
+    "window/logMessage",
+    "window/showDocument",
+    "window/showMessage",
+    "window/showMessageRequest",
+    "window/workDoneProgress/cancel",
+    "window/workDoneProgress/create",
+    "workspace/applyEdit",
+    "workspace/codeLens/refresh",
+    "workspace/configuration",
+    "workspace/didChangeConfiguration",
+    "workspace/didChangeWatchedFiles",
+    "workspace/didChangeWorkspaceFolders",
+    "workspace/didCreateFiles",
+    "workspace/didDeleteFiles",
+    "workspace/didRenameFiles",
+    "workspace/executeCommand",
+    "workspace/semanticTokens/refresh",
+    "workspace/symbol",
+    "workspace/willCreateFiles",
+    "workspace/willDeleteFiles",
+    "workspace/willRenameFiles",
+    "workspace/workspaceFolders",
+];
+
+/// Prometheus metrics for the proxy and its language server, kept for as long as the proxy is running
+pub struct Metrics {
+    registry: Registry,
+    sessions: IntGaugeVec,
+    lsp_messages: IntCounterVec,
+    lsp_request_duration: HistogramVec,
+    file_sync_errors: IntCounterVec,
+    program_runs: IntCounterVec,
+    language_server_memory: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let sessions = IntGaugeVec::new(
+            Opts::new("lsp_proxy_websocket_sessions", "Open websocket sessions"),
+            &["kind"],
+        )
+        .unwrap();
+        let lsp_messages = IntCounterVec::new(
+            Opts::new(
+                "lsp_proxy_lsp_messages_total",
+                "LSP messages passed through the proxy",
+            ),
+            &["direction", "method"],
+        )
+        .unwrap();
+        let lsp_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "lsp_proxy_lsp_request_duration_seconds",
+                "Time from an LSP request passing through the proxy until its response does",
+            ),
+            &["direction", "method"],
+        )
+        .unwrap();
+        let file_sync_errors = IntCounterVec::new(
+            Opts::new(
+                "lsp_proxy_file_sync_errors_total",
+                "Errors syncing files from intercepted LSP messages",
+            ),
+            &["error"],
+        )
+        .unwrap();
+        let program_runs = IntCounterVec::new(
+            Opts::new("lsp_proxy_program_runs_total", "Finished program runs"),
+            &["outcome"],
+        )
+        .unwrap();
+        let language_server_memory = IntGauge::new(
+            "lsp_proxy_language_server_resident_memory_bytes",
+            "Resident memory of the language server process",
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        for collector in [
+            Box::new(sessions.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(lsp_messages.clone()),
+            Box::new(lsp_request_duration.clone()),
+            Box::new(file_sync_errors.clone()),
+            Box::new(program_runs.clone()),
+            Box::new(language_server_memory.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metrics are only registered once");
+        }
+        Metrics {
+            registry,
+            sessions,
+            lsp_messages,
+            lsp_request_duration,
+            file_sync_errors,
+            program_runs,
+            language_server_memory,
+        }
+    }
+
+    /// Counts a websocket session as open until the returned guard is dropped
+    pub fn session(self: &Arc<Self>, kind: &'static str) -> ActiveSession {
+        self.sessions.with_label_values(&[kind]).inc();
+        ActiveSession {
+            metrics: self.clone(),
+            kind,
+        }
+    }
+
+    pub fn file_sync_error(&self, err: &FileSyncError) {
+        let error = match err {
+            FileSyncError::InternalError { .. } => "internal_error",
+            FileSyncError::BadClientData { .. } => "bad_client_data",
+            FileSyncError::Timeout => "timeout",
+            FileSyncError::NotFound => "not_found",
+        };
+        self.file_sync_errors.with_label_values(&[error]).inc();
+    }
+
+    pub fn program_run(&self, state: ProgramState) {
+        let outcome = match state {
+            ProgramState::Exited => "exited",
+            ProgramState::Stopped => "stopped",
+            ProgramState::TimedOut => "timed_out",
+            ProgramState::Failed => "failed",
+            // runs are only counted once they have finished
+            ProgramState::Building | ProgramState::Running => return,
+        };
+        self.program_runs.with_label_values(&[outcome]).inc();
+    }
+
+    /// Metrics in the Prometheus text format, with the language server's memory use as of now
+    fn encode(&self, language_server_pid: Option<u32>) -> Result<String, prometheus::Error> {
+        if let Some(memory) = language_server_pid.and_then(resident_memory) {
+            self.language_server_memory.set(memory as i64);
+        }
+        let mut encoded = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut encoded)?;
+        Ok(String::from_utf8_lossy(&encoded).to_string())
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// A websocket session counted by the metrics, which stops being counted when dropped
+pub struct ActiveSession {
+    metrics: Arc<Metrics>,
+    kind: &'static str,
+}
+
+impl fmt::Debug for ActiveSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActiveSession")
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+impl Drop for ActiveSession {
+    fn drop(&mut self) {
+        self.metrics.sessions.with_label_values(&[self.kind]).dec();
+    }
+}
+
+/// Counts the messages of a language server session, and how long its requests took to be answered
+pub struct LspSessionMetrics {
+    _session: ActiveSession,
+    metrics: Arc<Metrics>,
+}
+
+impl LspSessionMetrics {
+    pub fn new(metrics: &Arc<Metrics>) -> Self {
+        LspSessionMetrics {
+            _session: metrics.session("language_server"),
+            metrics: metrics.clone(),
+        }
+    }
+
+    /// Counts a message, with responses counted under their request's method
+    pub fn message(
+        &self,
+        direction: Direction,
+        message: &Value,
+        completed: Option<&CompletedRequest>,
+    ) {
+        let method = match (message["method"].as_str(), completed) {
+            (Some(method), _) => method_label(method),
+            (None, Some(request)) => {
+                let method = method_label(&request.method);
+                self.metrics
+                    .lsp_request_duration
+                    .with_label_values(&[label(request.direction), method])
+                    .observe(request.elapsed.as_secs_f64());
+                method
+            }
+            (None, None) => UNKNOWN_METHOD,
+        };
+        self.metrics
+            .lsp_messages
+            .with_label_values(&[label(direction), method])
+            .inc();
+    }
+}
+
+fn method_label(method: &str) -> &str {
+    if LSP_METHODS.contains(&method) {
+        method
+    } else {
+        OTHER_METHOD
+    }
+}
+
+fn label(direction: Direction) -> &'static str {
+    match direction {
+        Direction::ClientToServer => "client_to_server",
+        Direction::ServerToClient => "server_to_client",
+    }
+}
+
+/// Resident memory of a process in bytes, where the platform makes it available
+#[cfg(target_os = "linux")]
+fn resident_memory(pid: u32) -> Option<u64> {
+    let statm = std::fs::read_to_string(format!("/proc/{}/statm", pid)).ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * page_size.max(0) as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// Serves the metrics in the Prometheus text format
+pub async fn get_metrics(
+    state: web::Data<AppState>,
+    child: Option<web::Data<Arc<std::sync::Mutex<Child>>>>,
+) -> HttpResponse {
+    let pid = child.map(|child| child.lock().unwrap_or_else(PoisonError::into_inner).id());
+    match state.metrics.encode(pid) {
+        Ok(metrics) => HttpResponse::Ok()
+            .content_type(TextEncoder::new().format_type())
+            .body(metrics),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LspSessionMetrics, Metrics};
+    use crate::language_server::{recorder::Direction, request_tracker::RequestTracker};
+    use serde_json::json;
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn responses_are_counted_under_their_requests_method() {
+        let metrics = Arc::new(Metrics::new());
+        let session = LspSessionMetrics::new(&metrics);
+        let mut requests = RequestTracker::new(Duration::from_secs(5), 1);
+        let mut pass = |direction, message| {
+            let completed = requests.message(direction, &message);
+            session.message(direction, &message, completed.as_ref());
+        };
+
+        pass(
+            Direction::ClientToServer,
+            json!({"id": 1, "method": "textDocument/hover"}),
+        );
+        pass(Direction::ServerToClient, json!({"id": 1, "result": null}));
+        pass(Direction::ServerToClient, json!({"id": 7, "result": null}));
+        pass(
+            Direction::ServerToClient,
+            json!({"method": "language/status", "params": {}}),
+        );
+
+        let encoded = metrics.encode(None).unwrap();
+        assert!(encoded.contains("lsp_proxy_websocket_sessions{kind=\"language_server\"} 1"));
+        assert!(encoded.contains(
+            "lsp_proxy_lsp_messages_total{direction=\"server_to_client\",method=\"textDocument/hover\"} 1"
+        ));
+        assert!(encoded.contains(
+            "lsp_proxy_lsp_messages_total{direction=\"server_to_client\",method=\"response\"} 1"
+        ));
+        assert!(encoded.contains(
+            "lsp_proxy_lsp_messages_total{direction=\"server_to_client\",method=\"other\"} 1"
+        ));
+        assert!(encoded.contains(
+            "lsp_proxy_lsp_request_duration_seconds_count{direction=\"client_to_server\",method=\"textDocument/hover\"} 1"
+        ));
+
+        drop(session);
+        let encoded = metrics.encode(None).unwrap();
+        assert!(encoded.contains("lsp_proxy_websocket_sessions{kind=\"language_server\"} 0"));
+    }
+}
@@ -0,0 +1,95 @@
+use lsp_types::{
+    notification::{Notification, PublishDiagnostics},
+    PublishDiagnosticsParams,
+};
+use path_slash::PathExt;
+use serde_json::json;
+use std::{path::Path, process::Stdio, sync::PoisonError, time::Duration};
+
+use crate::{language_server::server::ClientMessage, AppState};
+
+use super::{
+    code_runner::RunOptions,
+    diagnostics::{parse_diagnostics, publish_params},
+    program_handle::RunPlan,
+    runners::plan_prog,
+    user_program::UserProgramError,
+};
+
+/// How long a build can take when runs aren't given a time limit
+const DEFAULT_BUILD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Builds a file without running it and returns the compiler's diagnostics for each file
+pub async fn check_file(
+    state: &AppState,
+    path: &Path,
+) -> Result<Vec<PublishDiagnosticsParams>, UserProgramError> {
+    let file_path = Path::new(&state.workspace_dir).join(path);
+    if !file_path.is_file() {
+        return Err(UserProgramError::NoProgram);
+    }
+
+    let build = async {
+        match plan_prog(state, &file_path, path, &RunOptions::default()).await {
+            Ok(plan) => run_build(plan).await,
+            Err(UserProgramError::CompilationErrors { output }) => {
+                Ok((output, Some("javac".to_string())))
+            }
+            Err(err) => Err(err),
+        }
+    };
+    let limit = state.run_limits.timeout.unwrap_or(DEFAULT_BUILD_TIMEOUT);
+    // the build's processes are killed when they are dropped at the time limit
+    let (output, failed_tool) = tokio::time::timeout(limit, build)
+        .await
+        .map_err(|_| UserProgramError::BuildTimedOut)??;
+    let diagnostics = parse_diagnostics(&output, Path::new(&state.workspace_dir));
+    if let (Some(tool), true) = (failed_tool, diagnostics.is_empty()) {
+        return Err(UserProgramError::FailedBuild { tool });
+    }
+    Ok(publish_params(
+        diagnostics,
+        &path.to_slash_lossy(),
+        Path::new(&state.workspace_dir),
+    ))
+}
+
+/// Sends diagnostics to the language server websocket's client, replacing the server's
+pub fn publish_to_client(state: &AppState, params: &[PublishDiagnosticsParams]) {
+    let client = state
+        .ls_client
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(client) = client.as_ref() {
+        for params in params {
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": PublishDiagnostics::METHOD,
+                "params": params,
+            });
+            client.do_send(ClientMessage(notification.to_string()));
+        }
+    }
+}
+
+/// Runs a plan's build steps up to the first that fails, returning their output and any failed tool
+async fn run_build(plan: RunPlan) -> Result<(String, Option<String>), UserProgramError> {
+    let mut output = String::new();
+    for mut step in plan.build {
+        let result = step
+            .command
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|_| UserProgramError::FailedBuild {
+                tool: step.tool.clone(),
+            })?;
+        output.push_str(&String::from_utf8_lossy(&result.stdout));
+        output.push_str(&String::from_utf8_lossy(&result.stderr));
+        if !result.status.success() {
+            return Ok((output, Some(step.tool)));
+        }
+    }
+    Ok((output, None))
+}
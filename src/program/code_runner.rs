@@ -1,37 +1,331 @@
-use crate::{
-    config::{self, get_ls_args},
-    file_system::file_sync_command::FileSyncError,
-    AppState,
+use crate::{file_system::file_sync_command::FileSyncError, AppState};
+use actix_web::{http::header, web, web::Json, HttpRequest, HttpResponse, Result};
+use actix_web_actors::ws;
+use lsp_types::PublishDiagnosticsParams;
+use path_slash::PathExt;
+use serde::{
+    de::{self, DeserializeOwned},
+    Deserialize, Deserializer,
+};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    check::{check_file, publish_to_client},
+    exec::{exec_file, ExecRequest, ExecResult},
+    program_handle::{ProgramHandle, ProgramStatus},
+    run_configurations::{self, RunConfiguration},
+    run_history::{RunRecord, TranscriptEntry},
+    runners::run_prog,
+    test_runner::run_tests_for,
+    test_session::TestSession,
+    user_program::{UserProgram, UserProgramError, WindowSize},
 };
-use actix_web::{web, HttpRequest, HttpResponse, Result};
-use std::path::{Path, PathBuf};
 
-use super::{runners::run_java_prog, user_program::UserProgramError};
+/// Query parameters accepted when running a program
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RunOptions {
+    /// Run the program in a pseudo-terminal, streaming raw bytes both ways instead of lines
+    #[serde(default)]
+    pub pty: bool,
+    pub rows: Option<u16>,
+    pub cols: Option<u16>,
+    /// Class to launch, instead of the one worked out from the project or file
+    pub main: Option<String>,
+    /// Arguments for the program, as a JSON array of strings
+    #[serde(default, deserialize_with = "json_parameter")]
+    pub args: Vec<String>,
+    /// Environment variables to set for the program, as a JSON object of strings
+    #[serde(default, deserialize_with = "json_parameter")]
+    pub env: BTreeMap<String, String>,
+    /// Workspace file to pipe to the program's stdin, instead of reading input from the client
+    pub stdin: Option<String>,
+    /// Saved run configuration to start from, which the other options override
+    pub config: Option<String>,
+    /// Client session the run counts against, for the limit on concurrent runs
+    pub session: Option<String>,
+    /// Port for a debugger to attach to, with the program suspended until it does
+    #[serde(skip)]
+    pub debug_port: Option<u16>,
+}
+
+impl RunOptions {
+    /// Fills in the options that weren't given from a saved run configuration
+    pub fn with_configuration(mut self, configuration: RunConfiguration) -> Self {
+        if self.args.is_empty() {
+            self.args = configuration.args;
+        }
+        let mut env = configuration.env;
+        env.append(&mut self.env);
+        self.env = env;
+        self.stdin = self.stdin.or(configuration.stdin);
+        self.main = self.main.or(configuration.main);
+        self
+    }
+
+    /// Applies the options' saved run configuration and checks they can run in the workspace
+    pub async fn resolve(self, workspace_dir: &str, file: &str) -> Result<Self, UserProgramError> {
+        let mut options = match &self.config {
+            Some(name) => {
+                let configuration = run_configurations::find(workspace_dir, file, name).await?;
+                self.with_configuration(configuration)
+            }
+            None => self,
+        };
+
+        run_configurations::validate_env(&options.env)?;
+        if let Some(stdin) = &options.stdin {
+            if options.pty {
+                return Err(UserProgramError::InvalidRunOptions {
+                    cause: "stdin files can't be used with a terminal".to_string(),
+                });
+            }
+            let stdin = run_configurations::resolve_stdin(workspace_dir, stdin)?;
+            options.stdin = Some(stdin.to_string_lossy().to_string());
+        }
+        Ok(options)
+    }
+
+    /// Initial size of the program's terminal, if it should run in one
+    pub fn terminal_size(&self) -> Option<WindowSize> {
+        if !self.pty {
+            return None;
+        }
+        let default = WindowSize::default();
+        Some(WindowSize {
+            rows: self.rows.unwrap_or(default.rows),
+            cols: self.cols.unwrap_or(default.cols),
+        })
+    }
+
+    /// Options for the JVM, which load the debug agent when debugging
+    pub fn jvm_args(&self) -> Vec<String> {
+        self.debug_port
+            .map(|port| {
+                format!(
+                    "-agentlib:jdwp=transport=dt_socket,server=y,suspend=y,address=127.0.0.1:{}",
+                    port
+                )
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Reads a parameter given either as JSON or as a string holding JSON
+fn json_parameter<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(json) => serde_json::from_str(&json).map_err(de::Error::custom),
+        value => serde_json::from_value(value).map_err(de::Error::custom),
+    }
+}
 
 /// Starts a websocket to run the requested file
 pub async fn run_program_file(
     req: HttpRequest,
     stream: web::Payload,
     state: web::Data<AppState>,
+    options: web::Query<RunOptions>,
 ) -> Result<HttpResponse> {
-    let path: PathBuf =
+    let path = requested_path(&req)?;
+
+    let file_path = Path::new(&state.workspace_dir).join(path.clone());
+    if !file_path.exists() {
+        return Ok(HttpResponse::NotFound().body("Nothing to execute."));
+    }
+
+    let options = options
+        .into_inner()
+        .resolve(&state.workspace_dir, &path.to_slash_lossy())
+        .await?;
+    run_prog(req, stream, state, file_path, path, options).await
+}
+
+/// Builds and runs the requested file to completion, returning its captured output
+pub async fn exec_program(
+    state: web::Data<AppState>,
+    request: Json<ExecRequest>,
+) -> Result<Json<ExecResult>> {
+    Ok(Json(exec_file(&state, request.into_inner()).await?))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BuildOptions {
+    /// Also send the diagnostics to the editor over the language server websocket
+    #[serde(default)]
+    pub publish: bool,
+}
+
+/// Builds the requested file without running it, returning the compiler's diagnostics
+pub async fn build_program(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    options: web::Query<BuildOptions>,
+) -> Result<Json<Vec<PublishDiagnosticsParams>>> {
+    let path = requested_path(&req)?;
+
+    let diagnostics = check_file(&state, &path).await?;
+    if options.publish {
+        publish_to_client(&state, &diagnostics);
+    }
+    Ok(Json(diagnostics))
+}
+
+/// Runs the tests for the requested file or directory, or the whole codebase for an empty path
+pub async fn run_tests(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let path = requested_path(&req)?;
+
+    if !Path::new(&state.workspace_dir).join(&path).exists() {
+        return Err(UserProgramError::NoProgram.into());
+    }
+    if req.headers().contains_key(header::UPGRADE) {
+        return ws::start(TestSession::new(state, path), &req, stream);
+    }
+    let report = run_tests_for(&state, &path, &mut |_, _| ()).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Lists the run configurations saved for the requested file
+pub async fn list_run_configurations(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<Json<Vec<RunConfiguration>>> {
+    let file = configured_file(&req)?;
+    Ok(Json(
+        run_configurations::for_file(&state.workspace_dir, &file).await?,
+    ))
+}
+
+/// Saves a run configuration for the requested file, replacing any with the same name
+pub async fn save_run_configuration(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    configuration: Json<RunConfiguration>,
+) -> Result<Json<Vec<RunConfiguration>>> {
+    let file = configured_file(&req)?;
+    let _saving = state.run_configurations_lock.lock().await;
+    Ok(Json(
+        run_configurations::save(&state.workspace_dir, &file, configuration.into_inner()).await?,
+    ))
+}
+
+/// Path of the file that run configurations are requested for
+fn configured_file(req: &HttpRequest) -> Result<String> {
+    Ok(requested_path(req)?.to_slash_lossy())
+}
+
+/// Path within the workspace that the request's URL names
+fn requested_path(req: &HttpRequest) -> Result<PathBuf> {
+    let path =
         req.match_info()
             .query("filename")
             .parse()
             .map_err(|_| FileSyncError::BadClientData {
                 cause: "Error parsing request URL".to_string(),
             })?;
+    Ok(path)
+}
 
-    let file_path = Path::new(&get_ls_args().codebase_path).join(path.clone());
-    if !file_path.exists() {
-        return Ok(HttpResponse::NotFound().body("Nothing to execute."));
-    }
+/// Reports the status of the most recently started program
+pub async fn program_status(state: web::Data<AppState>) -> Result<Json<ProgramStatus>> {
+    let handle = state.runs.latest().ok_or(UserProgramError::NoProgram)?;
+    Ok(Json(handle.status()))
+}
 
-    let output = match &state.lang {
-        config::Lang::Java => run_java_prog(req, stream, state, file_path, path).await?,
-        config::Lang::C => return Err(UserProgramError::UnsupportedLanguage.into()),
-        config::Lang::Custom => return Err(UserProgramError::UnsupportedLanguage.into()),
+/// Stops the most recently started program, with SIGTERM and then SIGKILL
+pub async fn stop_program(state: web::Data<AppState>) -> Result<Json<ProgramStatus>> {
+    let handle = state.runs.latest().ok_or(UserProgramError::NoProgram)?;
+    stop(handle).await
+}
+
+/// Lists the status of every program that is running
+pub async fn list_runs(state: web::Data<AppState>) -> Json<Vec<ProgramStatus>> {
+    Json(state.runs.running())
+}
+
+/// Reports the status of the requested run
+pub async fn run_status(
+    state: web::Data<AppState>,
+    id: web::Path<u64>,
+) -> Result<Json<ProgramStatus>> {
+    let handle = state.runs.get(*id).ok_or(UserProgramError::NoProgram)?;
+    Ok(Json(handle.status()))
+}
+
+/// Stops the requested run, like stopping the most recently started program
+pub async fn stop_run(
+    state: web::Data<AppState>,
+    id: web::Path<u64>,
+) -> Result<Json<ProgramStatus>> {
+    let handle = state.runs.get(*id).ok_or(UserProgramError::NoProgram)?;
+    stop(handle).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ObserveOptions {
+    /// Input token the run's owner was given, letting this observer send the run input
+    pub input_token: Option<String>,
+}
+
+/// Starts a websocket that mirrors the requested run's output from its start
+pub async fn observe_run(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: web::Data<AppState>,
+    id: web::Path<u64>,
+    options: web::Query<ObserveOptions>,
+) -> Result<HttpResponse> {
+    let run = state.runs.active(*id).ok_or(UserProgramError::NoProgram)?;
+    let input = match &options.input_token {
+        Some(token) if *token == run.input_token => Some(
+            run.broadcast
+                .claim_input()
+                .ok_or(UserProgramError::InputTaken)?,
+        ),
+        Some(_) => return Err(UserProgramError::InputNotGranted.into()),
+        None => None,
     };
+    let events = run.broadcast.subscribe();
+    ws::start(
+        UserProgram::observer(
+            run.handle,
+            events,
+            run.terminal,
+            input,
+            state.metrics.session("observer"),
+        ),
+        &req,
+        stream,
+    )
+}
+
+/// Lists every recorded run, most recent first, including runs from earlier sessions
+pub async fn run_history(state: web::Data<AppState>) -> Json<Vec<RunRecord>> {
+    Json(state.runs.history().runs().await)
+}
 
-    Ok(output)
+/// Returns the interleaved stdin, stdout and stderr recorded for the requested run
+pub async fn run_transcript(
+    state: web::Data<AppState>,
+    id: web::Path<u64>,
+) -> Result<Json<Vec<TranscriptEntry>>> {
+    Ok(Json(state.runs.history().transcript(*id).await?))
+}
+
+async fn stop(handle: ProgramHandle) -> Result<Json<ProgramStatus>> {
+    if !handle.is_running() {
+        return Err(UserProgramError::NoProgram.into());
+    }
+    Ok(Json(handle.stop().await?))
 }
@@ -0,0 +1,258 @@
+use lsp_types::{DiagnosticSeverity, Position, PublishDiagnosticsParams, Range, Url};
+use path_slash::PathExt;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// Source of diagnostics from the proxy's own builds
+pub const BUILD_DIAGNOSTICS_SOURCE: &str = "lsp-proxy build";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A compiler message about a line of a source file
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// Path of the source file, relative to the root of the codebase when it is inside it
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Finds the diagnostics in javac, Gradle, Maven, gcc or clang output
+pub fn parse_diagnostics(output: &str, workspace_dir: &Path) -> Vec<Diagnostic> {
+    let workspace_dir = workspace_dir
+        .canonicalize()
+        .unwrap_or_else(|_| workspace_dir.to_path_buf());
+    let lines: Vec<&str> = output.lines().collect();
+
+    let mut diagnostics = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(mut diagnostic) = javac_diagnostic(line)
+            .or_else(|| maven_diagnostic(line))
+            .or_else(|| gcc_diagnostic(line))
+        {
+            if diagnostic.column.is_none() {
+                // javac follows the message with the source line, and a caret under the column
+                diagnostic.column = lines.get(i + 2).and_then(|line| caret_column(line));
+            }
+            if let Ok(relative) = Path::new(&diagnostic.file).strip_prefix(&workspace_dir) {
+                diagnostic.file = relative.to_slash_lossy();
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+fn javac_diagnostic(line: &str) -> Option<Diagnostic> {
+    let (severity, start, marker) = [
+        (Severity::Error, ": error: "),
+        (Severity::Warning, ": warning: "),
+    ]
+    .iter()
+    .find_map(|(severity, marker)| line.find(marker).map(|start| (*severity, start, marker)))?;
+    let (file, line_number) = line[..start].rsplit_once(':')?;
+    if !file.ends_with(".java") {
+        return None;
+    }
+    Some(Diagnostic {
+        file: file.to_string(),
+        line: line_number.parse().ok()?,
+        column: None,
+        severity,
+        message: line[start + marker.len()..].to_string(),
+    })
+}
+
+fn maven_diagnostic(line: &str) -> Option<Diagnostic> {
+    let (severity, rest) = if let Some(rest) = line.strip_prefix("[ERROR] ") {
+        (Severity::Error, rest)
+    } else {
+        (Severity::Warning, line.strip_prefix("[WARNING] ")?)
+    };
+    let (file, rest) = rest.split_once(".java:[")?;
+    let (position, message) = rest.split_once("] ")?;
+    let (line_number, column) = position.split_once(',')?;
+    Some(Diagnostic {
+        file: format!("{}.java", file),
+        line: line_number.parse().ok()?,
+        column: column.parse().ok(),
+        severity,
+        message: message.to_string(),
+    })
+}
+
+fn gcc_diagnostic(line: &str) -> Option<Diagnostic> {
+    let (severity, start, marker) = [
+        (Severity::Error, ": error: "),
+        (Severity::Error, ": fatal error: "),
+        (Severity::Warning, ": warning: "),
+    ]
+    .iter()
+    .find_map(|(severity, marker)| line.find(marker).map(|start| (*severity, start, marker)))?;
+    let mut position = line[..start].rsplitn(3, ':');
+    let column = position.next()?.parse().ok()?;
+    let line_number = position.next()?.parse().ok()?;
+    Some(Diagnostic {
+        file: position.next()?.to_string(),
+        line: line_number,
+        column: Some(column),
+        severity,
+        message: line[start + marker.len()..].to_string(),
+    })
+}
+
+fn caret_column(line: &str) -> Option<u32> {
+    if line.trim() != "^" {
+        return None;
+    }
+    Some(line.find('^')? as u32 + 1)
+}
+
+/// Groups diagnostics by file, with an entry for `file` even if it has none
+pub fn publish_params(
+    diagnostics: Vec<Diagnostic>,
+    file: &str,
+    workspace_dir: &Path,
+) -> Vec<PublishDiagnosticsParams> {
+    let workspace_dir = workspace_dir
+        .canonicalize()
+        .unwrap_or_else(|_| workspace_dir.to_path_buf());
+    let mut files = BTreeMap::new();
+    files.insert(file.to_string(), Vec::new());
+    for diagnostic in diagnostics {
+        let line = diagnostic.line.saturating_sub(1);
+        let character = diagnostic.column.unwrap_or(1).saturating_sub(1);
+        let position = Position::new(line, character);
+        files
+            .entry(diagnostic.file)
+            .or_insert_with(Vec::new)
+            .push(lsp_types::Diagnostic {
+                range: Range::new(position, position),
+                severity: Some(match diagnostic.severity {
+                    Severity::Error => DiagnosticSeverity::Error,
+                    Severity::Warning => DiagnosticSeverity::Warning,
+                }),
+                source: Some(BUILD_DIAGNOSTICS_SOURCE.to_string()),
+                message: diagnostic.message,
+                ..lsp_types::Diagnostic::default()
+            });
+    }
+    files
+        .into_iter()
+        .filter_map(|(file, diagnostics)| {
+            let uri = Url::from_file_path(workspace_dir.join(file)).ok()?;
+            Some(PublishDiagnosticsParams::new(uri, diagnostics, None))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_diagnostics, publish_params, Diagnostic, Severity, BUILD_DIAGNOSTICS_SOURCE,
+    };
+    use lsp_types::{DiagnosticSeverity, Position};
+    use std::path::Path;
+
+    #[test]
+    fn javac_diagnostics_are_parsed_with_columns() {
+        let output = "/ws/src/Main.java:3: error: ';' expected\n        int x = 1\n                 ^\n/ws/src/Util.java:7: warning: [removal] Integer(int) in Integer has been deprecated\n        new Integer(1);\n        ^\n1 error\n1 warning\n";
+
+        let diagnostics = parse_diagnostics(output, Path::new("/ws"));
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    file: "src/Main.java".to_string(),
+                    line: 3,
+                    column: Some(18),
+                    severity: Severity::Error,
+                    message: "';' expected".to_string(),
+                },
+                Diagnostic {
+                    file: "src/Util.java".to_string(),
+                    line: 7,
+                    column: Some(9),
+                    severity: Severity::Warning,
+                    message: "[removal] Integer(int) in Integer has been deprecated".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn maven_diagnostics_are_parsed() {
+        let output = "[INFO] Compiling 2 source files\n[ERROR] /ws/src/main/java/App.java:[3,40] ';' expected\n[ERROR] Failed to execute goal\n";
+
+        let diagnostics = parse_diagnostics(output, Path::new("/elsewhere"));
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                file: "/ws/src/main/java/App.java".to_string(),
+                line: 3,
+                column: Some(40),
+                severity: Severity::Error,
+                message: "';' expected".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn gcc_diagnostics_are_parsed() {
+        let output = "/ws/hello.c: In function 'main':\n/ws/hello.c:5:4: error: expected ';' before 'return'\n    5 |    return 0;\n      |    ^~~~~~\n/ws/util.h:2:1: warning: data definition has no type\n";
+
+        let diagnostics = parse_diagnostics(output, Path::new("/ws"));
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    file: "hello.c".to_string(),
+                    line: 5,
+                    column: Some(4),
+                    severity: Severity::Error,
+                    message: "expected ';' before 'return'".to_string(),
+                },
+                Diagnostic {
+                    file: "util.h".to_string(),
+                    line: 2,
+                    column: Some(1),
+                    severity: Severity::Warning,
+                    message: "data definition has no type".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnostics_are_published_per_file() {
+        let diagnostics = vec![Diagnostic {
+            file: "src/Util.java".to_string(),
+            line: 3,
+            column: Some(18),
+            severity: Severity::Error,
+            message: "';' expected".to_string(),
+        }];
+
+        let params = publish_params(diagnostics, "src/Main.java", Path::new("/ws"));
+
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[0].uri.as_str(), "file:///ws/src/Main.java");
+        assert!(params[0].diagnostics.is_empty());
+        assert_eq!(params[1].uri.as_str(), "file:///ws/src/Util.java");
+        let diagnostic = &params[1].diagnostics[0];
+        assert_eq!(diagnostic.range.start, Position::new(2, 17));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::Error));
+        assert_eq!(diagnostic.source.as_deref(), Some(BUILD_DIAGNOSTICS_SOURCE));
+    }
+}
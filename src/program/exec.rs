@@ -0,0 +1,178 @@
+use path_slash::PathExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tempfile::NamedTempFile;
+
+use crate::AppState;
+
+use super::{
+    code_runner::RunOptions,
+    diagnostics::{parse_diagnostics, Diagnostic},
+    program_handle::{ProgramEvent, ProgramHandle, ProgramState},
+    runners::plan_prog,
+    user_program::UserProgramError,
+};
+
+/// Body of a batch run request. The run options are given as JSON values rather than JSON strings.
+#[derive(Debug, Deserialize)]
+pub struct ExecRequest {
+    /// Path of the file to run, relative to the root of the codebase
+    pub file: String,
+    /// Text to pipe to the program's stdin, instead of a workspace file
+    pub input: Option<String>,
+    #[serde(flatten)]
+    pub options: RunOptions,
+}
+
+/// Everything a batch run printed, and how it ended
+#[derive(Debug, Serialize)]
+pub struct ExecResult {
+    /// `failed` if the program could not be built or started
+    pub state: ProgramState,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether output past the output limit was dropped
+    pub truncated: bool,
+    /// Milliseconds taken to build and run the program
+    pub duration_ms: u64,
+    /// Output of the compiler or build tool
+    pub build_output: String,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Why the program could not be built or started
+    pub error: Option<String>,
+}
+
+impl ExecResult {
+    fn failed(error: &UserProgramError, build_output: String, started: Instant) -> Self {
+        ExecResult {
+            state: ProgramState::Failed,
+            exit_code: None,
+            stdout: String::new(),
+            stderr: String::new(),
+            truncated: false,
+            duration_ms: started.elapsed().as_millis() as u64,
+            build_output,
+            diagnostics: Vec::new(),
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Stops a batch run whose request was dropped before the run finished
+pub struct StopOnDrop(pub ProgramHandle);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        if self.0.is_running() {
+            let handle = self.0.clone();
+            actix::spawn(async move {
+                let _ = handle.stop().await;
+            });
+        }
+    }
+}
+
+/// Builds and runs a file to completion, capturing its output
+pub async fn exec_file(
+    state: &AppState,
+    request: ExecRequest,
+) -> Result<ExecResult, UserProgramError> {
+    let path = PathBuf::from(&request.file);
+    let file_path = Path::new(&state.workspace_dir).join(&path);
+    if request.file.is_empty() || !file_path.is_file() {
+        return Err(UserProgramError::NoProgram);
+    }
+    if request.options.pty {
+        return Err(UserProgramError::InvalidRunOptions {
+            cause: "batch runs can't use a terminal".to_string(),
+        });
+    }
+
+    let mut options = request
+        .options
+        .resolve(&state.workspace_dir, &path.to_slash_lossy())
+        .await?;
+    // kept until the run finishes, so the program can read it. Without any input the program reads an empty file, rather than waiting for input that never comes.
+    let _input_file = match (request.input, &options.stdin) {
+        (Some(_), Some(_)) => {
+            return Err(UserProgramError::InvalidRunOptions {
+                cause: "input can't be given along with a stdin file".to_string(),
+            })
+        }
+        (input, None) => {
+            let file = write_input(&state.build_dir, &input.unwrap_or_default())?;
+            options.stdin = Some(file.path().to_string_lossy().to_string());
+            Some(file)
+        }
+        (None, Some(_)) => None,
+    };
+
+    let started = Instant::now();
+    let plan = match plan_prog(state, &file_path, &path, &options).await {
+        Ok(plan) => plan,
+        Err(UserProgramError::CompilationErrors { output }) => {
+            let mut result =
+                ExecResult::failed(&UserProgramError::FailedCompilation, output, started);
+            result.diagnostics =
+                parse_diagnostics(&result.build_output, Path::new(&state.workspace_dir));
+            return Ok(result);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let (handle, mut events) =
+        state
+            .runs
+            .start(options.session.as_deref(), plan, state.run_limits)?;
+    let run = StopOnDrop(handle);
+    let mut build_output = String::new();
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut error = None;
+    let mut exited = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            ProgramEvent::Build(line) => push_line(&mut build_output, line),
+            ProgramEvent::Stdout(line) => push_line(&mut stdout, line),
+            ProgramEvent::Stderr(line) => push_line(&mut stderr, line),
+            ProgramEvent::Terminal(bytes) => stdout.push_str(&String::from_utf8_lossy(&bytes)),
+            ProgramEvent::Failed(err) => error = Some(err.to_string()),
+            ProgramEvent::Input(_) => (),
+            ProgramEvent::Exited(status) => {
+                exited = Some(status);
+                break;
+            }
+        }
+    }
+    let status = exited.unwrap_or_else(|| run.0.status());
+
+    Ok(ExecResult {
+        state: status.state,
+        exit_code: status.exit_code,
+        stdout,
+        stderr,
+        truncated: status.truncated,
+        duration_ms: started.elapsed().as_millis() as u64,
+        diagnostics: parse_diagnostics(&build_output, Path::new(&state.workspace_dir)),
+        build_output,
+        error,
+    })
+}
+
+/// Writes the input to a file for the program's stdin, so the program sees the end of its input
+fn write_input(build_dir: &Path, input: &str) -> Result<NamedTempFile, UserProgramError> {
+    std::fs::create_dir_all(build_dir)
+        .and_then(|_| NamedTempFile::new_in(build_dir))
+        .and_then(|mut file| file.write_all(input.as_bytes()).map(|_| file))
+        .map_err(|_| UserProgramError::FailedRun)
+}
+
+fn push_line(output: &mut String, line: String) {
+    output.push_str(&line);
+    output.push('\n');
+}
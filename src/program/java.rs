@@ -0,0 +1,211 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::process::Command;
+use walkdir::WalkDir;
+
+use crate::file_system::files::is_ignored;
+
+use super::user_program::UserProgramError;
+
+/// A Java source file that can be launched, along with the module it belongs to
+#[derive(Debug, PartialEq)]
+pub struct JavaProgram {
+    /// Directory that the file's package path is relative to
+    pub source_root: PathBuf,
+    /// Fully qualified name of the class to launch
+    pub main_class: String,
+}
+
+impl JavaProgram {
+    /// Works out the source root and main class of a file from its `package` declaration
+    pub async fn locate(file_path: &Path) -> Result<Self, UserProgramError> {
+        // sources are handed to javac, which runs from the workspace rather than the proxy's directory
+        let file_path = tokio::fs::canonicalize(file_path)
+            .await
+            .map_err(|_| UserProgramError::NoProgram)?;
+        let source = tokio::fs::read_to_string(&file_path)
+            .await
+            .map_err(|_| UserProgramError::NoProgram)?;
+        Self::from_source(&file_path, &source).ok_or(UserProgramError::NoProgram)
+    }
+
+    fn from_source(file_path: &Path, source: &str) -> Option<Self> {
+        let class_name = file_path.file_stem()?.to_str()?;
+        let mut source_root = file_path.parent()?.to_path_buf();
+        let package = package_name(source);
+
+        if let Some(package) = &package {
+            // walk up one directory per package segment, as long as the directories agree with the package
+            for segment in package.rsplit('.') {
+                if !source_root.ends_with(segment) {
+                    return Some(JavaProgram {
+                        source_root: file_path.parent()?.to_path_buf(),
+                        main_class: format!("{}.{}", package, class_name),
+                    });
+                }
+                source_root.pop();
+            }
+        }
+
+        Some(JavaProgram {
+            source_root,
+            main_class: match package {
+                Some(package) => format!("{}.{}", package, class_name),
+                None => class_name.to_string(),
+            },
+        })
+    }
+
+    /// All Java sources in the program's module
+    pub fn sources(&self) -> Vec<PathBuf> {
+        module_sources(&self.source_root)
+    }
+
+    /// Compiles every source in the module into `out_dir`, replacing anything already there
+    pub async fn compile(
+        &self,
+        workspace_dir: &str,
+        out_dir: &Path,
+    ) -> Result<(), UserProgramError> {
+        compile_module(&self.source_root, workspace_dir, out_dir, None).await
+    }
+}
+
+/// All Java sources under a source root, skipping ignored files and directories
+pub fn module_sources(source_root: &Path) -> Vec<PathBuf> {
+    WalkDir::new(source_root)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !is_ignored(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| p.extension() == Some(OsStr::new("java")))
+        .collect()
+}
+
+/// Compiles every source under a source root into `out_dir`, replacing anything already there
+pub async fn compile_module(
+    source_root: &Path,
+    workspace_dir: &str,
+    out_dir: &Path,
+    classpath: Option<&Path>,
+) -> Result<(), UserProgramError> {
+    if out_dir.exists() {
+        tokio::fs::remove_dir_all(out_dir)
+            .await
+            .map_err(|_| UserProgramError::FailedCompilation)?;
+    }
+    tokio::fs::create_dir_all(out_dir)
+        .await
+        .map_err(|_| UserProgramError::FailedCompilation)?;
+
+    let mut command = Command::new("javac");
+    command.current_dir(workspace_dir).arg("-d").arg(out_dir);
+    if let Some(classpath) = classpath {
+        command.arg("-cp").arg(classpath);
+    }
+    let output = command
+        .args(module_sources(source_root))
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|_| UserProgramError::FailedCompilation)?;
+
+    if !output.status.success() {
+        return Err(UserProgramError::CompilationErrors {
+            output: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Finds the package a Java compilation unit declares, ignoring comments
+fn package_name(source: &str) -> Option<String> {
+    let code = strip_comments(source);
+    let declaration = code.trim_start().strip_prefix("package")?;
+    if !declaration.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let package: String = declaration
+        .split(';')
+        .next()?
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if package.is_empty() {
+        None
+    } else {
+        Some(package)
+    }
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut code = String::with_capacity(source.len());
+    let mut rest = source;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("//") {
+            rest = comment.find('\n').map_or("", |end| &comment[end..]);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+            code.push(' ');
+        } else {
+            let c = rest.chars().next().unwrap();
+            code.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{package_name, JavaProgram};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn package_is_found_after_comments() {
+        let source = "/* Licence\n * header */\n// a comment\npackage com.example.app ;\n\nimport java.util.List;\nclass Main {}";
+
+        assert_eq!(package_name(source), Some("com.example.app".to_string()));
+    }
+
+    #[test]
+    fn default_package_has_no_name() {
+        assert_eq!(
+            package_name("import java.util.Scanner;\nclass Hello {}"),
+            None
+        );
+        assert_eq!(package_name("class packageless {}"), None);
+    }
+
+    #[test]
+    fn source_root_is_found_from_package() {
+        let program = JavaProgram::from_source(
+            Path::new("/ws/src/main/java/com/example/Main.java"),
+            "package com.example;\nclass Main {}",
+        );
+
+        assert_eq!(
+            program,
+            Some(JavaProgram {
+                source_root: PathBuf::from("/ws/src/main/java"),
+                main_class: "com.example.Main".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn mismatched_package_directories_use_file_directory() {
+        let program = JavaProgram::from_source(
+            Path::new("/ws/src/Main.java"),
+            "package com.example;\nclass Main {}",
+        )
+        .expect("could not locate program");
+
+        assert_eq!(program.source_root, PathBuf::from("/ws/src"));
+        assert_eq!(program.main_class, "com.example.Main");
+    }
+}
@@ -1,3 +1,19 @@
+pub mod check;
 pub mod code_runner;
+pub mod diagnostics;
+pub mod exec;
+pub mod java;
+pub mod program_handle;
+pub mod project;
+#[cfg(unix)]
+pub mod pty;
+pub mod run_broadcast;
+pub mod run_configurations;
+pub mod run_history;
+pub mod run_registry;
+pub mod run_template;
 pub mod runners;
+pub mod test_report;
+pub mod test_runner;
+pub mod test_session;
 pub mod user_program;
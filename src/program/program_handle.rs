@@ -0,0 +1,617 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    process::{ExitStatus, Stdio},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, Command},
+    stream::StreamExt,
+    sync::{mpsc, watch},
+    time::{delay_for, Delay, Instant},
+};
+use tracing::{error, info, info_span, Instrument};
+
+#[cfg(unix)]
+use super::pty::Pty;
+use super::user_program::{UserProgramError, WindowSize};
+
+/// How long a program has to exit after being asked to terminate, before it is killed
+#[cfg(unix)]
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// How long to wait for the rest of a process's output once it has exited
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgramState {
+    Building,
+    Running,
+    Exited,
+    Stopped,
+    /// Stopped for running longer than the time limit
+    #[serde(rename = "timed_out")]
+    TimedOut,
+    Failed,
+}
+
+/// Snapshot of a user program's process, serialised for the run status endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgramStatus {
+    /// Run id, unique while the proxy is running
+    pub id: u64,
+    /// Not known until the program has been built and started
+    pub pid: Option<u32>,
+    pub file: String,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    pub state: ProgramState,
+    pub exit_code: Option<i32>,
+    /// Whether output past the output limit was dropped
+    pub truncated: bool,
+}
+
+impl ProgramStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self.state, ProgramState::Building | ProgramState::Running)
+    }
+}
+
+/// Output and lifecycle events of a program run, in the order they happened
+#[derive(Debug, Clone)]
+pub enum ProgramEvent {
+    /// A line of output from a build step
+    Build(String),
+    Stdout(String),
+    Stderr(String),
+    /// Raw output from a program running in a terminal
+    Terminal(Vec<u8>),
+    /// Input from the client, once it has been written to the program
+    Input(String),
+    /// The program could not be built or started
+    Failed(UserProgramError),
+    Exited(ProgramStatus),
+}
+
+#[derive(Debug)]
+pub enum ProgramInput {
+    /// Written to the program followed by a newline
+    Line(String),
+    /// Written to the program as-is
+    Bytes(Vec<u8>),
+    Resize(WindowSize),
+    Stop,
+}
+
+/// A command run before the program, typically a build tool
+#[derive(Debug)]
+pub struct BuildStep {
+    /// Name of the tool, used when reporting a failed step
+    pub tool: String,
+    pub command: Command,
+}
+
+/// Everything needed to run a user program
+pub struct RunPlan {
+    pub file: String,
+    pub build: Vec<BuildStep>,
+    pub launch: Box<dyn FnOnce() -> Result<Command, UserProgramError>>,
+    /// Run the program in a pseudo-terminal of this size, rather than with piped stdio
+    pub terminal: Option<WindowSize>,
+    /// Arguments for the program, passed after those of the launch command
+    pub args: Vec<String>,
+    /// Environment variables set for the program, on top of the proxy's own
+    pub env: BTreeMap<String, String>,
+    /// File the program reads its stdin from, instead of input from the client
+    pub stdin: Option<PathBuf>,
+    /// Directory the program is built into, removed once the run is over
+    pub build_dir: Option<TempDir>,
+}
+
+/// Limits applied to every program run, whether it is run over a websocket or as a batch
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunLimits {
+    /// How long the program can run for once it has been built, before it is stopped
+    pub timeout: Option<Duration>,
+    /// Bytes of output sent before the rest of the program's output is dropped
+    pub max_output: Option<usize>,
+}
+
+/// Thread safe handle to a user program built and run by a background task
+#[derive(Debug, Clone)]
+pub struct ProgramHandle {
+    status: watch::Receiver<ProgramStatus>,
+    input: mpsc::UnboundedSender<ProgramInput>,
+}
+
+impl ProgramHandle {
+    /// Starts running the plan in the background, returning a handle to the run and its events
+    pub fn start(
+        id: u64,
+        plan: RunPlan,
+        limits: RunLimits,
+    ) -> (Self, mpsc::UnboundedReceiver<ProgramEvent>) {
+        let status = ProgramStatus {
+            id,
+            pid: None,
+            file: plan.file.clone(),
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|t| t.as_secs())
+                .unwrap_or_default(),
+            state: ProgramState::Building,
+            exit_code: None,
+            truncated: false,
+        };
+        let (status_tx, status_rx) = watch::channel(status.clone());
+        let (input_tx, input_rx) = mpsc::unbounded_channel();
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let run = Run {
+            events: events_tx,
+            input: input_rx,
+            status_tx,
+            status,
+            pending_input: Vec::new(),
+            limits,
+            output_left: limits.max_output,
+        };
+        let span = info_span!("run", id, file = %plan.file);
+        actix::spawn(run.run(plan).instrument(span));
+
+        let handle = ProgramHandle {
+            status: status_rx,
+            input: input_tx,
+        };
+        (handle, events_rx)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.status.borrow().id
+    }
+
+    pub fn status(&self) -> ProgramStatus {
+        self.status.borrow().clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.status.borrow().is_finished()
+    }
+
+    pub fn send(&self, input: ProgramInput) {
+        // the run may have already finished, in which case nothing is listening
+        let _ = self.input.send(input);
+    }
+
+    /// Stops the program, returning its status once it has exited
+    pub async fn stop(&self) -> Result<ProgramStatus, UserProgramError> {
+        self.send(ProgramInput::Stop);
+        // the run is gone without recording an exit, so the process could not be killed
+        self.finished().await.ok_or(UserProgramError::FailedKill)
+    }
+
+    /// Waits for the run to finish, returning its final status
+    pub async fn finished(&self) -> Option<ProgramStatus> {
+        let mut status = self.status.clone();
+        while let Some(current) = status.recv().await {
+            if current.is_finished() {
+                return Some(current);
+            }
+        }
+        None
+    }
+}
+
+/// How a build step or program ended
+enum Outcome {
+    Exited(ExitStatus),
+    Stopped(ExitStatus),
+    TimedOut(ExitStatus),
+}
+
+/// Background task that owns a program's processes
+struct Run {
+    events: mpsc::UnboundedSender<ProgramEvent>,
+    input: mpsc::UnboundedReceiver<ProgramInput>,
+    status_tx: watch::Sender<ProgramStatus>,
+    status: ProgramStatus,
+    /// Input received before the program started, written once it has
+    pending_input: Vec<ProgramInput>,
+    limits: RunLimits,
+    /// Bytes of output that can still be sent, if output is limited
+    output_left: Option<usize>,
+}
+
+impl Run {
+    async fn run(mut self, plan: RunPlan) {
+        let _build_dir = plan.build_dir;
+        for step in plan.build {
+            let tool = step.tool.clone();
+            match self.build(step).await {
+                Ok(Outcome::Exited(exit)) if exit.success() => (),
+                Ok(Outcome::Exited(exit)) => {
+                    return self.fail(UserProgramError::FailedBuild { tool }, exit.code())
+                }
+                Ok(Outcome::Stopped(exit)) | Ok(Outcome::TimedOut(exit)) => {
+                    return self.finish(ProgramState::Stopped, exit.code())
+                }
+                Err(err) => return self.fail(err, None),
+            }
+        }
+
+        let mut command = match (plan.launch)() {
+            Ok(command) => command,
+            Err(err) => return self.fail(err, None),
+        };
+        command.args(&plan.args).envs(&plan.env);
+        let outcome = match plan.terminal {
+            #[cfg(unix)]
+            Some(size) => self.run_in_terminal(&mut command, size).await,
+            #[cfg(not(unix))]
+            Some(_) => Err(UserProgramError::UnsupportedTerminal),
+            None => self.run_with_pipes(&mut command, plan.stdin).await,
+        };
+
+        match outcome {
+            Ok(Outcome::Exited(exit)) => self.finish(ProgramState::Exited, exit.code()),
+            Ok(Outcome::Stopped(exit)) => self.finish(ProgramState::Stopped, exit.code()),
+            Ok(Outcome::TimedOut(exit)) => self.finish(ProgramState::TimedOut, exit.code()),
+            Err(err) => self.fail(err, None),
+        }
+    }
+
+    /// Runs a build step to completion, streaming its stdout and stderr as build output
+    async fn build(&mut self, mut step: BuildStep) -> Result<Outcome, UserProgramError> {
+        let mut child = own_process_group(&mut step.command)
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|_| UserProgramError::FailedBuild { tool: step.tool })?;
+        let mut group = GroupGuard::new(&child);
+        let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+
+        let mut input_open = true;
+        let outcome = loop {
+            tokio::select! {
+                Some(line) = stdout.next() => self.emit(ProgramEvent::Build(read_line(line))),
+                Some(line) = stderr.next() => self.emit(ProgramEvent::Build(read_line(line))),
+                input = self.input.recv(), if input_open => match input {
+                    Some(ProgramInput::Stop) => {
+                        if let Err(err) = kill(&mut child) {
+                            break Err(err);
+                        }
+                        break (&mut child).await.map(Outcome::Stopped);
+                    }
+                    Some(input) => self.pending_input.push(input),
+                    None => input_open = false,
+                },
+                exit = &mut child => break exit.map(Outcome::Exited),
+            }
+        };
+        group.waited();
+
+        let rest = async {
+            while let Some(line) = stdout.next().await {
+                self.emit(ProgramEvent::Build(read_line(line)));
+            }
+            while let Some(line) = stderr.next().await {
+                self.emit(ProgramEvent::Build(read_line(line)));
+            }
+        };
+        let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, rest).await;
+        outcome.map_err(|_| UserProgramError::FailedKill)
+    }
+
+    /// Runs the program with piped stdio
+    async fn run_with_pipes(
+        &mut self,
+        command: &mut Command,
+        stdin_file: Option<PathBuf>,
+    ) -> Result<Outcome, UserProgramError> {
+        let stdin = match stdin_file {
+            Some(path) => {
+                Stdio::from(std::fs::File::open(path).map_err(|_| UserProgramError::FailedRun)?)
+            }
+            None => Stdio::piped(),
+        };
+        let mut child = own_process_group(command)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|_| UserProgramError::FailedRun)?;
+        let mut group = GroupGuard::new(&child);
+        self.started(&child);
+
+        let mut stdin = child.stdin.take();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+        for input in std::mem::take(&mut self.pending_input) {
+            self.write_input(stdin.as_mut(), input).await;
+        }
+
+        let mut input_open = true;
+        let mut deadline = self.deadline();
+        let outcome = loop {
+            if self.past_deadline(&deadline) {
+                break terminate(&mut child).await.map(Outcome::TimedOut);
+            }
+            tokio::select! {
+                Some(line) = stdout.next() => self.emit_output(ProgramEvent::Stdout(read_line(line))),
+                Some(line) = stderr.next() => self.emit_output(ProgramEvent::Stderr(read_line(line))),
+                input = self.input.recv(), if input_open => match input {
+                    Some(ProgramInput::Stop) => break terminate(&mut child).await.map(Outcome::Stopped),
+                    Some(input) => self.write_input(stdin.as_mut(), input).await,
+                    None => input_open = false,
+                },
+                _ = &mut deadline, if self.limits.timeout.is_some() => {
+                    break terminate(&mut child).await.map(Outcome::TimedOut)
+                }
+                exit = &mut child => break exit.map(Outcome::Exited),
+            }
+        };
+        group.waited();
+
+        let rest = async {
+            while let Some(line) = stdout.next().await {
+                self.emit_output(ProgramEvent::Stdout(read_line(line)));
+            }
+            while let Some(line) = stderr.next().await {
+                self.emit_output(ProgramEvent::Stderr(read_line(line)));
+            }
+        };
+        let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, rest).await;
+        outcome.map_err(|_| UserProgramError::FailedKill)
+    }
+
+    #[cfg(unix)]
+    async fn run_in_terminal(
+        &mut self,
+        command: &mut Command,
+        size: WindowSize,
+    ) -> Result<Outcome, UserProgramError> {
+        let pty = Pty::attach(command, size).map_err(|_| UserProgramError::FailedRun)?;
+        let mut output = pty.output().map_err(|_| UserProgramError::FailedRun)?;
+        let mut child = command.spawn().map_err(|_| UserProgramError::FailedRun)?;
+        let mut group = GroupGuard::new(&child);
+        self.started(&child);
+
+        for input in std::mem::take(&mut self.pending_input) {
+            self.write_terminal_input(&pty, input);
+        }
+
+        let mut input_open = true;
+        let mut deadline = self.deadline();
+        let outcome = loop {
+            if self.past_deadline(&deadline) {
+                break terminate(&mut child).await.map(Outcome::TimedOut);
+            }
+            tokio::select! {
+                Some(bytes) = output.recv() => self.emit_output(ProgramEvent::Terminal(bytes)),
+                input = self.input.recv(), if input_open => match input {
+                    Some(ProgramInput::Stop) => break terminate(&mut child).await.map(Outcome::Stopped),
+                    Some(input) => self.write_terminal_input(&pty, input),
+                    None => input_open = false,
+                },
+                _ = &mut deadline, if self.limits.timeout.is_some() => {
+                    break terminate(&mut child).await.map(Outcome::TimedOut)
+                }
+                exit = &mut child => break exit.map(Outcome::Exited),
+            }
+        };
+        group.waited();
+
+        let rest = async {
+            while let Some(bytes) = output.recv().await {
+                self.emit_output(ProgramEvent::Terminal(bytes));
+            }
+        };
+        let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, rest).await;
+        outcome.map_err(|_| UserProgramError::FailedKill)
+    }
+
+    /// Writes input from the client to the program's stdin, which is dropped if stdin is read from a file
+    async fn write_input(&self, stdin: Option<&mut ChildStdin>, input: ProgramInput) {
+        let (stdin, bytes) = match (stdin, input_bytes(input)) {
+            (Some(stdin), Some(bytes)) => (stdin, bytes),
+            _ => return,
+        };
+        match stdin.write_all(&bytes).await {
+            Ok(()) => self.emit(ProgramEvent::Input(
+                String::from_utf8_lossy(&bytes).to_string(),
+            )),
+            Err(er) => error!(error = %er, "Error writing to program"),
+        }
+    }
+
+    #[cfg(unix)]
+    fn write_terminal_input(&self, pty: &Pty, input: ProgramInput) {
+        if let ProgramInput::Resize(size) = input {
+            if let Err(err) = pty.resize(size) {
+                error!(error = %err, "Error resizing program terminal");
+            }
+        } else if let Some(bytes) = input_bytes(input) {
+            self.emit(ProgramEvent::Input(
+                String::from_utf8_lossy(&bytes).to_string(),
+            ));
+            pty.write(bytes);
+        }
+    }
+
+    fn emit(&self, event: ProgramEvent) {
+        // the run carries on whether or not anyone is listening
+        let _ = self.events.send(event);
+    }
+
+    /// Emits the program's output until the output limit is reached, cutting off the output that reaches it
+    fn emit_output(&mut self, event: ProgramEvent) {
+        let left = match self.output_left {
+            Some(left) => left,
+            None => return self.emit(event),
+        };
+        let size = match &event {
+            ProgramEvent::Stdout(line) | ProgramEvent::Stderr(line) => line.len() + 1,
+            ProgramEvent::Terminal(bytes) => bytes.len(),
+            _ => 0,
+        };
+        if size <= left {
+            self.output_left = Some(left - size);
+            return self.emit(event);
+        }
+
+        self.output_left = Some(0);
+        if !self.status.truncated {
+            self.status.truncated = true;
+            let _ = self.status_tx.broadcast(self.status.clone());
+        }
+        if left > 0 {
+            self.emit(match event {
+                ProgramEvent::Stdout(line) => ProgramEvent::Stdout(truncate(line, left)),
+                ProgramEvent::Stderr(line) => ProgramEvent::Stderr(truncate(line, left)),
+                ProgramEvent::Terminal(mut bytes) => {
+                    bytes.truncate(left);
+                    ProgramEvent::Terminal(bytes)
+                }
+                event => event,
+            });
+        }
+    }
+
+    /// Fires once the program has used up its time limit, if it has one
+    fn deadline(&self) -> Delay {
+        delay_for(self.limits.timeout.unwrap_or_default())
+    }
+
+    /// A program flooding its output can starve the deadline's timer, so it is also checked between reads
+    fn past_deadline(&self, deadline: &Delay) -> bool {
+        self.limits.timeout.is_some() && Instant::now() >= deadline.deadline()
+    }
+
+    fn started(&mut self, child: &Child) {
+        self.status.pid = Some(child.id());
+        self.status.state = ProgramState::Running;
+        info!(pid = child.id(), "Program started");
+        let _ = self.status_tx.broadcast(self.status.clone());
+    }
+
+    fn finish(mut self, state: ProgramState, exit_code: Option<i32>) {
+        info!(
+            ?state,
+            ?exit_code,
+            truncated = self.status.truncated,
+            "Program finished"
+        );
+        self.status.state = state;
+        self.status.exit_code = exit_code;
+        let _ = self.status_tx.broadcast(self.status.clone());
+        self.emit(ProgramEvent::Exited(self.status.clone()));
+    }
+
+    fn fail(self, err: UserProgramError, exit_code: Option<i32>) {
+        info!(error = %err, "Program failed");
+        self.emit(ProgramEvent::Failed(err));
+        self.finish(ProgramState::Failed, exit_code);
+    }
+}
+
+/// Cuts a line down to at most `len` bytes, without splitting a character
+fn truncate(mut line: String, len: usize) -> String {
+    let mut end = len.min(line.len());
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    line.truncate(end);
+    line
+}
+
+fn read_line(line: std::io::Result<String>) -> String {
+    line.unwrap_or_else(|_| "Failed to read from user program".to_string())
+}
+
+/// Bytes to write to the program for input from the client, if the input is meant for the program
+fn input_bytes(input: ProgramInput) -> Option<Vec<u8>> {
+    match input {
+        ProgramInput::Line(text) => Some(format!("{}\n", text).into_bytes()),
+        ProgramInput::Bytes(bytes) => Some(bytes),
+        ProgramInput::Resize(_) | ProgramInput::Stop => None,
+    }
+}
+
+/// Sends SIGTERM to the process's group, falling back to SIGKILL after the grace period
+async fn terminate(child: &mut Child) -> std::io::Result<ExitStatus> {
+    #[cfg(unix)]
+    {
+        signal_group(child.id(), libc::SIGTERM);
+        if let Ok(exit) = tokio::time::timeout(STOP_GRACE_PERIOD, &mut *child).await {
+            return exit;
+        }
+    }
+    kill(child)?;
+    child.await
+}
+
+/// Kills the process along with the rest of its group
+fn kill(child: &mut Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    signal_group(child.id(), libc::SIGKILL);
+    child.kill()
+}
+
+/// Starts the command in a process group of its own, so stopping it also stops the processes it
+/// starts, such as the program a run template's shell runs
+fn own_process_group(command: &mut Command) -> &mut Command {
+    // SAFETY: only async-signal-safe functions are called between fork and exec
+    #[cfg(unix)]
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    command
+}
+
+/// Sends a signal to the process group a child leads. Programs run in a terminal lead their own
+/// session, and so their own group, too
+#[cfg(unix)]
+fn signal_group(pid: u32, signal: libc::c_int) {
+    // SAFETY: the pid belongs to a child we have not yet reaped, so its group cannot have been reused
+    unsafe { libc::killpg(pid as libc::pid_t, signal) };
+}
+
+/// Kills a child's process group if the run is dropped before the child has been waited for,
+/// as `kill_on_drop` only kills the child itself
+struct GroupGuard {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pid: Option<u32>,
+}
+
+impl GroupGuard {
+    fn new(child: &Child) -> Self {
+        GroupGuard {
+            pid: Some(child.id()),
+        }
+    }
+
+    /// Called once the child has been waited for, after which its pid may be reused
+    fn waited(&mut self) {
+        self.pid = None;
+    }
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            signal_group(pid, libc::SIGKILL);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use super::{
+    code_runner::RunOptions,
+    program_handle::{BuildStep, RunPlan},
+    user_program::UserProgramError,
+};
+
+/// Gradle init script adding a task that builds a project and writes out its runtime classpath
+const GRADLE_CLASSPATH_INIT_SCRIPT: &str = r#"allprojects {
+    plugins.withId('java') {
+        tasks.register('lspProxyClasspath') {
+            dependsOn 'classes'
+            doLast {
+                new File(project.property('lspProxyClasspathFile')).text =
+                    project.sourceSets.main.runtimeClasspath.asPath
+            }
+        }
+    }
+}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuildTool {
+    Maven,
+    Gradle,
+}
+
+impl BuildTool {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildTool::Maven => "maven",
+            BuildTool::Gradle => "gradle",
+        }
+    }
+
+    fn manifests(self) -> &'static [&'static str] {
+        match self {
+            BuildTool::Maven => &["pom.xml"],
+            BuildTool::Gradle => &["build.gradle", "build.gradle.kts"],
+        }
+    }
+
+    /// Command for the tool, preferring the project's wrapper script if it has one
+    fn command(self, root: &Path) -> Command {
+        let (wrapper, tool) = match (self, cfg!(windows)) {
+            (BuildTool::Maven, false) => ("mvnw", "mvn"),
+            (BuildTool::Maven, true) => ("mvnw.cmd", "mvn.cmd"),
+            (BuildTool::Gradle, false) => ("gradlew", "gradle"),
+            (BuildTool::Gradle, true) => ("gradlew.bat", "gradle.bat"),
+        };
+        let wrapper = root.join(wrapper);
+        let mut command = if wrapper.is_file() {
+            Command::new(wrapper)
+        } else {
+            Command::new(tool)
+        };
+        command.current_dir(root);
+        command
+    }
+}
+
+/// A Maven or Gradle project
+#[derive(Debug, PartialEq)]
+pub struct Project {
+    pub tool: BuildTool,
+    /// Directory containing the project's build file
+    pub root: PathBuf,
+}
+
+impl Project {
+    /// Finds the closest project that the file or directory belongs to, without looking outside of the workspace
+    pub fn detect(file_path: &Path, workspace_dir: &Path) -> Option<Project> {
+        let workspace_dir = workspace_dir.canonicalize().ok()?;
+        let file_path = file_path.canonicalize().ok()?;
+
+        for dir in file_path.ancestors() {
+            if !dir.starts_with(&workspace_dir) {
+                break;
+            }
+            for tool in [BuildTool::Maven, BuildTool::Gradle].iter() {
+                if tool.manifests().iter().any(|m| dir.join(m).is_file()) {
+                    return Some(Project {
+                        tool: *tool,
+                        root: dir.to_path_buf(),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// The main class declared in the project's build file, if there is one
+    pub async fn main_class(&self) -> Option<String> {
+        for manifest in self.tool.manifests() {
+            if let Ok(contents) = tokio::fs::read_to_string(self.root.join(manifest)).await {
+                return match self.tool {
+                    BuildTool::Maven => maven_main_class(&contents),
+                    BuildTool::Gradle => gradle_main_class(&contents),
+                };
+            }
+        }
+        None
+    }
+
+    /// Command that runs the project's tests offline, along with the directory its JUnit XML reports are written to
+    pub fn test_command(&self, repo_path: Option<&Path>) -> (Command, PathBuf) {
+        let mut command = self.offline_command(repo_path);
+        match self.tool {
+            BuildTool::Maven => {
+                command.arg("-Dmaven.test.failure.ignore=true").arg("test");
+                (command, self.root.join("target").join("surefire-reports"))
+            }
+            BuildTool::Gradle => {
+                // keep going past failing test tasks, so every module's results are reported
+                command.arg("test").arg("--continue");
+                (
+                    command,
+                    self.root.join("build").join("test-results").join("test"),
+                )
+            }
+        }
+    }
+
+    /// The build tool's command with its options for non-interactive offline builds
+    fn offline_command(&self, repo_path: Option<&Path>) -> Command {
+        let mut command = self.tool.command(&self.root);
+        command.arg("--offline");
+        match self.tool {
+            BuildTool::Maven => {
+                command.arg("--batch-mode");
+                if let Some(repo_path) = repo_path {
+                    command.arg(format!("-Dmaven.repo.local={}", repo_path.display()));
+                }
+            }
+            BuildTool::Gradle => {
+                command.arg("--console=plain");
+                if let Some(repo_path) = repo_path {
+                    command.arg("--gradle-user-home").arg(repo_path);
+                }
+            }
+        }
+        command
+    }
+
+    /// Plans a run that builds the project offline, then launches the main class on the project's runtime classpath.
+    /// Dependencies are resolved from `repo_path` when given, the local Maven repository or Gradle user home respectively.
+    pub fn plan(
+        &self,
+        file: String,
+        main_class: String,
+        build_dir: &Path,
+        repo_path: Option<&Path>,
+        workspace_dir: &str,
+        options: &RunOptions,
+    ) -> Result<RunPlan, UserProgramError> {
+        std::fs::create_dir_all(build_dir).map_err(|_| UserProgramError::FailedBuild {
+            tool: self.tool.name().to_string(),
+        })?;
+        let classpath_file = build_dir.join(format!("{}.classpath", self.tool.name()));
+        // a classpath left over from an earlier build must not be mistaken for this one's
+        let _ = std::fs::remove_file(&classpath_file);
+
+        let mut command = self.offline_command(repo_path);
+        let mut extra_classpath = None;
+        match self.tool {
+            BuildTool::Maven => {
+                command
+                    .arg("-DskipTests")
+                    .arg(format!("-Dmdep.outputFile={}", classpath_file.display()))
+                    .arg("-Dmdep.includeScope=runtime")
+                    .arg("compile")
+                    .arg("dependency:build-classpath");
+                // the dependency plugin only lists dependencies, not the project's own classes
+                extra_classpath = Some(self.root.join("target").join("classes"));
+            }
+            BuildTool::Gradle => {
+                let init_script = build_dir.join("lsp-proxy-classpath.gradle");
+                std::fs::write(&init_script, GRADLE_CLASSPATH_INIT_SCRIPT).map_err(|_| {
+                    UserProgramError::FailedBuild {
+                        tool: self.tool.name().to_string(),
+                    }
+                })?;
+                command
+                    .arg("--init-script")
+                    .arg(&init_script)
+                    .arg(format!(
+                        "-PlspProxyClasspathFile={}",
+                        classpath_file.display()
+                    ))
+                    .arg("lspProxyClasspath");
+            }
+        }
+
+        let workspace_dir = workspace_dir.to_string();
+        let jvm_args = options.jvm_args();
+        let launch = move || {
+            let classpath = std::fs::read_to_string(&classpath_file)
+                .map_err(|_| UserProgramError::FailedRun)?;
+            let classpath = std::env::join_paths(
+                extra_classpath
+                    .into_iter()
+                    .chain(std::env::split_paths(classpath.trim())),
+            )
+            .map_err(|_| UserProgramError::FailedRun)?;
+
+            let mut command = Command::new("java");
+            command
+                .kill_on_drop(true)
+                .current_dir(workspace_dir)
+                .args(jvm_args)
+                .arg("-cp")
+                .arg(classpath)
+                .arg(main_class);
+            Ok(command)
+        };
+
+        Ok(RunPlan {
+            file,
+            build: vec![BuildStep {
+                tool: self.tool.name().to_string(),
+                command,
+            }],
+            launch: Box::new(launch),
+            terminal: options.terminal_size(),
+            args: options.args.clone(),
+            env: options.env.clone(),
+            stdin: options.stdin.as_ref().map(PathBuf::from),
+            build_dir: None,
+        })
+    }
+}
+
+fn maven_main_class(pom: &str) -> Option<String> {
+    let start = pom.find("<mainClass>")? + "<mainClass>".len();
+    let end = start + pom[start..].find("</mainClass>")?;
+    let main_class = pom[start..end].trim();
+    if main_class.is_empty() || main_class.starts_with("${") {
+        None
+    } else {
+        Some(main_class.to_string())
+    }
+}
+
+/// Reads `mainClassName = '...'`, `mainClass = '...'` or `mainClass.set("...")` from a Gradle build file
+fn gradle_main_class(build_file: &str) -> Option<String> {
+    build_file.match_indices("mainClass").find_map(|(i, _)| {
+        let rest = build_file[i + "mainClass".len()..].trim_start_matches("Name");
+        let rest = rest.trim_start();
+        let rest = rest
+            .strip_prefix('=')
+            .or_else(|| rest.strip_prefix(".set("))?
+            .trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let rest = &rest[1..];
+        Some(rest[..rest.find(quote)?].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gradle_main_class, maven_main_class, BuildTool, Project};
+    use tempfile::tempdir;
+
+    #[test]
+    fn closest_project_to_file_is_detected() {
+        let workspace = tempdir().expect("couldn't create directory for testing!");
+        let module = workspace.path().join("app");
+        let sources = module.join("src/main/java/com/example");
+        std::fs::create_dir_all(&sources).unwrap();
+        std::fs::write(workspace.path().join("pom.xml"), "<project/>").unwrap();
+        std::fs::write(module.join("build.gradle.kts"), "").unwrap();
+        let file = sources.join("Main.java");
+        std::fs::write(&file, "").unwrap();
+
+        let project = Project::detect(&file, workspace.path()).expect("no project found");
+
+        assert_eq!(project.tool, BuildTool::Gradle);
+        assert_eq!(project.root, module.canonicalize().unwrap());
+    }
+
+    #[test]
+    fn projects_outside_workspace_are_ignored() {
+        let dir = tempdir().expect("couldn't create directory for testing!");
+        let workspace = dir.path().join("workspace");
+        std::fs::create_dir_all(&workspace).unwrap();
+        std::fs::write(dir.path().join("pom.xml"), "<project/>").unwrap();
+        let file = workspace.join("Main.java");
+        std::fs::write(&file, "").unwrap();
+
+        assert_eq!(Project::detect(&file, &workspace), None);
+    }
+
+    #[test]
+    fn main_class_is_read_from_pom() {
+        let pom = "<project><build><plugins><plugin><configuration><archive><manifest>\n<mainClass> com.example.App </mainClass>\n</manifest></archive></configuration></plugin></plugins></build></project>";
+
+        assert_eq!(maven_main_class(pom), Some("com.example.App".to_string()));
+        assert_eq!(maven_main_class("<mainClass>${main}</mainClass>"), None);
+    }
+
+    #[test]
+    fn main_class_is_read_from_gradle_build() {
+        assert_eq!(
+            gradle_main_class("application {\n    mainClassName = 'com.example.App'\n}"),
+            Some("com.example.App".to_string())
+        );
+        assert_eq!(
+            gradle_main_class("application {\n    mainClass.set(\"com.example.Kts\")\n}"),
+            Some("com.example.Kts".to_string())
+        );
+        assert_eq!(gradle_main_class("plugins { id 'java' }"), None);
+    }
+}
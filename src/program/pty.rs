@@ -0,0 +1,110 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::io::{AsRawFd, FromRawFd},
+    process::Stdio,
+    sync::mpsc,
+    thread,
+};
+use tokio::{process::Command, sync::mpsc as async_mpsc};
+use tracing::error;
+
+use super::user_program::WindowSize;
+
+/// Master side of a pseudo-terminal that a user program is attached to
+#[derive(Debug)]
+pub struct Pty {
+    master: File,
+    input: mpsc::Sender<Vec<u8>>,
+}
+
+impl Pty {
+    /// Opens a new pseudo-terminal as the command's controlling terminal
+    pub fn attach(command: &mut Command, size: WindowSize) -> io::Result<Pty> {
+        let (mut master_fd, mut slave_fd) = (0, 0);
+        let mut size = to_winsize(size);
+        // SAFETY: the out pointers are valid for the duration of the call, name and termios are optional
+        let res = unsafe {
+            libc::openpty(
+                &mut master_fd,
+                &mut slave_fd,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::addr_of_mut!(size),
+            )
+        };
+        if res == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty succeeded, so both descriptors are open and owned by us
+        let (master, slave) =
+            unsafe { (File::from_raw_fd(master_fd), File::from_raw_fd(slave_fd)) };
+
+        command
+            .env("TERM", "xterm-256color")
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: only async-signal-safe functions are called between fork and exec
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let (input, input_rx) = mpsc::channel::<Vec<u8>>();
+        let mut writer = master.try_clone()?;
+        thread::spawn(move || {
+            for bytes in input_rx {
+                if writer.write_all(&bytes).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Pty { master, input })
+    }
+
+    /// Stream of raw output from the terminal, ending once the program has exited
+    pub fn output(&self) -> io::Result<async_mpsc::UnboundedReceiver<Vec<u8>>> {
+        let (output, output_rx) = async_mpsc::unbounded_channel();
+        let mut reader = self.master.try_clone()?;
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // reading fails with EIO once every handle to the slave side is closed
+            while let Ok(read) = reader.read(&mut buf) {
+                if read == 0 || output.send(buf[..read].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(output_rx)
+    }
+
+    pub fn write(&self, bytes: Vec<u8>) {
+        if self.input.send(bytes).is_err() {
+            error!("Error writing to program terminal");
+        }
+    }
+
+    pub fn resize(&self, size: WindowSize) -> io::Result<()> {
+        let size = to_winsize(size);
+        // SAFETY: the master descriptor is open for as long as self is alive
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &size) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+fn to_winsize(size: WindowSize) -> libc::winsize {
+    libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
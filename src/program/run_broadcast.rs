@@ -0,0 +1,194 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use super::program_handle::ProgramEvent;
+
+/// Bytes of output kept for observers that attach after the run has started
+const MAX_BACKLOG_BYTES: usize = 256 * 1024;
+
+/// Shares a run's events with observers, starting each observer with the output so far
+#[derive(Debug, Clone, Default)]
+pub struct RunBroadcast {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    backlog: VecDeque<ProgramEvent>,
+    backlog_bytes: usize,
+    observers: Vec<UnboundedSender<ProgramEvent>>,
+    finished: bool,
+    input_claimed: bool,
+}
+
+/// The right to send a run input as an observer, given up when dropped
+#[derive(Debug)]
+pub struct InputClaim(RunBroadcast);
+
+impl RunBroadcast {
+    /// Passes a run's events on to its client, sharing them with observers on the way
+    pub fn tap(events: UnboundedReceiver<ProgramEvent>) -> (Self, UnboundedReceiver<ProgramEvent>) {
+        let broadcast = RunBroadcast::default();
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let shared = broadcast.clone();
+        actix::spawn(async move {
+            let mut events = events;
+            while let Some(event) = events.recv().await {
+                shared.publish(&event);
+                // observers carry on watching whether or not the run's client is still listening
+                let _ = events_tx.send(event);
+            }
+            shared.lock().finish();
+        });
+        (broadcast, events_rx)
+    }
+
+    /// Events of the run from its start, ending once the run is over
+    pub fn subscribe(&self) -> UnboundedReceiver<ProgramEvent> {
+        let (observer, events) = mpsc::unbounded_channel();
+        let mut shared = self.lock();
+        for event in &shared.backlog {
+            let _ = observer.send(event.clone());
+        }
+        if !shared.finished {
+            shared.observers.push(observer);
+        }
+        events
+    }
+
+    /// Claims the right to send the run input, which only one observer can hold at a time
+    pub fn claim_input(&self) -> Option<InputClaim> {
+        let mut shared = self.lock();
+        if shared.input_claimed {
+            return None;
+        }
+        shared.input_claimed = true;
+        Some(InputClaim(self.clone()))
+    }
+
+    fn publish(&self, event: &ProgramEvent) {
+        let mut shared = self.lock();
+        if let ProgramEvent::Input(_) = event {
+            // observers see the run as its client does, which doesn't have its input echoed
+            return;
+        }
+        shared
+            .observers
+            .retain(|observer| observer.send(event.clone()).is_ok());
+        shared.backlog_bytes += event_size(event);
+        shared.backlog.push_back(event.clone());
+        while shared.backlog_bytes > MAX_BACKLOG_BYTES {
+            match shared.backlog.pop_front() {
+                Some(dropped) => shared.backlog_bytes -= event_size(&dropped),
+                None => break,
+            }
+        }
+        if let ProgramEvent::Exited(_) = event {
+            shared.finish();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Shared {
+    /// Ends the observers' streams, as there is nothing more to send them
+    fn finish(&mut self) {
+        self.finished = true;
+        self.observers.clear();
+    }
+}
+
+impl Drop for InputClaim {
+    fn drop(&mut self) {
+        self.0.lock().input_claimed = false;
+    }
+}
+
+fn event_size(event: &ProgramEvent) -> usize {
+    match event {
+        ProgramEvent::Build(line) | ProgramEvent::Stdout(line) | ProgramEvent::Stderr(line) => {
+            line.len()
+        }
+        ProgramEvent::Terminal(bytes) => bytes.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunBroadcast;
+    use crate::program::program_handle::{ProgramEvent, ProgramState, ProgramStatus};
+    use tokio::sync::mpsc;
+
+    fn lines(events: &[ProgramEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                ProgramEvent::Stdout(line) => Some(line.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn observers_get_backlog_then_live_output() {
+        let (run_tx, run_rx) = mpsc::unbounded_channel();
+        let (broadcast, mut client) = RunBroadcast::tap(run_rx);
+
+        run_tx
+            .send(ProgramEvent::Stdout("first".to_string()))
+            .unwrap();
+        assert!(client.recv().await.is_some());
+        let mut observer = broadcast.subscribe();
+        run_tx
+            .send(ProgramEvent::Input("typed\n".to_string()))
+            .unwrap();
+        run_tx
+            .send(ProgramEvent::Stdout("second".to_string()))
+            .unwrap();
+        run_tx
+            .send(ProgramEvent::Exited(ProgramStatus {
+                id: 1,
+                pid: None,
+                file: "Main.java".to_string(),
+                started_at: 0,
+                state: ProgramState::Exited,
+                exit_code: Some(0),
+                truncated: false,
+            }))
+            .unwrap();
+
+        let mut observed = Vec::new();
+        while let Some(event) = observer.recv().await {
+            observed.push(event);
+        }
+        assert_eq!(lines(&observed), vec!["first", "second"]);
+        assert!(matches!(observed.last(), Some(ProgramEvent::Exited(_))));
+
+        // observers attaching after the run has finished get all of it
+        let mut late = broadcast.subscribe();
+        let mut observed = Vec::new();
+        while let Some(event) = late.recv().await {
+            observed.push(event);
+        }
+        assert_eq!(lines(&observed), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn only_one_observer_can_send_input_at_a_time() {
+        let broadcast = RunBroadcast::default();
+
+        let claim = broadcast.claim_input();
+        assert!(claim.is_some());
+        assert!(broadcast.claim_input().is_none());
+
+        drop(claim);
+        assert!(broadcast.claim_input().is_some());
+    }
+}
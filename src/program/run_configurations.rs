@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use super::user_program::UserProgramError;
+
+/// Where run configurations are saved, relative to the root of the codebase
+const RUN_CONFIGURATIONS_FILE: &str = ".lsp-proxy/run-configurations.json";
+
+/// Saved arguments, environment and stdin to run a file with
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunConfiguration {
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Workspace file to pipe to the program's stdin
+    #[serde(default)]
+    pub stdin: Option<String>,
+    /// Class to launch, instead of the one worked out from the project or file
+    #[serde(default)]
+    pub main: Option<String>,
+}
+
+impl RunConfiguration {
+    /// Checks that the configuration could be run within the workspace
+    pub fn validate(&self, workspace_dir: &str) -> Result<(), UserProgramError> {
+        if self.name.trim().is_empty() {
+            return Err(invalid("run configurations need a name"));
+        }
+        validate_env(&self.env)?;
+        if let Some(stdin) = &self.stdin {
+            resolve_stdin(workspace_dir, stdin)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run configurations saved for a file, in the order they were first saved
+pub async fn for_file(
+    workspace_dir: &str,
+    file: &str,
+) -> Result<Vec<RunConfiguration>, UserProgramError> {
+    let mut saved = load(workspace_dir).await?;
+    Ok(saved.remove(file).unwrap_or_default())
+}
+
+/// Finds a file's run configuration by name
+pub async fn find(
+    workspace_dir: &str,
+    file: &str,
+    name: &str,
+) -> Result<RunConfiguration, UserProgramError> {
+    for_file(workspace_dir, file)
+        .await?
+        .into_iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| invalid(&format!("no run configuration named {}", name)))
+}
+
+/// Saves a file's run configuration and returns the file's configurations. Callers serialise saves
+pub async fn save(
+    workspace_dir: &str,
+    file: &str,
+    configuration: RunConfiguration,
+) -> Result<Vec<RunConfiguration>, UserProgramError> {
+    configuration.validate(workspace_dir)?;
+    let mut saved = load(workspace_dir).await?;
+    let configurations = saved.entry(file.to_string()).or_default();
+    match configurations
+        .iter_mut()
+        .find(|c| c.name == configuration.name)
+    {
+        Some(existing) => *existing = configuration,
+        None => configurations.push(configuration),
+    }
+    let configurations = configurations.clone();
+
+    let path = Path::new(workspace_dir).join(RUN_CONFIGURATIONS_FILE);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|_| UserProgramError::FailedSavingRunConfiguration)?;
+    }
+    let contents = serde_json::to_string_pretty(&saved)
+        .map_err(|_| UserProgramError::FailedSavingRunConfiguration)?;
+    // written beside the saved file and renamed over it, so a failed write can't leave it half written
+    let temp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&temp_path, contents)
+        .await
+        .map_err(|_| UserProgramError::FailedSavingRunConfiguration)?;
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .map_err(|_| UserProgramError::FailedSavingRunConfiguration)?;
+    Ok(configurations)
+}
+
+/// Every saved run configuration, keyed by the path of the file they run
+async fn load(
+    workspace_dir: &str,
+) -> Result<BTreeMap<String, Vec<RunConfiguration>>, UserProgramError> {
+    match tokio::fs::read_to_string(Path::new(workspace_dir).join(RUN_CONFIGURATIONS_FILE)).await {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map_err(|_| invalid("saved run configurations could not be read")),
+        Err(_) => Ok(BTreeMap::new()),
+    }
+}
+
+/// Resolves a stdin file relative to the workspace, which it must not be outside of
+pub fn resolve_stdin(workspace_dir: &str, stdin: &str) -> Result<PathBuf, UserProgramError> {
+    let workspace_dir = Path::new(workspace_dir)
+        .canonicalize()
+        .map_err(|_| invalid("workspace could not be found"))?;
+    let path = workspace_dir
+        .join(stdin)
+        .canonicalize()
+        .map_err(|_| invalid(&format!("stdin file {} does not exist", stdin)))?;
+    if !path.starts_with(&workspace_dir) || !path.is_file() {
+        return Err(invalid(&format!(
+            "stdin file {} is not a file in the workspace",
+            stdin
+        )));
+    }
+    Ok(path)
+}
+
+/// Checks that every variable name could be set in a process's environment
+pub fn validate_env(env: &BTreeMap<String, String>) -> Result<(), UserProgramError> {
+    let invalid_name = env
+        .keys()
+        .find(|name| name.is_empty() || name.contains(['=', '\0']));
+    let invalid_value = env.values().any(|value| value.contains('\0'));
+    match invalid_name {
+        Some(name) => Err(invalid(&format!(
+            "{:?} is not a valid environment variable name",
+            name
+        ))),
+        None if invalid_value => Err(invalid("environment variables can't contain NUL")),
+        None => Ok(()),
+    }
+}
+
+fn invalid(cause: &str) -> UserProgramError {
+    UserProgramError::InvalidRunOptions {
+        cause: cause.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_stdin, save, validate_env, RunConfiguration};
+    use std::collections::BTreeMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn stdin_must_be_a_file_in_the_workspace() {
+        let dir = tempdir().expect("couldn't create directory for testing!");
+        let workspace = dir.path().join("workspace");
+        std::fs::create_dir_all(workspace.join("inputs")).unwrap();
+        std::fs::write(workspace.join("inputs/in.txt"), "input").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+        let workspace = workspace.to_str().unwrap();
+
+        assert!(resolve_stdin(workspace, "inputs/in.txt").is_ok());
+        assert!(resolve_stdin(workspace, "inputs/../inputs/in.txt").is_ok());
+        assert!(resolve_stdin(workspace, "../secret.txt").is_err());
+        assert!(resolve_stdin(workspace, "inputs").is_err());
+        assert!(resolve_stdin(workspace, "missing.txt").is_err());
+    }
+
+    #[test]
+    fn env_names_are_validated() {
+        let mut env = BTreeMap::new();
+        env.insert("GREETING".to_string(), "Hi there".to_string());
+        assert!(validate_env(&env).is_ok());
+
+        env.insert("A=B".to_string(), String::new());
+        assert!(validate_env(&env).is_err());
+    }
+
+    #[tokio::test]
+    async fn saving_replaces_configuration_with_same_name() {
+        let workspace = tempdir().expect("couldn't create directory for testing!");
+        let workspace = workspace.path().to_str().unwrap();
+        let configuration = |name: &str, args: &[&str]| RunConfiguration {
+            name: name.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            ..RunConfiguration::default()
+        };
+
+        save(workspace, "src/Main.java", configuration("small", &["1"]))
+            .await
+            .unwrap();
+        save(
+            workspace,
+            "src/Main.java",
+            configuration("large", &["1000"]),
+        )
+        .await
+        .unwrap();
+        let saved = save(workspace, "src/Main.java", configuration("small", &["2"]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            saved,
+            vec![
+                configuration("small", &["2"]),
+                configuration("large", &["1000"])
+            ]
+        );
+    }
+}
@@ -0,0 +1,349 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::File,
+    io::AsyncWriteExt,
+    sync::mpsc::{self, UnboundedReceiver},
+};
+use tracing::error;
+
+use super::{
+    program_handle::{ProgramEvent, ProgramState, ProgramStatus},
+    user_program::UserProgramError,
+};
+
+/// Bytes of transcript kept for each run
+const MAX_TRANSCRIPT_BYTES: usize = 1 << 20;
+
+/// What was run, and how the run ended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: u64,
+    pub file: String,
+    pub args: Vec<String>,
+    /// Seconds since the Unix epoch
+    pub started_at: u64,
+    /// Seconds since the Unix epoch, not set while the program is running
+    pub ended_at: Option<u64>,
+    pub state: ProgramState,
+    pub exit_code: Option<i32>,
+    /// Why the program could not be built or started
+    pub error: Option<String>,
+    /// Whether the transcript stops short of the end of the run
+    pub transcript_truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptStream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+/// Text a program read or wrote, in the order it happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub stream: TranscriptStream,
+    pub text: String,
+}
+
+/// Runs recorded under the proxy's data directory, one directory per run id
+#[derive(Debug, Clone)]
+pub struct RunHistory {
+    dir: PathBuf,
+    max_runs: usize,
+}
+
+impl RunHistory {
+    pub fn new(data_dir: &Path, max_runs: usize) -> Self {
+        RunHistory {
+            dir: data_dir.join("runs"),
+            max_runs: max_runs.max(1),
+        }
+    }
+
+    /// Highest run id that has been recorded, so run ids carry on from earlier sessions
+    pub fn last_id(&self) -> u64 {
+        std::fs::read_dir(&self.dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| e.file_name().to_str()?.parse().ok())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Records a run as its events pass through, returning the events for the run's client
+    pub fn record(
+        &self,
+        status: ProgramStatus,
+        args: Vec<String>,
+        events: UnboundedReceiver<ProgramEvent>,
+    ) -> UnboundedReceiver<ProgramEvent> {
+        let record = RunRecord {
+            id: status.id,
+            file: status.file,
+            args,
+            started_at: status.started_at,
+            ended_at: None,
+            state: status.state,
+            exit_code: None,
+            error: None,
+            transcript_truncated: false,
+        };
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let (runs_dir, max_runs) = (self.dir.clone(), self.max_runs);
+        let dir = self.dir.join(record.id.to_string());
+        actix::spawn(async move {
+            let mut recorder = Recorder::create(dir, record).await;
+            remove_old_runs(&runs_dir, max_runs).await;
+            let mut events = events;
+            while let Some(event) = events.recv().await {
+                recorder.record(&event).await;
+                // the run is recorded to the end whether or not its client is still listening
+                let _ = events_tx.send(event);
+            }
+        });
+        events_rx
+    }
+
+    /// Every recorded run, most recent first
+    pub async fn runs(&self) -> Vec<RunRecord> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut runs = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Ok(record) = read_record(&entry.path()).await {
+                runs.push(record);
+            }
+        }
+        runs.sort_by_key(|run| Reverse(run.id));
+        runs
+    }
+
+    /// Transcript of a run, as much as has been recorded if the run is still going
+    pub async fn transcript(&self, id: u64) -> Result<Vec<TranscriptEntry>, UserProgramError> {
+        let contents =
+            tokio::fs::read_to_string(self.dir.join(id.to_string()).join("transcript.jsonl"))
+                .await
+                .map_err(|_| UserProgramError::NoProgram)?;
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+/// Deletes the oldest recorded runs past the most recent `max_runs`
+async fn remove_old_runs(runs_dir: &Path, max_runs: usize) {
+    let mut entries = match tokio::fs::read_dir(runs_dir).await {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut ids: Vec<u64> = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Some(id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable_by_key(|id| Reverse(*id));
+    for id in ids.into_iter().skip(max_runs) {
+        if let Err(err) = tokio::fs::remove_dir_all(runs_dir.join(id.to_string())).await {
+            error!(run = id, error = %err, "Error deleting old run");
+        }
+    }
+}
+
+async fn read_record(run_dir: &Path) -> Result<RunRecord, ()> {
+    let contents = tokio::fs::read_to_string(run_dir.join("run.json"))
+        .await
+        .map_err(|_| ())?;
+    serde_json::from_str(&contents).map_err(|_| ())
+}
+
+/// Writes a run's record and transcript as the run goes
+struct Recorder {
+    dir: PathBuf,
+    record: RunRecord,
+    transcript: Option<File>,
+    transcript_left: usize,
+}
+
+impl Recorder {
+    async fn create(dir: PathBuf, record: RunRecord) -> Self {
+        let transcript = match tokio::fs::create_dir_all(&dir).await {
+            Ok(()) => File::create(dir.join("transcript.jsonl")).await.ok(),
+            Err(_) => None,
+        };
+        let recorder = Recorder {
+            dir,
+            record,
+            transcript,
+            transcript_left: MAX_TRANSCRIPT_BYTES,
+        };
+        recorder.save().await;
+        recorder
+    }
+
+    async fn record(&mut self, event: &ProgramEvent) {
+        match event {
+            ProgramEvent::Input(text) => self.append(TranscriptStream::Stdin, text.clone()).await,
+            ProgramEvent::Stdout(line) => {
+                self.append(TranscriptStream::Stdout, format!("{}\n", line))
+                    .await
+            }
+            ProgramEvent::Stderr(line) => {
+                self.append(TranscriptStream::Stderr, format!("{}\n", line))
+                    .await
+            }
+            ProgramEvent::Terminal(bytes) => {
+                let text = String::from_utf8_lossy(bytes).to_string();
+                self.append(TranscriptStream::Stdout, text).await
+            }
+            ProgramEvent::Build(_) => (),
+            ProgramEvent::Failed(err) => self.record.error = Some(err.to_string()),
+            ProgramEvent::Exited(status) => {
+                self.record.ended_at = Some(now());
+                self.record.state = status.state;
+                self.record.exit_code = status.exit_code;
+                self.save().await;
+            }
+        }
+    }
+
+    async fn append(&mut self, stream: TranscriptStream, text: String) {
+        let mut entry = match serde_json::to_string(&TranscriptEntry { stream, text }) {
+            Ok(entry) => entry,
+            Err(_) => return,
+        };
+        entry.push('\n');
+        if entry.len() > self.transcript_left {
+            self.transcript_left = 0;
+            if !self.record.transcript_truncated {
+                self.record.transcript_truncated = true;
+                self.save().await;
+            }
+            return;
+        }
+        self.transcript_left -= entry.len();
+        if let Some(transcript) = &mut self.transcript {
+            // flushed straight away, so the transcript can be read while the program is running
+            let written = transcript.write_all(entry.as_bytes()).await;
+            if let Err(err) = written.and(transcript.flush().await) {
+                error!(run = self.record.id, error = %err, "Error writing run transcript");
+            }
+        }
+    }
+
+    async fn save(&self) {
+        let saved = match serde_json::to_string_pretty(&self.record) {
+            Ok(record) => tokio::fs::write(self.dir.join("run.json"), record).await,
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = saved {
+            error!(run = self.record.id, error = %err, "Error saving run record");
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunHistory, TranscriptEntry, TranscriptStream};
+    use crate::program::program_handle::{ProgramEvent, ProgramState, ProgramStatus};
+    use tokio::sync::mpsc;
+
+    fn status(id: u64, state: ProgramState, exit_code: Option<i32>) -> ProgramStatus {
+        ProgramStatus {
+            id,
+            pid: None,
+            file: "src/Main.java".to_string(),
+            started_at: 0,
+            state,
+            exit_code,
+            truncated: false,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn run_is_recorded_with_interleaved_transcript() {
+        let data_dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let history = RunHistory::new(data_dir.path(), 10);
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let mut events = history.record(
+            status(7, ProgramState::Running, None),
+            vec!["--verbose".to_string()],
+            events_rx,
+        );
+
+        let sent = vec![
+            ProgramEvent::Stdout("What's your name?".to_string()),
+            ProgramEvent::Input("Ada\n".to_string()),
+            ProgramEvent::Stderr("warning".to_string()),
+            ProgramEvent::Exited(status(7, ProgramState::Exited, Some(0))),
+        ];
+        for event in sent {
+            events_tx.send(event).unwrap();
+        }
+        drop(events_tx);
+        while events.recv().await.is_some() {}
+
+        let runs = history.runs().await;
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].id, 7);
+        assert_eq!(runs[0].args, vec!["--verbose"]);
+        assert_eq!(runs[0].state, ProgramState::Exited);
+        assert_eq!(runs[0].exit_code, Some(0));
+        assert!(runs[0].ended_at.is_some());
+        assert_eq!(history.last_id(), 7);
+
+        let entry = |stream, text: &str| TranscriptEntry {
+            stream,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            history.transcript(7).await.unwrap(),
+            vec![
+                entry(TranscriptStream::Stdout, "What's your name?\n"),
+                entry(TranscriptStream::Stdin, "Ada\n"),
+                entry(TranscriptStream::Stderr, "warning\n"),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn oldest_runs_are_deleted_past_the_limit() {
+        let data_dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let history = RunHistory::new(data_dir.path(), 2);
+        for id in 1..=3 {
+            let (events_tx, events_rx) = mpsc::unbounded_channel();
+            let mut events =
+                history.record(status(id, ProgramState::Running, None), vec![], events_rx);
+            drop(events_tx);
+            while events.recv().await.is_some() {}
+        }
+
+        let ids: Vec<u64> = history.runs().await.iter().map(|run| run.id).collect();
+        assert_eq!(ids, vec![3, 2]);
+        assert_eq!(history.last_id(), 3);
+    }
+}
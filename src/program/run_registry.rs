@@ -0,0 +1,233 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::metrics::Metrics;
+
+use super::{
+    program_handle::{ProgramEvent, ProgramHandle, ProgramStatus, RunLimits, RunPlan},
+    run_broadcast::RunBroadcast,
+    run_history::RunHistory,
+    user_program::UserProgramError,
+};
+
+/// Characters in the token that lets an observer send a run input
+const INPUT_TOKEN_LENGTH: usize = 32;
+
+/// Programs started in the workspace, keyed by run id
+pub struct RunRegistry {
+    max_runs: usize,
+    next_id: AtomicU64,
+    runs: Mutex<Runs>,
+    history: RunHistory,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Default)]
+struct Runs {
+    /// Runs that had not finished when the registry was last used, oldest first
+    active: BTreeMap<u64, ActiveRun>,
+    /// Most recently started run, kept once it has finished for its final status
+    latest: Option<ProgramHandle>,
+}
+
+/// A run that is still going, with what observers need to watch it
+#[derive(Clone)]
+pub struct ActiveRun {
+    pub handle: ProgramHandle,
+    pub broadcast: RunBroadcast,
+    /// Whether the program runs in a terminal, and so sends its output as bytes
+    pub terminal: bool,
+    /// Client session the run counts against, if the client named one
+    pub session: Option<String>,
+    /// Given to the run's owner, who can pass it on to the observer allowed to send the run input
+    pub input_token: String,
+}
+
+impl RunRegistry {
+    pub fn new(max_runs: usize, history: RunHistory, metrics: Arc<Metrics>) -> Self {
+        RunRegistry {
+            max_runs: max_runs.max(1),
+            next_id: AtomicU64::new(history.last_id() + 1),
+            runs: Mutex::new(Runs::default()),
+            history,
+            metrics,
+        }
+    }
+
+    /// Starts running the plan under a new run id, unless the session is at its run limit
+    pub fn start(
+        &self,
+        session: Option<&str>,
+        plan: RunPlan,
+        limits: RunLimits,
+    ) -> Result<(ProgramHandle, UnboundedReceiver<ProgramEvent>), UserProgramError> {
+        let mut runs = self.lock();
+        runs.clean_up();
+        let session_runs = runs
+            .active
+            .values()
+            .filter(|run| run.session.as_deref() == session)
+            .count();
+        if session_runs >= self.max_runs {
+            return Err(UserProgramError::TooManyRuns {
+                max_runs: self.max_runs,
+            });
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let args = plan.args.clone();
+        let terminal = plan.terminal.is_some();
+        let (handle, events) = ProgramHandle::start(id, plan, limits);
+        let events = self.history.record(handle.status(), args, events);
+        let (broadcast, events) = RunBroadcast::tap(events);
+        let (finished, metrics) = (handle.clone(), self.metrics.clone());
+        actix::spawn(async move {
+            if let Some(status) = finished.finished().await {
+                metrics.program_run(status.state);
+            }
+        });
+
+        let run = ActiveRun {
+            handle: handle.clone(),
+            broadcast,
+            terminal,
+            session: session.map(String::from),
+            input_token: thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(INPUT_TOKEN_LENGTH)
+                .collect(),
+        };
+        runs.active.insert(id, run);
+        runs.latest = Some(handle.clone());
+        Ok((handle, events))
+    }
+
+    /// Finds a run that is still running, or the most recent run
+    pub fn get(&self, id: u64) -> Option<ProgramHandle> {
+        let runs = self.lock();
+        runs.active
+            .get(&id)
+            .map(|run| &run.handle)
+            .or_else(|| runs.latest.as_ref().filter(|latest| latest.id() == id))
+            .cloned()
+    }
+
+    /// Finds a run that is still running, to be watched by an observer
+    pub fn active(&self, id: u64) -> Option<ActiveRun> {
+        let mut runs = self.lock();
+        runs.clean_up();
+        runs.active.get(&id).cloned()
+    }
+
+    pub fn history(&self) -> &RunHistory {
+        &self.history
+    }
+
+    pub fn latest(&self) -> Option<ProgramHandle> {
+        self.lock().latest.clone()
+    }
+
+    /// Status of every run that is still running, oldest first
+    pub fn running(&self) -> Vec<ProgramStatus> {
+        let mut runs = self.lock();
+        runs.clean_up();
+        runs.active
+            .values()
+            .map(|run| run.handle.status())
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Runs> {
+        // the runs are left consistent even if a thread panics while holding the lock
+        self.runs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Runs {
+    /// Forgets runs that have finished
+    fn clean_up(&mut self) {
+        self.active.retain(|_, run| run.handle.is_running());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunRegistry;
+    use crate::{
+        metrics::Metrics,
+        program::{
+            program_handle::{RunLimits, RunPlan},
+            run_history::RunHistory,
+            runners::shell_command,
+            user_program::UserProgramError,
+        },
+    };
+    use std::{collections::BTreeMap, sync::Arc};
+
+    fn sleep_plan() -> RunPlan {
+        RunPlan {
+            file: "sleep".to_string(),
+            build: Vec::new(),
+            launch: Box::new(|| Ok(shell_command("sleep 30"))),
+            terminal: None,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            stdin: None,
+            build_dir: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn runs_over_the_session_limit_are_rejected() {
+        let data_dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+        let registry = RunRegistry::new(
+            2,
+            RunHistory::new(data_dir.path(), 10),
+            Arc::new(Metrics::new()),
+        );
+        let start = |session| registry.start(session, sleep_plan(), RunLimits::default());
+
+        let (first, _first_events) = start(Some("a")).unwrap();
+        let (second, _second_events) = start(Some("a")).unwrap();
+        assert!(matches!(
+            start(Some("a")),
+            Err(UserProgramError::TooManyRuns { max_runs: 2 })
+        ));
+        let (other, _other_events) = start(Some("b")).unwrap();
+
+        assert!(first.is_running());
+        let running: Vec<u64> = registry.running().iter().map(|s| s.id).collect();
+        assert_eq!(running, vec![first.id(), second.id(), other.id()]);
+        assert_eq!(registry.latest().map(|l| l.id()), Some(other.id()));
+
+        first.stop().await.unwrap();
+        let (third, _third_events) = start(Some("a")).unwrap();
+        assert!(registry.get(first.id()).is_none());
+
+        second.stop().await.unwrap();
+        third.stop().await.unwrap();
+        other.stop().await.unwrap();
+        assert!(registry.running().is_empty());
+        assert!(registry.get(other.id()).is_none());
+        assert_eq!(registry.get(third.id()).map(|l| l.id()), Some(third.id()));
+
+        // run ids carry on from the recorded runs
+        let registry = RunRegistry::new(
+            2,
+            RunHistory::new(data_dir.path(), 10),
+            Arc::new(Metrics::new()),
+        );
+        let (fourth, _fourth_events) = registry
+            .start(None, sleep_plan(), RunLimits::default())
+            .unwrap();
+        assert_eq!(fourth.id(), third.id() + 1);
+        fourth.stop().await.unwrap();
+    }
+}
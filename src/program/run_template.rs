@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::config::Lang;
+
+use super::{
+    code_runner::RunOptions,
+    program_handle::{BuildStep, RunPlan},
+    runners::shell_command,
+};
+
+/// Build and run steps for languages without a built in runner, as shell command lines
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunTemplate {
+    pub build: Option<String>,
+    pub run: String,
+}
+
+impl RunTemplate {
+    /// Template for running the language's files, falling back to the language's default steps
+    pub fn for_lang(lang: &Lang, build: Option<String>, run: Option<String>) -> Option<Self> {
+        match (lang, run) {
+            (Lang::Java, _) => None,
+            (_, Some(run)) => Some(RunTemplate { build, run }),
+            (Lang::C, None) => Some(RunTemplate {
+                build: build.or_else(|| Some("cc {file} -o {out}".to_string())),
+                run: "{out}".to_string(),
+            }),
+            (Lang::Custom, None) => None,
+        }
+    }
+
+    /// Plans building and running a file with the template, from the root of the codebase
+    pub fn plan(
+        &self,
+        file_path: &Path,
+        file: String,
+        workspace_dir: &str,
+        build_dir: &Path,
+        options: &RunOptions,
+    ) -> RunPlan {
+        let out = build_dir.join(if cfg!(windows) { "out.exe" } else { "out" });
+        // the commands are run from the root of the codebase, so relative paths would be resolved twice
+        let file_path = absolute(file_path);
+        let root = absolute(Path::new(workspace_dir));
+        let fill = |template: &str| {
+            template
+                .replace("{file}", &quote(&file_path))
+                .replace("{out}", &quote(&out))
+                .replace("{root}", &quote(&root))
+        };
+
+        let build = self.build.as_deref().map(|build| {
+            let mut command = shell_command(&fill(build));
+            command.current_dir(workspace_dir);
+            BuildStep {
+                tool: build.split_whitespace().next().unwrap_or(build).to_string(),
+                command,
+            }
+        });
+        let run = fill(&self.run);
+        let workspace_dir = PathBuf::from(workspace_dir);
+        RunPlan {
+            file,
+            build: build.into_iter().collect(),
+            launch: Box::new(move || {
+                let mut command = with_program_args(&run);
+                command.kill_on_drop(true).current_dir(workspace_dir);
+                Ok(command)
+            }),
+            terminal: options.terminal_size(),
+            args: options.args.clone(),
+            env: options.env.clone(),
+            stdin: options.stdin.as_ref().map(PathBuf::from),
+            build_dir: None,
+        }
+    }
+}
+
+/// Command that runs a filled in template with the platform's shell, appending the program's
+/// arguments to it as `"$@"` so they reach the program rather than the shell
+fn with_program_args(command_line: &str) -> Command {
+    if cfg!(windows) {
+        return shell_command(command_line);
+    }
+    // the shell's own name comes first, so the program's arguments fill in `$@`
+    let mut command = shell_command(&format!("{} \"$@\"", command_line));
+    command.arg("sh");
+    command
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Quotes a path for the platform's shell, so paths with spaces are kept whole
+fn quote(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(windows) {
+        format!("\"{}\"", path)
+    } else {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quote, RunTemplate};
+    use crate::config::Lang;
+    use std::path::Path;
+
+    #[test]
+    fn languages_fall_back_to_their_default_template() {
+        assert_eq!(RunTemplate::for_lang(&Lang::Java, None, None), None);
+        assert_eq!(RunTemplate::for_lang(&Lang::Custom, None, None), None);
+        assert_eq!(
+            RunTemplate::for_lang(&Lang::C, None, None),
+            Some(RunTemplate {
+                build: Some("cc {file} -o {out}".to_string()),
+                run: "{out}".to_string(),
+            })
+        );
+        assert_eq!(
+            RunTemplate::for_lang(&Lang::Custom, None, Some("python3 {file}".to_string())),
+            Some(RunTemplate {
+                build: None,
+                run: "python3 {file}".to_string(),
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_are_quoted_for_the_shell() {
+        assert_eq!(quote(Path::new("/code/my file.c")), "'/code/my file.c'");
+        assert_eq!(quote(Path::new("/code/it's.c")), r"'/code/it'\''s.c'");
+    }
+}
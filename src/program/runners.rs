@@ -1,50 +1,170 @@
-use std::{path::PathBuf, process::Stdio};
+use std::path::{Path, PathBuf};
 
-use actix_web::{web, HttpRequest, HttpResponse, Result};
+use actix_web::{
+    http::{HeaderName, HeaderValue},
+    web, HttpRequest, HttpResponse, Result,
+};
 use actix_web_actors::ws;
+use tempfile::TempDir;
 use tokio::process::Command;
 
-use crate::AppState;
+use crate::{config, AppState};
 
-use super::user_program::{UserProgram, UserProgramError};
+use super::{
+    code_runner::RunOptions,
+    java::JavaProgram,
+    program_handle::RunPlan,
+    project::Project,
+    run_template::RunTemplate,
+    user_program::{UserProgram, UserProgramError},
+};
 
-pub async fn run_java_prog(
+/// Response header holding the id of a run started over a websocket
+pub const RUN_ID_HEADER: &str = "x-run-id";
+
+/// Builds and runs a file over a websocket as a new run
+pub async fn run_prog(
     req: HttpRequest,
     stream: web::Payload,
     state: web::Data<AppState>,
     file_path: PathBuf,
     path: PathBuf,
+    options: RunOptions,
 ) -> Result<HttpResponse> {
-    let compiler = Command::new("javac")
-        .current_dir(&state.workspace_dir)
-        .arg(&file_path)
-        .output();
-
-    compiler
-        .await
-        .map_err(|_| UserProgramError::FailedCompilation)?;
-
-    if let Ok(user_program) = &mut state.user_program.try_lock() {
-        user_program.replace(UserProgram::start(
-            Command::new("java")
-                .kill_on_drop(true)
-                .current_dir(&state.workspace_dir)
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .arg(path)
-                .spawn()
-                .map_err(|_| UserProgramError::FailedRun)?,
-        ));
-
-        if user_program.is_some() {
-            let user_program = ws::start(user_program.take().unwrap(), &req, stream)
-                .map_err(|_| UserProgramError::FailedRun)?;
-
-            return Ok(user_program);
+    // checked before anything is built, so a request that isn't a websocket doesn't leave a run going
+    let mut response = ws::handshake(&req).map_err(|_| UserProgramError::NotWebSocket)?;
+    let plan = plan_prog(&state, &file_path, &path, &options).await?;
+    let terminal = plan.terminal.is_some();
+    let (handle, events) = state
+        .runs
+        .start(options.session.as_deref(), plan, state.run_limits)?;
+    let run_id = handle.id();
+    let input_token = state.runs.active(run_id).map(|run| run.input_token);
+
+    let session = state.metrics.session("program");
+    response.set_header(
+        HeaderName::from_static(RUN_ID_HEADER),
+        HeaderValue::from(run_id),
+    );
+    Ok(response.streaming(ws::WebsocketContext::create(
+        UserProgram::new(handle, events, terminal, input_token, session),
+        stream,
+    )))
+}
+
+/// Plans how to build and launch a file in the codebase's language
+pub async fn plan_prog(
+    state: &AppState,
+    file_path: &Path,
+    path: &Path,
+    options: &RunOptions,
+) -> Result<RunPlan, UserProgramError> {
+    match (&state.lang, &state.run_template) {
+        (config::Lang::Java, _) => plan_java_prog(state, file_path, path, options).await,
+        (_, Some(template)) => plan_template_prog(state, template, file_path, path, options),
+        (_, None) => Err(UserProgramError::UnsupportedLanguage),
+    }
+}
+
+/// Plans how to build and launch a Java file, each run in its own build directory
+pub async fn plan_java_prog(
+    state: &AppState,
+    file_path: &Path,
+    path: &Path,
+    options: &RunOptions,
+) -> Result<RunPlan, UserProgramError> {
+    let file = path.to_string_lossy().to_string();
+    let build_dir = run_build_dir(&state.build_dir)?;
+    let mut plan = match Project::detect(file_path, Path::new(&state.workspace_dir)) {
+        Some(project) => {
+            let main_class = match options.main.clone() {
+                Some(main_class) => main_class,
+                None => match project.main_class().await {
+                    Some(main_class) => main_class,
+                    None => JavaProgram::locate(file_path).await?.main_class,
+                },
+            };
+            project.plan(
+                file,
+                main_class,
+                build_dir.path(),
+                state.build_repo_path.as_deref(),
+                &state.workspace_dir,
+                options,
+            )?
+        }
+        None => {
+            let program = JavaProgram::locate(file_path).await?;
+            let out_dir = build_dir.path().join("java");
+            program.compile(&state.workspace_dir, &out_dir).await?;
+
+            let workspace_dir = state.workspace_dir.clone();
+            let main_class = options.main.clone().unwrap_or(program.main_class);
+            let jvm_args = options.jvm_args();
+            RunPlan {
+                file,
+                build: Vec::new(),
+                launch: Box::new(move || {
+                    let mut command = Command::new("java");
+                    command
+                        .kill_on_drop(true)
+                        .current_dir(workspace_dir)
+                        .args(jvm_args)
+                        .arg("-cp")
+                        .arg(out_dir)
+                        .arg(main_class);
+                    Ok(command)
+                }),
+                terminal: options.terminal_size(),
+                args: options.args.clone(),
+                env: options.env.clone(),
+                stdin: options.stdin.as_ref().map(PathBuf::from),
+                build_dir: None,
+            }
         }
+    };
+    plan.build_dir = Some(build_dir);
+    Ok(plan)
+}
+
+/// Plans how to build and launch a file with a runner template, building into the run's own directory
+fn plan_template_prog(
+    state: &AppState,
+    template: &RunTemplate,
+    file_path: &Path,
+    path: &Path,
+    options: &RunOptions,
+) -> Result<RunPlan, UserProgramError> {
+    let build_dir = run_build_dir(&state.build_dir)?;
+    let mut plan = template.plan(
+        file_path,
+        path.to_string_lossy().to_string(),
+        &state.workspace_dir,
+        build_dir.path(),
+        options,
+    );
+    plan.build_dir = Some(build_dir);
+    Ok(plan)
+}
+
+/// Creates a directory for a run to be built in, under the proxy's build directory
+fn run_build_dir(build_dir: &Path) -> Result<TempDir, UserProgramError> {
+    std::fs::create_dir_all(build_dir)
+        .and_then(|_| tempfile::Builder::new().prefix("run").tempdir_in(build_dir))
+        .map_err(|_| UserProgramError::FailedRun)
+}
+
+/// Command that runs a command line with the platform's shell
+pub fn shell_command(command_line: &str) -> Command {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
     } else {
-        return Err(UserProgramError::FailedProgramLock.into());
-    }
-    Err(UserProgramError::FailedRun.into())
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(command_line);
+    command
 }
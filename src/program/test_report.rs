@@ -0,0 +1,622 @@
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use serde::Serialize;
+use std::{collections::HashMap, path::Path};
+use tracing::warn;
+
+/// Results of a test run, grouped into the suites reported by the test runner
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TestReport {
+    /// Whether every test passed or was skipped
+    pub passed: bool,
+    pub suites: Vec<TestSuite>,
+}
+
+impl TestReport {
+    pub fn new(suites: Vec<TestSuite>) -> Self {
+        TestReport {
+            passed: suites.iter().all(|s| s.failures == 0 && s.errors == 0),
+            suites,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TestSuite {
+    pub name: String,
+    pub tests: usize,
+    pub failures: usize,
+    pub errors: usize,
+    pub skipped: usize,
+    /// Seconds taken to run the suite
+    pub duration: f64,
+    pub cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    fn new(name: String, duration: f64) -> Self {
+        TestSuite {
+            name,
+            tests: 0,
+            failures: 0,
+            errors: 0,
+            skipped: 0,
+            duration,
+            cases: Vec::new(),
+        }
+    }
+
+    /// Reports a test command that wrote no reports as a single test case
+    pub fn from_exit_status(command: &str, success: bool, output: String) -> Self {
+        let mut suite = TestSuite::new(command.to_string(), 0.0);
+        suite.add(TestCase {
+            name: command.to_string(),
+            class_name: None,
+            status: if success {
+                TestStatus::Passed
+            } else {
+                TestStatus::Failed
+            },
+            duration: 0.0,
+            failure: if success {
+                None
+            } else {
+                Some(TestFailure {
+                    message: None,
+                    kind: None,
+                    details: output,
+                    location: None,
+                })
+            },
+        });
+        suite
+    }
+
+    fn add(&mut self, case: TestCase) {
+        self.tests += 1;
+        match case.status {
+            TestStatus::Failed => self.failures += 1,
+            TestStatus::Error => self.errors += 1,
+            TestStatus::Skipped => self.skipped += 1,
+            TestStatus::Passed => (),
+        }
+        self.cases.push(case);
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TestCase {
+    pub name: String,
+    pub class_name: Option<String>,
+    pub status: TestStatus,
+    /// Seconds taken to run the test
+    pub duration: f64,
+    pub failure: Option<TestFailure>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    /// An assertion in the test failed
+    Failed,
+    /// The test threw an unexpected exception
+    Error,
+    Skipped,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TestFailure {
+    pub message: Option<String>,
+    /// Type of the exception or assertion error
+    pub kind: Option<String>,
+    /// Stack trace or other output explaining the failure
+    pub details: String,
+    pub location: Option<SourceLocation>,
+}
+
+/// Where in the test's source a failure happened
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SourceLocation {
+    /// Path of the source file, relative to the root of the codebase when it could be found there
+    pub file: String,
+    pub line: u32,
+}
+
+/// Parses a JUnit XML report, flattening nested suites
+pub fn parse_junit_xml(xml: &str) -> Result<Vec<TestSuite>, quick_xml::Error> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut suites = Vec::new();
+    let mut open_suites: Vec<TestSuite> = Vec::new();
+    let mut case: Option<TestCase> = None;
+    let mut failure_text: Option<String> = None;
+
+    loop {
+        let event = reader.read_event(&mut buf)?;
+        let (start, end) = match &event {
+            Event::Start(e) => (Some(e), None),
+            Event::Empty(e) => (Some(e), Some(e.name().to_vec())),
+            Event::End(e) => (None, Some(e.name().to_vec())),
+            Event::Text(e) => {
+                if let Some(text) = failure_text.as_mut() {
+                    text.push_str(&e.unescape_and_decode(&reader)?);
+                }
+                (None, None)
+            }
+            Event::CData(e) => {
+                if let Some(text) = failure_text.as_mut() {
+                    text.push_str(&String::from_utf8_lossy(e));
+                }
+                (None, None)
+            }
+            Event::Eof => break,
+            _ => (None, None),
+        };
+
+        if let Some(e) = start {
+            match e.name() {
+                b"testsuite" => open_suites.push(TestSuite::new(
+                    attribute(e, &reader, b"name").unwrap_or_default(),
+                    duration(e, &reader),
+                )),
+                b"testcase" => {
+                    case = Some(TestCase {
+                        name: attribute(e, &reader, b"name").unwrap_or_default(),
+                        class_name: attribute(e, &reader, b"classname"),
+                        status: TestStatus::Passed,
+                        duration: duration(e, &reader),
+                        failure: None,
+                    })
+                }
+                name @ b"failure" | name @ b"error" | name @ b"skipped" => {
+                    if let Some(case) = case.as_mut() {
+                        case.status = match name {
+                            b"failure" => TestStatus::Failed,
+                            b"error" => TestStatus::Error,
+                            _ => TestStatus::Skipped,
+                        };
+                        case.failure = Some(TestFailure {
+                            message: attribute(e, &reader, b"message"),
+                            kind: attribute(e, &reader, b"type"),
+                            details: String::new(),
+                            location: None,
+                        });
+                        failure_text = Some(String::new());
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(name) = end {
+            match name.as_slice() {
+                b"testsuite" => {
+                    if let Some(suite) = open_suites.pop() {
+                        if !suite.cases.is_empty() {
+                            suites.push(suite);
+                        }
+                    }
+                }
+                b"testcase" => {
+                    if let (Some(case), Some(suite)) = (case.take(), open_suites.last_mut()) {
+                        suite.add(case);
+                    }
+                }
+                b"failure" | b"error" | b"skipped" => {
+                    let details = failure_text.take().unwrap_or_default();
+                    let location = case_location(&details, case_class(&case));
+                    if let Some(failure) = case.as_mut().and_then(|c| c.failure.as_mut()) {
+                        failure.location = location;
+                        failure.details = details;
+                    }
+                }
+                _ => (),
+            }
+        }
+        buf.clear();
+    }
+    Ok(suites)
+}
+
+/// Markers the console launcher ends each test in its tree with, in its Unicode and ASCII themes
+const CONSOLE_STATUSES: [(&str, TestStatus); 8] = [
+    ("✔", TestStatus::Passed),
+    ("[OK]", TestStatus::Passed),
+    ("✘", TestStatus::Failed),
+    ("[X]", TestStatus::Failed),
+    ("■", TestStatus::Skipped),
+    ("[A]", TestStatus::Skipped),
+    ("↷", TestStatus::Skipped),
+    ("[S]", TestStatus::Skipped),
+];
+
+/// A test or container in the console launcher's tree
+struct ConsoleNode {
+    indent: usize,
+    name: String,
+    status: TestStatus,
+    message: Option<String>,
+}
+
+/// What the console launcher's failure summary says about a failed test
+#[derive(Default)]
+struct ConsoleFailure {
+    class_name: Option<String>,
+    kind: Option<String>,
+    details: String,
+}
+
+/// Parses the JUnit console launcher's `--details=tree` output
+pub fn parse_console_output(output: &str) -> Vec<TestSuite> {
+    let nodes: Vec<ConsoleNode> = output
+        .lines()
+        .skip_while(|line| !matches!(line.trim(), "╷" | "."))
+        .take_while(|line| {
+            !line.starts_with("Failures (") && !line.starts_with("Test run finished")
+        })
+        .filter_map(console_node)
+        .collect();
+    let failures = console_failures(output);
+
+    let mut suites: Vec<TestSuite> = Vec::new();
+    let mut ancestors: Vec<&ConsoleNode> = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        while ancestors.last().is_some_and(|a| a.indent >= node.indent) {
+            ancestors.pop();
+        }
+        let is_leaf = nodes
+            .get(i + 1)
+            .is_none_or(|next| next.indent <= node.indent);
+        // a leaf at the top of the tree is a test engine that found no tests
+        if is_leaf && !ancestors.is_empty() {
+            let container = ancestors.last().map(|a| a.name.clone()).unwrap_or_default();
+            let path: Vec<&str> = ancestors[1..]
+                .iter()
+                .map(|a| a.name.as_str())
+                .chain(std::iter::once(node.name.as_str()))
+                .collect();
+            let summary = failures.get(&path.join(":"));
+            let failure = match node.status {
+                TestStatus::Passed => None,
+                _ => Some(TestFailure {
+                    message: node.message.clone(),
+                    kind: summary.and_then(|f| f.kind.clone()),
+                    details: summary
+                        .map(|f| f.details.clone())
+                        .or_else(|| node.message.clone())
+                        .unwrap_or_default(),
+                    location: summary
+                        .and_then(|f| case_location(&f.details, f.class_name.as_deref())),
+                }),
+            };
+            let case = TestCase {
+                name: node.name.clone(),
+                class_name: summary
+                    .and_then(|f| f.class_name.clone())
+                    .or_else(|| Some(container.clone())),
+                status: node.status,
+                duration: 0.0,
+                failure,
+            };
+            match suites.iter_mut().find(|s| s.name == container) {
+                Some(suite) => suite.add(case),
+                None => {
+                    let mut suite = TestSuite::new(container, 0.0);
+                    suite.add(case);
+                    suites.push(suite);
+                }
+            }
+        }
+        ancestors.push(node);
+    }
+    suites
+}
+
+fn console_node(line: &str) -> Option<ConsoleNode> {
+    let start =
+        line.find(|c| !matches!(c, ' ' | '│' | '├' | '└' | '─' | '|' | '+' | '\'' | '-'))?;
+    let rest = &line[start..];
+    let (at, marker, status) = CONSOLE_STATUSES
+        .iter()
+        .filter_map(|(marker, status)| {
+            rest.find(&format!(" {}", marker))
+                .map(|at| (at, *marker, *status))
+        })
+        .min_by_key(|(at, ..)| *at)?;
+    let message = rest[at + 1 + marker.len()..].trim();
+    Some(ConsoleNode {
+        indent: line[..start].chars().count(),
+        name: rest[..at].to_string(),
+        status,
+        message: Some(message.to_string()).filter(|m| !m.is_empty()),
+    })
+}
+
+/// Reads the console launcher's failure summary, keyed by test path
+fn console_failures(output: &str) -> HashMap<String, ConsoleFailure> {
+    let mut failures = HashMap::new();
+    let mut current: Option<(String, ConsoleFailure)> = None;
+    let lines = output
+        .lines()
+        .skip_while(|line| !line.starts_with("Failures ("))
+        .skip(1)
+        .take_while(|line| line.is_empty() || line.starts_with(' '));
+    for line in lines {
+        let indent = line.len() - line.trim_start().len();
+        let text = line.trim();
+        if indent == 2 {
+            if let Some((path, failure)) = current.take() {
+                failures.insert(path, failure);
+            }
+            let path = text.split_once(':').map_or(text, |(_, path)| path);
+            current = Some((path.to_string(), ConsoleFailure::default()));
+            continue;
+        }
+        let failure = match current.as_mut() {
+            Some((_, failure)) => failure,
+            None => continue,
+        };
+        if let Some(source) = text.strip_prefix("MethodSource [className = '") {
+            failure.class_name = source.split('\'').next().map(String::from);
+        } else if let Some(exception) = text.strip_prefix("=> ") {
+            failure.kind = exception.split(':').next().map(String::from);
+            failure.details = exception.to_string();
+        } else if !text.is_empty() && !failure.details.is_empty() {
+            failure.details.push_str("\n\tat ");
+            failure.details.push_str(text);
+        }
+    }
+    if let Some((path, failure)) = current {
+        failures.insert(path, failure);
+    }
+    failures
+}
+
+fn case_class(case: &Option<TestCase>) -> Option<&str> {
+    case.as_ref().and_then(|c| c.class_name.as_deref())
+}
+
+fn case_location(details: &str, class_name: Option<&str>) -> Option<SourceLocation> {
+    let (file, line) = failure_location(details, class_name?)?;
+    Some(SourceLocation { file, line })
+}
+
+/// Finds the deepest stack frame in the test class, as a source path and line number
+fn failure_location(stack_trace: &str, class_name: &str) -> Option<(String, u32)> {
+    stack_trace.lines().find_map(|frame| {
+        let frame = frame.trim().strip_prefix("at ")?;
+        let (method, location) = frame.split_at(frame.find('(')?);
+        let class = &method[..method.rfind('.')?];
+        if class != class_name && !class.starts_with(&format!("{}$", class_name)) {
+            return None;
+        }
+        let (file, line) = location
+            .trim_matches(|c| c == '(' || c == ')')
+            .split_once(':')?;
+        let package_dir = match class_name.rfind('.') {
+            Some(end) => format!("{}/", class_name[..end].replace('.', "/")),
+            None => String::new(),
+        };
+        Some((format!("{}{}", package_dir, file), line.parse().ok()?))
+    })
+}
+
+fn attribute(e: &BytesStart, reader: &Reader<&[u8]>, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .filter_map(|a| a.ok())
+        .find(|a| a.key == name)
+        .and_then(|a| a.unescape_and_decode_value(reader).ok())
+}
+
+/// Reads a `time` attribute, which Surefire formats with thousands separators
+fn duration(e: &BytesStart, reader: &Reader<&[u8]>) -> f64 {
+    attribute(e, reader, b"time")
+        .and_then(|time| time.replace(',', "").parse().ok())
+        .unwrap_or_default()
+}
+
+/// Reads every JUnit XML report in a directory, skipping unreadable ones
+pub async fn read_reports(dir: &Path) -> Vec<TestSuite> {
+    let mut reports = Vec::new();
+    if let Ok(mut entries) = tokio::fs::read_dir(dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("xml") {
+                reports.push(path);
+            }
+        }
+    }
+    reports.sort();
+
+    let mut suites = Vec::new();
+    for report in reports {
+        let parsed = match tokio::fs::read_to_string(&report).await {
+            Ok(xml) => parse_junit_xml(&xml).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match parsed {
+            Ok(report_suites) => suites.extend(report_suites),
+            Err(e) => warn!(report = %report.display(), error = %e, "Error reading test report"),
+        }
+    }
+    suites
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        failure_location, parse_console_output, parse_junit_xml, SourceLocation, TestReport,
+        TestStatus,
+    };
+
+    const SUREFIRE_REPORT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="com.example.GreetingTest" time="1,002.5" tests="4" errors="1" skipped="1" failures="1">
+  <properties><property name="java.version" value="11"/></properties>
+  <testcase name="greetsByName" classname="com.example.GreetingTest" time="0.01"/>
+  <testcase name="greetsNobody" classname="com.example.GreetingTest" time="0.002">
+    <failure message="expected: &lt;Hello&gt; but was: &lt;Hi&gt;" type="org.opentest4j.AssertionFailedError"><![CDATA[org.opentest4j.AssertionFailedError: expected: <Hello> but was: <Hi>
+	at org.junit.jupiter.api.AssertionUtils.fail(AssertionUtils.java:55)
+	at com.example.GreetingTest.greetsNobody(GreetingTest.java:17)
+]]></failure>
+    <system-out>greeting nobody</system-out>
+  </testcase>
+  <testcase name="throws" classname="com.example.GreetingTest" time="0">
+    <error type="java.lang.IllegalStateException">java.lang.IllegalStateException
+	at com.example.Greeting.check(Greeting.java:9)
+	at com.example.GreetingTest$Nested.throws(GreetingTest.java:30)</error>
+  </testcase>
+  <testcase name="ignored" classname="com.example.GreetingTest" time="0"><skipped/></testcase>
+</testsuite>"#;
+
+    #[test]
+    fn surefire_report_is_parsed() {
+        let suites = parse_junit_xml(SUREFIRE_REPORT).expect("report failed to parse");
+
+        assert_eq!(suites.len(), 1);
+        let suite = &suites[0];
+        assert_eq!(suite.name, "com.example.GreetingTest");
+        assert_eq!(suite.duration, 1002.5);
+        assert_eq!(
+            (suite.tests, suite.failures, suite.errors, suite.skipped),
+            (4, 1, 1, 1)
+        );
+        let statuses: Vec<_> = suite.cases.iter().map(|c| c.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                TestStatus::Passed,
+                TestStatus::Failed,
+                TestStatus::Error,
+                TestStatus::Skipped
+            ]
+        );
+
+        let failure = suite.cases[1]
+            .failure
+            .as_ref()
+            .expect("no failure reported");
+        assert_eq!(
+            failure.message.as_deref(),
+            Some("expected: <Hello> but was: <Hi>")
+        );
+        assert_eq!(
+            failure.kind.as_deref(),
+            Some("org.opentest4j.AssertionFailedError")
+        );
+        assert!(failure.details.contains("AssertionUtils.java:55"));
+        assert_eq!(
+            failure.location,
+            Some(SourceLocation {
+                file: "com/example/GreetingTest.java".to_string(),
+                line: 17
+            })
+        );
+        let error = suite.cases[2].failure.as_ref().expect("no error reported");
+        assert_eq!(error.location.as_ref().map(|l| l.line), Some(30));
+        assert!(!TestReport::new(suites).passed);
+    }
+
+    #[test]
+    fn nested_suites_are_flattened() {
+        let report = r#"<testsuites>
+            <testsuite name="junit-jupiter">
+                <testsuite name="empty"></testsuite>
+                <testcase name="adds()" classname="Calculator" time="0.1"/>
+            </testsuite>
+        </testsuites>"#;
+
+        let suites = parse_junit_xml(report).expect("report failed to parse");
+
+        assert_eq!(suites.len(), 1);
+        assert_eq!(suites[0].name, "junit-jupiter");
+        assert!(TestReport::new(suites).passed);
+    }
+
+    #[test]
+    fn console_launcher_tree_is_parsed() {
+        let output = "\
+Thanks for using JUnit!
+╷
+├─ JUnit Jupiter ✔
+│  └─ CalculatorTest ✔
+│     ├─ adds() ✔
+│     ├─ divides() ✘ expected: <2> but was: <3>
+│     └─ rounds() ↷ not ready
+└─ JUnit Vintage ✔
+
+Failures (1):
+  JUnit Jupiter:CalculatorTest:divides()
+    MethodSource [className = 'com.example.CalculatorTest', methodName = 'divides', methodParameterTypes = '']
+    => org.opentest4j.AssertionFailedError: expected: <2> but was: <3>
+       org.junit.jupiter.api.AssertionUtils.fail(AssertionUtils.java:55)
+       com.example.CalculatorTest.divides(CalculatorTest.java:14)
+
+Test run finished after 52 ms
+[         3 tests found           ]
+";
+
+        let suites = parse_console_output(output);
+
+        assert_eq!(suites.len(), 1);
+        let suite = &suites[0];
+        assert_eq!(suite.name, "CalculatorTest");
+        assert_eq!(
+            (suite.tests, suite.failures, suite.errors, suite.skipped),
+            (3, 1, 0, 1)
+        );
+        let failed = &suite.cases[1];
+        assert_eq!(failed.name, "divides()");
+        assert_eq!(
+            failed.class_name.as_deref(),
+            Some("com.example.CalculatorTest")
+        );
+        let failure = failed.failure.as_ref().expect("no failure reported");
+        assert_eq!(
+            failure.message.as_deref(),
+            Some("expected: <2> but was: <3>")
+        );
+        assert_eq!(
+            failure.kind.as_deref(),
+            Some("org.opentest4j.AssertionFailedError")
+        );
+        assert_eq!(
+            failure.location,
+            Some(SourceLocation {
+                file: "com/example/CalculatorTest.java".to_string(),
+                line: 14
+            })
+        );
+        let skipped = suite.cases[2].failure.as_ref().expect("no reason for skip");
+        assert_eq!(skipped.message.as_deref(), Some("not ready"));
+    }
+
+    #[test]
+    fn console_launcher_ascii_tree_is_parsed() {
+        let output = ".\n+-- JUnit Jupiter [OK]\n| '-- CalculatorTest [OK]\n|   '-- adds() [OK]\n'-- JUnit Vintage [OK]\n";
+
+        let suites = parse_console_output(output);
+
+        assert_eq!(suites.len(), 1);
+        assert_eq!(suites[0].cases[0].name, "adds()");
+        assert_eq!(suites[0].cases[0].status, TestStatus::Passed);
+        assert!(TestReport::new(suites).passed);
+    }
+
+    #[test]
+    fn failures_outside_the_test_class_have_no_location() {
+        let trace = "java.lang.AssertionError\n\tat org.junit.Assert.fail(Assert.java:89)";
+
+        assert_eq!(failure_location(trace, "CalculatorTest"), None);
+        assert_eq!(
+            failure_location(
+                "\tat CalculatorTest.adds(CalculatorTest.java:8)",
+                "CalculatorTest"
+            ),
+            Some(("CalculatorTest.java".to_string(), 8))
+        );
+    }
+}
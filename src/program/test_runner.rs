@@ -0,0 +1,229 @@
+use path_slash::PathExt;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tokio::process::Command;
+use walkdir::WalkDir;
+
+use crate::{config, file_system::files::is_ignored, AppState};
+
+use super::{
+    exec::StopOnDrop,
+    java::{compile_module, JavaProgram},
+    program_handle::{ProgramEvent, ProgramState, RunPlan},
+    project::Project,
+    runners::shell_command,
+    test_report::{parse_console_output, read_reports, TestReport, TestSuite},
+    user_program::UserProgramError,
+};
+
+/// Receives each line the tests print, with the stream it was printed to
+pub type TestOutput<'a> = &'a mut dyn FnMut(&'static str, String);
+
+/// How a test command ended, and what it printed
+struct TestExit {
+    success: bool,
+    console: String,
+}
+
+/// Runs the tests for a file or directory in the workspace as batch runs
+pub async fn run_tests_for(
+    state: &AppState,
+    path: &Path,
+    output: TestOutput<'_>,
+) -> Result<TestReport, UserProgramError> {
+    let target = Path::new(&state.workspace_dir).join(path);
+    if !target.exists() {
+        return Err(UserProgramError::NoProgram);
+    }
+    let file = path.to_slash_lossy();
+    match &state.lang {
+        config::Lang::Java => run_java_tests(state, &file, &target, output).await,
+        config::Lang::C | config::Lang::Custom => run_command_tests(state, &file, output).await,
+    }
+}
+
+/// Runs the tests of the project or module containing the target
+async fn run_java_tests(
+    state: &AppState,
+    file: &str,
+    target: &Path,
+    output: TestOutput<'_>,
+) -> Result<TestReport, UserProgramError> {
+    if let Some(project) = Project::detect(target, Path::new(&state.workspace_dir)) {
+        let (command, reports_dir) = project.test_command(state.build_repo_path.as_deref());
+        let (suites, exit) = run_with_reports(state, file, command, &reports_dir, output).await?;
+        if suites.is_empty() && !exit.success {
+            return Err(UserProgramError::FailedBuild {
+                tool: project.tool.name().to_string(),
+            });
+        }
+        return Ok(report(suites, &project.root, &state.workspace_dir));
+    }
+
+    let junit_path = match &state.junit_path {
+        Some(junit_path) => junit_path,
+        None => return run_command_tests(state, file, output).await,
+    };
+    let source_root = if target.is_dir() {
+        target.to_path_buf()
+    } else {
+        JavaProgram::locate(target).await?.source_root
+    };
+    let out_dir = state.build_dir.join("java-tests");
+    compile_module(
+        &source_root,
+        &state.workspace_dir,
+        &out_dir,
+        Some(junit_path),
+    )
+    .await?;
+
+    let reports_dir = state.build_dir.join("test-reports");
+    let mut command = Command::new("java");
+    command
+        .current_dir(&state.workspace_dir)
+        .arg("-jar")
+        .arg(junit_path)
+        .arg("--disable-banner")
+        .arg("--disable-ansi-colors")
+        .arg("--details=tree")
+        .arg("--class-path")
+        .arg(&out_dir)
+        .arg("--scan-class-path")
+        .arg("--reports-dir")
+        .arg(&reports_dir);
+    let (mut suites, exit) = run_with_reports(state, file, command, &reports_dir, output).await?;
+    if suites.is_empty() {
+        suites = parse_console_output(&exit.console);
+    }
+    if suites.is_empty() && !exit.success {
+        return Err(UserProgramError::FailedRun);
+    }
+    Ok(report(suites, &source_root, &state.workspace_dir))
+}
+
+/// Runs the configured test command in the workspace
+async fn run_command_tests(
+    state: &AppState,
+    file: &str,
+    output: TestOutput<'_>,
+) -> Result<TestReport, UserProgramError> {
+    let test_cmd = state
+        .test_cmd
+        .as_deref()
+        .ok_or(UserProgramError::NoTestRunner)?;
+    let reports_dir = state.build_dir.join("test-reports");
+
+    let mut command = shell_command(test_cmd);
+    command
+        .current_dir(&state.workspace_dir)
+        .env("TEST_REPORTS_DIR", &reports_dir);
+    let (mut suites, exit) = run_with_reports(state, file, command, &reports_dir, output).await?;
+    if suites.is_empty() {
+        suites.push(TestSuite::from_exit_status(
+            test_cmd,
+            exit.success,
+            exit.console,
+        ));
+    }
+    Ok(report(
+        suites,
+        Path::new(&state.workspace_dir),
+        &state.workspace_dir,
+    ))
+}
+
+/// Runs a test command after clearing out its reports directory, then reads the reports it wrote
+async fn run_with_reports(
+    state: &AppState,
+    file: &str,
+    mut command: Command,
+    reports_dir: &Path,
+    output: TestOutput<'_>,
+) -> Result<(Vec<TestSuite>, TestExit), UserProgramError> {
+    if reports_dir.exists() {
+        tokio::fs::remove_dir_all(reports_dir)
+            .await
+            .map_err(|_| UserProgramError::FailedRun)?;
+    }
+    tokio::fs::create_dir_all(reports_dir)
+        .await
+        .map_err(|_| UserProgramError::FailedRun)?;
+
+    command.kill_on_drop(true);
+    let plan = RunPlan {
+        file: file.to_string(),
+        build: Vec::new(),
+        launch: Box::new(move || Ok(command)),
+        terminal: None,
+        args: Vec::new(),
+        env: BTreeMap::new(),
+        stdin: None,
+        build_dir: None,
+    };
+    let (handle, mut events) = state.runs.start(None, plan, state.run_limits)?;
+    let run = StopOnDrop(handle);
+    let mut console = String::new();
+    let mut exited = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            ProgramEvent::Stdout(line) => record_line(&mut console, output, "stdout", line),
+            ProgramEvent::Stderr(line) => record_line(&mut console, output, "stderr", line),
+            ProgramEvent::Failed(err) => return Err(err),
+            ProgramEvent::Exited(status) => {
+                exited = Some(status);
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    let status = exited.unwrap_or_else(|| run.0.status());
+    if status.state == ProgramState::TimedOut {
+        return Err(UserProgramError::TestsTimedOut);
+    }
+    let exit = TestExit {
+        success: status.state == ProgramState::Exited && status.exit_code == Some(0),
+        console,
+    };
+    Ok((read_reports(reports_dir).await, exit))
+}
+
+fn record_line(console: &mut String, output: TestOutput<'_>, stream: &'static str, line: String) {
+    console.push_str(&line);
+    console.push('\n');
+    output(stream, line);
+}
+
+/// Builds the report, pointing failure locations at the test sources
+fn report(mut suites: Vec<TestSuite>, source_root: &Path, workspace_dir: &str) -> TestReport {
+    let workspace_dir = Path::new(workspace_dir)
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from(workspace_dir));
+    let locations = suites
+        .iter_mut()
+        .flat_map(|s| s.cases.iter_mut())
+        .filter_map(|c| c.failure.as_mut())
+        .filter_map(|f| f.location.as_mut());
+    for location in locations {
+        if let Some(file) = find_source(source_root, &location.file) {
+            if let Ok(relative) = file.strip_prefix(&workspace_dir) {
+                location.file = relative.to_slash_lossy();
+            }
+        }
+    }
+    TestReport::new(suites)
+}
+
+/// Finds the file under `source_root` whose path ends with the package relative path
+fn find_source(source_root: &Path, package_path: &str) -> Option<PathBuf> {
+    let package_path = Path::new(package_path);
+    WalkDir::new(source_root)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !is_ignored(e))
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_type().is_file() && e.path().ends_with(package_path))
+        .and_then(|e| e.into_path().canonicalize().ok())
+}
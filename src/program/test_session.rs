@@ -0,0 +1,83 @@
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web::web;
+use actix_web_actors::ws;
+use serde_json::json;
+use std::path::PathBuf;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use crate::AppState;
+
+use super::{
+    test_report::TestReport,
+    test_runner::run_tests_for,
+    user_program::{output_line, UserProgramError},
+};
+
+/// What a test run has to tell its websocket, in the order it happened
+#[derive(Debug)]
+enum TestEvent {
+    Output(&'static str, String),
+    Finished(Result<TestReport, UserProgramError>),
+}
+
+/// WebSocket connection streaming the output and then the report of a test run
+pub struct TestSession {
+    state: web::Data<AppState>,
+    path: PathBuf,
+}
+
+impl TestSession {
+    pub fn new(state: web::Data<AppState>, path: PathBuf) -> Self {
+        TestSession { state, path }
+    }
+}
+
+impl Actor for TestSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let (events, received) = unbounded_channel();
+        ctx.add_stream(received);
+        let (state, path) = (self.state.clone(), self.path.clone());
+        // dropped with the websocket, which stops the run
+        ctx.spawn(actix::fut::wrap_future(run(state, path, events)));
+    }
+}
+
+async fn run(state: web::Data<AppState>, path: PathBuf, events: UnboundedSender<TestEvent>) {
+    let output_events = events.clone();
+    let mut output = move |stream, line| {
+        let _ = output_events.send(TestEvent::Output(stream, line));
+    };
+    let report = run_tests_for(&state, &path, &mut output).await;
+    let _ = events.send(TestEvent::Finished(report));
+}
+
+impl StreamHandler<TestEvent> for TestSession {
+    fn handle(&mut self, event: TestEvent, ctx: &mut Self::Context) {
+        match event {
+            TestEvent::Output(stream, line) => ctx.text(output_line(stream, &line)),
+            TestEvent::Finished(Ok(report)) => {
+                ctx.text(json!({ "report": report }).to_string());
+                ctx.close(Some(ws::CloseCode::Normal.into()));
+                ctx.stop();
+            }
+            TestEvent::Finished(Err(err)) => {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Error,
+                    description: Some(err.to_string()),
+                }));
+                ctx.stop();
+            }
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for TestSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        if let Ok(ws::Message::Close(reason)) = msg {
+            ctx.close(reason);
+            ctx.stop();
+        }
+    }
+}
@@ -1,28 +1,66 @@
-use actix::{Actor, AsyncContext, StreamHandler};
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
 use actix_web::{dev::HttpResponseBuilder, error, http::header, http::StatusCode, HttpResponse};
 use actix_web_actors::ws;
 use derive_more::{Display, Error};
-use std::sync::Arc;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::{Child, ChildStdin, ChildStdout},
-    stream::StreamExt,
-    sync::Mutex,
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::{error, warn};
+
+use crate::metrics::ActiveSession;
+
+use super::{
+    program_handle::{ProgramEvent, ProgramHandle, ProgramInput},
+    run_broadcast::InputClaim,
 };
 
-use crate::Line;
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct WindowSize {
+    pub rows: u16,
+    pub cols: u16,
+}
 
+impl Default for WindowSize {
+    fn default() -> Self {
+        WindowSize { rows: 24, cols: 80 }
+    }
+}
+
+/// Control messages a client can send as text to a program running in a terminal
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TerminalControl {
+    Resize(WindowSize),
+}
+
+/// WebSocket connection to a user program
 #[derive(Debug)]
 pub struct UserProgram {
-    child: Option<Child>,
-    stdin: Arc<Mutex<Option<ChildStdin>>>,
-    stdout: Option<ChildStdout>,
+    handle: ProgramHandle,
+    events: Option<UnboundedReceiver<ProgramEvent>>,
+    terminal: bool,
+    /// Set when watching a run started by another connection
+    observer: Option<Option<InputClaim>>,
+    /// Sent to the run's owner along with the run id, for granting an observer input
+    input_token: Option<String>,
+    _session: ActiveSession,
 }
 
-impl StreamHandler<Result<Line, ws::ProtocolError>> for UserProgram {
-    fn handle(&mut self, msg: Result<Line, ws::ProtocolError>, ctx: &mut Self::Context) {
-        if let Ok(line) = msg {
-            ctx.text(line.0)
+impl StreamHandler<ProgramEvent> for UserProgram {
+    fn handle(&mut self, event: ProgramEvent, ctx: &mut Self::Context) {
+        match event {
+            ProgramEvent::Build(line) => ctx.text(output_line("build", &line)),
+            ProgramEvent::Stdout(line) => ctx.text(output_line("stdout", &line)),
+            ProgramEvent::Stderr(line) => ctx.text(output_line("stderr", &line)),
+            ProgramEvent::Terminal(bytes) => ctx.binary(bytes),
+            ProgramEvent::Failed(err) => {
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Error,
+                    description: Some(err.to_string()),
+                }));
+                ctx.stop();
+            }
+            ProgramEvent::Input(_) | ProgramEvent::Exited(_) => (),
         }
     }
 }
@@ -31,69 +69,134 @@ impl Actor for UserProgram {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        /* Send the bytes received from stdout to ctx */
-        let stdout = self.stdout.take().unwrap();
-        let reader = BufReader::new(stdout).lines();
-        ctx.add_stream(reader.map(|l| {
-            if let Ok(l) = l {
-                Ok(Line(l))
-            } else {
-                Ok(Line("Failed to read from user program".to_string()))
+        /* Tell the client which run it started, then send the program's output to ctx, the websocket closes once the run is over */
+        if self.observer.is_none() {
+            let started = json!({ "run_id": self.handle.id(), "input_token": self.input_token });
+            ctx.text(started.to_string());
+        }
+        if let Some(events) = self.events.take() {
+            ctx.add_stream(events);
+        }
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if self.observer.is_some() {
+            return;
+        }
+        /* Make sure the program doesn't outlive its websocket */
+        let handle = self.handle.clone();
+        actix::spawn(async move {
+            if let Err(err) = handle.stop().await {
+                error!(run = handle.id(), error = %err, "Error stopping program");
             }
-        }));
+        });
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for UserProgram {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        if let Ok(ws::Message::Text(text)) = msg {
-            let stdin = self.stdin.clone();
-            let user_program_fut = async move {
-                let mut stdin = stdin.lock().await;
-                if stdin.is_some() {
-                    let stdin = stdin.as_mut().unwrap();
-                    let text = format!("{}\n", text);
-                    if let Err(er) = stdin.write_all(&text.as_bytes()).await {
-                        eprintln!("Error writing to program! {:?}", er);
+        match msg {
+            Ok(ws::Message::Text(_)) | Ok(ws::Message::Binary(_)) if !self.can_send_input() => (),
+            Ok(ws::Message::Text(text)) if self.terminal => {
+                match serde_json::from_str::<TerminalControl>(&text) {
+                    Ok(TerminalControl::Resize(size)) => {
+                        self.handle.send(ProgramInput::Resize(size))
                     }
-                    stdin.flush();
+                    Err(err) => warn!(error = %err, "Ignoring unknown terminal control message"),
                 }
-            };
-            let user_program_fut = actix::fut::wrap_future(user_program_fut);
-            ctx.spawn(user_program_fut);
+            }
+            Ok(ws::Message::Text(text)) => self.handle.send(ProgramInput::Line(text)),
+            Ok(ws::Message::Binary(bytes)) => self.handle.send(ProgramInput::Bytes(bytes.to_vec())),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => (),
         }
     }
 }
 
 impl UserProgram {
-    pub fn start(child: Child) -> Self {
-        let mut child = child;
-        let stdin = child.stdin.take();
-        let stdout = child.stdout.take();
+    /// Connects to a started run, passing raw bytes both ways if the program is running in a terminal
+    pub fn new(
+        handle: ProgramHandle,
+        events: UnboundedReceiver<ProgramEvent>,
+        terminal: bool,
+        input_token: Option<String>,
+        session: ActiveSession,
+    ) -> Self {
+        UserProgram {
+            handle,
+            events: Some(events),
+            terminal,
+            observer: None,
+            input_token,
+            _session: session,
+        }
+    }
+
+    /// Watches a run started by another connection, sending the run input only with the run's input claim
+    pub fn observer(
+        handle: ProgramHandle,
+        events: UnboundedReceiver<ProgramEvent>,
+        terminal: bool,
+        input: Option<InputClaim>,
+        session: ActiveSession,
+    ) -> Self {
         UserProgram {
-            child: Some(child),
-            stdin: Arc::new(Mutex::new(stdin)),
-            stdout,
+            observer: Some(input),
+            ..UserProgram::new(handle, events, terminal, None, session)
         }
     }
+
+    fn can_send_input(&self) -> bool {
+        !matches!(self.observer, Some(None))
+    }
+}
+
+/// Text message for a line of output, tagged with the stream it was written to
+pub fn output_line(stream: &str, text: &str) -> String {
+    json!({"stream": stream, "text": text}).to_string()
 }
 
-#[derive(Debug, Display, Error, Clone, Copy)]
+#[derive(Debug, Display, Error, Clone)]
 pub enum UserProgramError {
     #[display(fmt = "Program failed to start")]
     FailedRun,
     #[display(fmt = "Program failed to compile")]
     FailedCompilation,
+    #[display(fmt = "Program failed to compile:\n{}", output)]
+    CompilationErrors { output: String },
     #[display(fmt = "No program to run")]
     NoProgram,
     #[display(fmt = "Program failed to exit")]
     FailedKill,
     #[display(fmt = "Running this programming language is not currently supported")]
     UnsupportedLanguage,
-    #[display(fmt = "Failed to acuqire lock on running program")]
-    FailedProgramLock,
     #[display(fmt = "Failed to get output from the program")]
     NoOutput,
+    #[display(fmt = "Running programs in a terminal is not supported on this platform")]
+    UnsupportedTerminal,
+    #[display(fmt = "Program failed to build with {}", tool)]
+    FailedBuild { tool: String },
+    #[display(fmt = "No test runner is configured for this language")]
+    NoTestRunner,
+    #[display(fmt = "Invalid run options: {}", cause)]
+    InvalidRunOptions { cause: String },
+    #[display(fmt = "Failed to save run configuration")]
+    FailedSavingRunConfiguration,
+    #[display(fmt = "Another observer is already sending input to this program")]
+    InputTaken,
+    #[display(fmt = "The input token doesn't match the program's")]
+    InputNotGranted,
+    #[display(fmt = "Tests did not finish within the time limit")]
+    TestsTimedOut,
+    #[display(fmt = "Build did not finish within the time limit")]
+    BuildTimedOut,
+    #[display(fmt = "Only {} programs can run at once", max_runs)]
+    TooManyRuns { max_runs: usize },
+    #[display(fmt = "Programs can only be run over a websocket")]
+    NotWebSocket,
 }
 
 impl error::ResponseError for UserProgramError {
@@ -104,6 +207,18 @@ impl error::ResponseError for UserProgramError {
     }
 
     fn status_code(&self) -> StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        match self {
+            UserProgramError::NoProgram => StatusCode::NOT_FOUND,
+            UserProgramError::CompilationErrors { .. }
+            | UserProgramError::InvalidRunOptions { .. }
+            | UserProgramError::NotWebSocket => StatusCode::BAD_REQUEST,
+            UserProgramError::InputTaken => StatusCode::CONFLICT,
+            UserProgramError::InputNotGranted => StatusCode::FORBIDDEN,
+            UserProgramError::TestsTimedOut | UserProgramError::BuildTimedOut => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            UserProgramError::TooManyRuns { .. } => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
     }
 }
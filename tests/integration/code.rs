@@ -1,6 +1,13 @@
-use crate::test_helper::{spawn_app, COMMON_TEST_DIRECTORY, COMMON_TEST_FILE, COMMON_TEST_LANG};
+use crate::test_helper::{
+    run_messages, run_to_completion, spawn_app, spawn_app_with, websocket_messages,
+    BROKEN_MAVEN_TEST_DIRECTORY, COMMON_TEST_DIRECTORY, COMMON_TEST_FILE, COMMON_TEST_LANG,
+    C_TEST_DIRECTORY, JAVA_ARGS_TEST_DIRECTORY, JAVA_PACKAGES_TEST_DIRECTORY, MAVEN_TEST_DIRECTORY,
+};
+use lsp_proxy::config::Lang;
 use reqwest::StatusCode;
-use std::{env, fs};
+use serde_json::{json, Value};
+use std::{env, fs, sync::mpsc, thread, time::Duration};
+use websocket::{ClientBuilder, OwnedMessage};
 
 #[actix_rt::test]
 async fn file_endpoint_responds_with_existing_file() {
@@ -24,3 +31,981 @@ async fn file_endpoint_responds_with_existing_file() {
 
     assert_eq!(actual_file, content.as_str());
 }
+
+#[actix_rt::test]
+async fn run_status_and_stop_without_program_are_not_found() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let status = client
+        .get(&format!("{}/code/run/status", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+    let stop = client
+        .post(&format!("{}/code/run/stop", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(status.status(), StatusCode::NOT_FOUND);
+    assert_eq!(stop.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn running_program_reports_status_and_can_be_stopped() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    // the program waits for input, so it keeps running while the socket is open
+    let run_address = format!("{}/code/run/src/Hello.java", address.replace("http", "ws"));
+    let (close_tx, close_rx) = mpsc::channel::<()>();
+    let socket = thread::spawn(move || {
+        let _client = ClientBuilder::new(&run_address)
+            .expect("bad websocket address")
+            .connect_insecure()
+            .expect("failed to open run websocket");
+        close_rx.recv().ok();
+    });
+
+    let mut status = Value::Null;
+    for _ in 0..50 {
+        let response = client
+            .get(&format!("{}/code/run/status", address))
+            .send()
+            .await
+            .expect("failed to execute request");
+        if response.status() == StatusCode::OK {
+            status = response.json().await.expect("status is not json");
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+    }
+    assert_eq!(status["state"], "running");
+    assert_eq!(status["file"], "src/Hello.java");
+
+    let response = client
+        .post(&format!("{}/code/run/stop", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let stopped: Value = response.json().await.expect("status is not json");
+    assert_eq!(stopped["state"], "stopped");
+    assert_eq!(stopped["pid"], status["pid"]);
+
+    close_tx.send(()).ok();
+    socket.join().expect("websocket thread panicked");
+}
+
+#[actix_rt::test]
+async fn programs_run_concurrently_and_are_stopped_by_run_id() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    // both programs wait for input, so they keep running while their sockets are open
+    let run_address = format!("{}/code/run/src/Hello.java", address.replace("http", "ws"));
+    let (close_tx, close_rx) = mpsc::channel::<()>();
+    let sockets = thread::spawn(move || {
+        let _clients: Vec<_> = (0..2)
+            .map(|_| {
+                ClientBuilder::new(&run_address)
+                    .expect("bad websocket address")
+                    .connect_insecure()
+                    .expect("failed to open run websocket")
+            })
+            .collect();
+        close_rx.recv().ok();
+    });
+
+    let mut runs = Value::Null;
+    for _ in 0..50 {
+        runs = client
+            .get(&format!("{}/code/runs", address))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .json()
+            .await
+            .expect("runs are not json");
+        if runs.as_array().map(Vec::len) == Some(2) {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+    }
+    assert_eq!(runs.as_array().map(Vec::len), Some(2));
+    assert_ne!(runs[0]["id"], runs[1]["id"]);
+
+    let response = client
+        .post(&format!("{}/code/runs/{}/stop", address, runs[0]["id"]))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(response.status(), StatusCode::OK);
+    let stopped: Value = response.json().await.expect("status is not json");
+    assert_eq!(stopped["state"], "stopped");
+
+    let running: Value = client
+        .get(&format!("{}/code/runs", address))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("runs are not json");
+    assert_eq!(running, json!([runs[1]]));
+
+    close_tx.send(()).ok();
+    sockets.join().expect("websocket thread panicked");
+}
+
+#[actix_rt::test]
+async fn observer_mirrors_run_output_and_can_send_input() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+    let ws_address = address.replace("http", "ws");
+
+    // the run's own websocket stays quiet, leaving the observer to answer the program
+    let run_address = format!("{}/code/run/src/Hello.java", ws_address);
+    let (started_tx, started_rx) = mpsc::channel::<Value>();
+    let (close_tx, close_rx) = mpsc::channel::<()>();
+    let owner = thread::spawn(move || {
+        let mut client = ClientBuilder::new(&run_address)
+            .expect("bad websocket address")
+            .connect_insecure()
+            .expect("failed to open run websocket");
+        if let Ok(OwnedMessage::Text(message)) = client.recv_message() {
+            let started = serde_json::from_str(&message).expect("message is not json");
+            started_tx.send(started).ok();
+        }
+        close_rx.recv().ok();
+    });
+
+    let mut started = None;
+    for _ in 0..50 {
+        started = started_rx.try_recv().ok();
+        if started.is_some() {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+    }
+    let started = started.expect("run id was not sent");
+    let (id, token) = (&started["run_id"], &started["input_token"]);
+
+    // only the owner's token lets an observer send input
+    let response = client
+        .get(&format!(
+            "{}/code/runs/{}/observe?input_token=guess",
+            address, id
+        ))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let observe_address = format!(
+        "{}/code/runs/{}/observe?input_token={}",
+        ws_address,
+        id,
+        token.as_str().expect("no input token")
+    );
+
+    let (answer_tx, answer_rx) = mpsc::channel::<()>();
+    let observer = thread::spawn(move || {
+        let mut client = ClientBuilder::new(&observe_address)
+            .expect("bad websocket address")
+            .connect_insecure()
+            .expect("failed to open observer websocket");
+        let mut lines = Vec::new();
+        while let Ok(OwnedMessage::Text(line)) = client.recv_message() {
+            let line: Value = serde_json::from_str(&line).expect("output is not json");
+            lines.push(line["text"].as_str().unwrap_or_default().to_string());
+            if lines.last().map(String::as_str) == Some("What's your name?") {
+                answer_rx.recv().ok();
+                client
+                    .send_message(&OwnedMessage::Text("Ada".to_string()))
+                    .expect("failed to send input");
+            }
+        }
+        lines
+    });
+
+    // input can only be sent by one observer at a time
+    let mut status = StatusCode::OK;
+    for _ in 0..50 {
+        status = client
+            .get(&format!(
+                "{}/code/runs/{}/observe?input_token={}",
+                address,
+                id,
+                token.as_str().unwrap_or_default()
+            ))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .status();
+        if status == StatusCode::CONFLICT {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+    }
+    assert_eq!(status, StatusCode::CONFLICT);
+    answer_tx.send(()).ok();
+
+    let lines = observer.join().expect("observer thread panicked");
+    assert_eq!(
+        lines,
+        vec!["Hello world!", "What's your name?", "Hello Ada!"]
+    );
+
+    close_tx.send(()).ok();
+    owner.join().expect("websocket thread panicked");
+}
+
+#[actix_rt::test]
+async fn program_in_pty_mode_streams_raw_terminal_bytes() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let run_address = format!(
+        "{}/code/run/src/Hello.java?pty=true&rows=30&cols=100",
+        address.replace("http", "ws")
+    );
+
+    // the websocket client is blocking, so it can't share the server's thread
+    let output = thread::spawn(move || {
+        let mut client = ClientBuilder::new(&run_address)
+            .expect("bad websocket address")
+            .connect_insecure()
+            .expect("failed to open run websocket");
+        client
+            .send_message(&OwnedMessage::Text(
+                r#"{"type": "resize", "rows": 40, "cols": 120}"#.to_string(),
+            ))
+            .expect("failed to send resize");
+
+        let mut output = String::new();
+        let mut answered = false;
+        while !output.contains("Hello Ferris!") {
+            match client.recv_message().expect("websocket closed early") {
+                OwnedMessage::Binary(bytes) => output.push_str(&String::from_utf8_lossy(&bytes)),
+                OwnedMessage::Close(_) => break,
+                _ => (),
+            }
+            if !answered && output.contains("What's your name?") {
+                client
+                    .send_message(&OwnedMessage::Binary(b"Ferris\r".to_vec()))
+                    .expect("failed to send input");
+                answered = true;
+            }
+        }
+        output
+    });
+
+    let output = loop {
+        if output.is_finished() {
+            break output.join().expect("websocket thread panicked");
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    };
+
+    assert!(output.contains("Hello world!\r\n"));
+    assert!(output.contains("Hello Ferris!"));
+}
+
+#[actix_rt::test]
+async fn program_in_package_is_compiled_with_its_module() {
+    let address = spawn_app(JAVA_PACKAGES_TEST_DIRECTORY, COMMON_TEST_LANG);
+
+    let (output, _) = run_to_completion(&address, "src/com/example/app/Main.java").await;
+
+    assert_eq!(output, vec!["Hello packages!"]);
+}
+
+#[actix_rt::test]
+async fn maven_project_is_built_before_running() {
+    let address = spawn_app(MAVEN_TEST_DIRECTORY, COMMON_TEST_LANG);
+
+    let (output, close) =
+        run_to_completion(&address, "src/main/java/com/example/Greeting.java").await;
+
+    // build output is streamed first, then the manifest's main class is run
+    assert_eq!(
+        output.first().map(String::as_str),
+        Some("[INFO] Building test-maven-repo")
+    );
+    assert_eq!(output.last().map(String::as_str), Some("Hello from maven!"));
+    assert_ne!(close.map(|close| close.status_code), Some(1011));
+}
+
+#[actix_rt::test]
+async fn failed_maven_build_closes_run_with_build_error() {
+    let address = spawn_app(BROKEN_MAVEN_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let (output, close) = run_to_completion(&address, "src/main/java/com/example/App.java").await;
+
+    assert!(output.iter().any(|line| line.contains("';' expected")));
+    assert_eq!(
+        output.last().map(String::as_str),
+        Some("[ERROR] BUILD FAILURE")
+    );
+    let close = close.expect("no close frame for failed build");
+    assert_eq!(close.status_code, 1011);
+    assert_eq!(close.reason, "Program failed to build with maven");
+
+    let status: Value = client
+        .get(&format!("{}/code/run/status", address))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("status is not json");
+    assert_eq!(status["state"], "failed");
+    assert_eq!(status["exit_code"], 1);
+    assert_eq!(status["pid"], Value::Null);
+}
+
+#[actix_rt::test]
+async fn tests_in_maven_project_are_reported_by_case() {
+    let address = spawn_app(MAVEN_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/code/test/src/test/java", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert!(response.status().is_success());
+    let report: Value = response.json().await.expect("test report is not json");
+    assert_eq!(report["passed"], false);
+    let suite = &report["suites"][0];
+    assert_eq!(suite["name"], "com.example.GreetingTest");
+    assert_eq!(
+        (&suite["tests"], &suite["failures"]),
+        (&json!(2), &json!(1))
+    );
+    assert_eq!(suite["cases"][0]["status"], "passed");
+    let failed = &suite["cases"][1];
+    assert_eq!(failed["name"], "sourceIsGradle");
+    assert_eq!(failed["status"], "failed");
+    assert_eq!(
+        failed["failure"]["message"],
+        "expected: <gradle> but was: <maven>"
+    );
+    assert_eq!(
+        failed["failure"]["location"],
+        json!({"file": "src/test/java/com/example/GreetingTest.java", "line": 15})
+    );
+}
+
+#[actix_rt::test]
+async fn tests_in_broken_maven_project_fail_to_build() {
+    let address = spawn_app(BROKEN_MAVEN_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/code/test/", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        response.text().await.expect("no response body"),
+        "Program failed to build with maven"
+    );
+}
+
+#[actix_rt::test]
+async fn tests_for_a_missing_path_are_not_found() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/code/test/src/Missing.java", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn test_output_is_streamed_over_websocket_before_report() {
+    let address = spawn_app_with(C_TEST_DIRECTORY, Lang::Custom, |args| {
+        args.test_cmd = Some("echo testing".to_string());
+    });
+
+    let (messages, close) = websocket_messages(&address, "code/test/").await;
+
+    assert_eq!(messages[0], json!({"stream": "stdout", "text": "testing"}));
+    let report = &messages.last().expect("no report")["report"];
+    assert_eq!(report["passed"], true);
+    assert_eq!(report["suites"][0]["name"], "echo testing");
+    assert_eq!(close.map(|close| close.status_code), Some(1000));
+}
+
+#[actix_rt::test]
+async fn tests_are_stopped_at_the_time_limit() {
+    let address = spawn_app_with(C_TEST_DIRECTORY, Lang::Custom, |args| {
+        args.test_cmd = Some("sleep 30".to_string());
+        args.run_timeout = Some(1);
+    });
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/code/test/", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[actix_rt::test]
+async fn builds_are_stopped_at_the_time_limit() {
+    let address = spawn_app_with(C_TEST_DIRECTORY, Lang::Custom, |args| {
+        args.build_template = Some("sleep 30".to_string());
+        args.run_template = Some("true".to_string());
+        args.run_timeout = Some(1);
+    });
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&format!("{}/code/build/hello.c", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+/// Query string for a run, with JSON parameters encoded
+fn run_query(params: &[(&str, &str)]) -> String {
+    let url = reqwest::Url::parse_with_params("http://localhost/", params).expect("bad query");
+    url.query().unwrap_or_default().to_string()
+}
+
+#[actix_rt::test]
+async fn program_is_run_with_args_env_and_stdin_file() {
+    let address = spawn_app(JAVA_ARGS_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let query = run_query(&[
+        ("args", r#"["one", "two words"]"#),
+        ("env", r#"{"GREETING": "Hi"}"#),
+        ("stdin", "input.txt"),
+    ]);
+
+    let (output, _) = run_to_completion(&address, &format!("src/Echo.java?{}", query)).await;
+
+    assert_eq!(
+        output,
+        vec![
+            "args: one,two words",
+            "greeting: Hi",
+            "stdin: first line",
+            "stdin: second line"
+        ]
+    );
+}
+
+#[actix_rt::test]
+async fn saved_run_configuration_is_listed_and_can_be_overridden() {
+    let address = spawn_app(JAVA_ARGS_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let configurations: Value = client
+        .get(&format!(
+            "{}/code/run-configurations/src/Echo.java",
+            address
+        ))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("run configurations are not json");
+    assert_eq!(configurations[0]["name"], "saved");
+
+    let query = run_query(&[("config", "saved"), ("env", r#"{"GREETING": "Hi"}"#)]);
+    let (output, _) = run_to_completion(&address, &format!("src/Echo.java?{}", query)).await;
+
+    assert_eq!(output[0], "args: from,config");
+    assert_eq!(output[1], "greeting: Hi");
+    assert_eq!(output[2], "stdin: first line");
+}
+
+#[actix_rt::test]
+async fn stdin_file_outside_workspace_is_rejected() {
+    let address = spawn_app(JAVA_ARGS_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/code/run/src/Echo.java", address))
+        .query(&[("stdin", "../test-java-repo/src/Hello.java")])
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn run_configuration_is_saved_for_file() {
+    let workspace = tempfile::tempdir().expect("couldn't create workspace");
+    fs::write(workspace.path().join("input.txt"), "input").expect("couldn't write input");
+    let address = spawn_app(workspace.path().to_str().unwrap(), COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+    let configurations_address = format!("{}/code/run-configurations/src/Main.java", address);
+
+    let saved: Value = client
+        .post(&configurations_address)
+        .json(&json!({"name": "big input", "args": ["1000"], "stdin": "input.txt"}))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("run configurations are not json");
+    let listed: Value = client
+        .get(&configurations_address)
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("run configurations are not json");
+
+    assert_eq!(saved, listed);
+    assert_eq!(
+        listed,
+        json!([{"name": "big input", "args": ["1000"], "env": {}, "stdin": "input.txt", "main": null}])
+    );
+
+    let invalid = client
+        .post(&configurations_address)
+        .json(&json!({"name": "leaky", "stdin": "../input.txt"}))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn build_returns_compiler_diagnostics_per_file() {
+    let workspace = tempfile::tempdir().expect("couldn't create workspace");
+    fs::create_dir(workspace.path().join("src")).expect("couldn't create src");
+    fs::write(
+        workspace.path().join("src/Broken.java"),
+        "class Broken {\n    public static void main(String[] args) {\n        int x = 1\n    }\n}\n",
+    )
+    .expect("couldn't write program");
+    let address = spawn_app(workspace.path().to_str().unwrap(), COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&format!("{}/code/build/src/Broken.java", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let published: Value = response.json().await.expect("diagnostics are not json");
+    assert_eq!(published.as_array().map(Vec::len), Some(1));
+    assert!(published[0]["uri"]
+        .as_str()
+        .unwrap_or_default()
+        .ends_with("/src/Broken.java"));
+    let diagnostic = &published[0]["diagnostics"][0];
+    assert_eq!(diagnostic["range"]["start"]["line"], 2);
+    assert_eq!(diagnostic["severity"], 1);
+    assert_eq!(diagnostic["source"], "lsp-proxy build");
+    assert_eq!(diagnostic["message"], "';' expected");
+}
+
+#[actix_rt::test]
+async fn build_of_clean_file_clears_its_diagnostics() {
+    let address = spawn_app(C_TEST_DIRECTORY, Lang::C);
+    let client = reqwest::Client::new();
+
+    let published: Value = client
+        .post(&format!("{}/code/build/hello.c", address))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("diagnostics are not json");
+
+    assert_eq!(published.as_array().map(Vec::len), Some(1));
+    assert_eq!(published[0]["diagnostics"], json!([]));
+
+    let response = client
+        .post(&format!("{}/code/build/missing.c", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn c_file_is_built_and_run_with_default_template() {
+    let address = spawn_app(C_TEST_DIRECTORY, Lang::C);
+
+    let (output, close) = run_to_completion(&address, "hello.c").await;
+
+    assert_eq!(output, vec!["Hello, World! "]);
+    assert_ne!(close.map(|close| close.status_code), Some(1011));
+}
+
+#[actix_rt::test]
+async fn custom_language_is_run_with_run_template() {
+    let address = spawn_app_with(C_TEST_DIRECTORY, Lang::Custom, |args| {
+        args.build_template = Some("cp {file} {out}".to_string());
+        args.run_template = Some("head -n 1 {out}; echo args:".to_string());
+    });
+    let client = reqwest::Client::new();
+
+    let result: Value = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "hello.c", "args": ["one", "two"]}))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("exec result is not json");
+
+    assert_eq!(result["state"], "exited");
+    assert_eq!(result["stdout"], "#include <stdio.h>\nargs: one two\n");
+}
+
+#[actix_rt::test]
+async fn output_lines_are_tagged_with_their_stream() {
+    let address = spawn_app_with(C_TEST_DIRECTORY, Lang::Custom, |args| {
+        args.run_template = Some("echo out; echo err >&2".to_string());
+    });
+
+    let (messages, _) = run_messages(&address, "hello.c").await;
+
+    // the streams are read separately, so their lines can arrive in either order
+    assert_eq!(messages.len(), 3);
+    assert!(messages[0]["run_id"].is_u64());
+    assert!(messages.contains(&json!({"stream": "stdout", "text": "out"})));
+    assert!(messages.contains(&json!({"stream": "stderr", "text": "err"})));
+}
+
+#[actix_rt::test]
+async fn runs_over_the_session_limit_are_rejected() {
+    let address = spawn_app_with(C_TEST_DIRECTORY, Lang::Custom, |args| {
+        args.run_template = Some("sleep 30".to_string());
+        args.max_runs = 1;
+    });
+    let client = reqwest::Client::new();
+
+    let run_address = format!(
+        "{}/code/run/hello.c?session=a",
+        address.replace("http", "ws")
+    );
+    let (close_tx, close_rx) = mpsc::channel::<()>();
+    let socket = thread::spawn(move || {
+        let _client = ClientBuilder::new(&run_address)
+            .expect("bad websocket address")
+            .connect_insecure()
+            .expect("failed to open run websocket");
+        close_rx.recv().ok();
+    });
+    let mut runs = Value::Null;
+    for _ in 0..50 {
+        runs = client
+            .get(&format!("{}/code/runs", address))
+            .send()
+            .await
+            .expect("failed to execute request")
+            .json()
+            .await
+            .expect("runs are not json");
+        if runs.as_array().map(Vec::len) == Some(1) {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+    }
+    assert_eq!(runs.as_array().map(Vec::len), Some(1));
+
+    let response = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "hello.c", "session": "a"}))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // requests that aren't websockets are turned away before a run is started
+    let response = client
+        .get(&format!("{}/code/run/hello.c?session=b", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let running: Value = client
+        .get(&format!("{}/code/runs", address))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("runs are not json");
+    assert_eq!(running, runs);
+
+    close_tx.send(()).ok();
+    socket.join().expect("websocket thread panicked");
+}
+
+#[cfg(unix)]
+#[actix_rt::test]
+async fn stopping_a_template_run_stops_the_processes_it_started() {
+    let dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+    let pid_file = dir.path().join("pid");
+    let template = format!("sleep 30 & echo $! > '{}'; wait", pid_file.display());
+    let address = spawn_app_with(C_TEST_DIRECTORY, Lang::Custom, |args| {
+        args.run_template = Some(template);
+        args.run_timeout = Some(1);
+    });
+    let client = reqwest::Client::new();
+
+    let result: Value = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "hello.c"}))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("exec result is not json");
+    assert_eq!(result["state"], "timed_out");
+
+    // the shell's background `sleep` is in the program's process group, so it was stopped with it
+    let pid = fs::read_to_string(&pid_file).expect("template didn't record its pid");
+    let stat = format!("/proc/{}/stat", pid.trim());
+    let mut running = true;
+    for _ in 0..50 {
+        // a killed process lingers as a zombie until it is reaped
+        running = fs::read_to_string(&stat).is_ok_and(|stat| !stat.contains(") Z "));
+        if !running {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+    assert!(!running);
+}
+
+#[actix_rt::test]
+async fn custom_language_without_run_template_is_unsupported() {
+    let address = spawn_app(C_TEST_DIRECTORY, Lang::Custom);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "hello.c"}))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        response.text().await.expect("no error message"),
+        "Running this programming language is not currently supported"
+    );
+}
+
+#[actix_rt::test]
+async fn exec_returns_output_of_program_run_with_input() {
+    let address = spawn_app(JAVA_ARGS_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({
+            "file": "src/Echo.java",
+            "input": "typed line\n",
+            "args": ["one"],
+            "env": {"GREETING": "Hi"},
+        }))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let result: Value = response.json().await.expect("exec result is not json");
+    assert_eq!(result["state"], "exited");
+    assert_eq!(result["exit_code"], 0);
+    assert_eq!(
+        result["stdout"],
+        "args: one\ngreeting: Hi\nstdin: typed line\n"
+    );
+    assert_eq!(result["stderr"], "");
+    assert_eq!(result["truncated"], false);
+    assert_eq!(result["diagnostics"], json!([]));
+    assert!(result["duration_ms"].is_u64());
+}
+
+#[actix_rt::test]
+async fn exec_reports_compile_diagnostics() {
+    let workspace = tempfile::tempdir().expect("couldn't create workspace");
+    fs::create_dir(workspace.path().join("src")).expect("couldn't create src");
+    fs::write(
+        workspace.path().join("src/Broken.java"),
+        "class Broken {\n    public static void main(String[] args) {\n        int x = 1\n    }\n}\n",
+    )
+    .expect("couldn't write program");
+    let address = spawn_app(workspace.path().to_str().unwrap(), COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let result: Value = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "src/Broken.java"}))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("exec result is not json");
+
+    assert_eq!(result["state"], "failed");
+    assert_eq!(result["error"], "Program failed to compile");
+    assert_eq!(
+        result["diagnostics"],
+        json!([{
+            "file": "src/Broken.java",
+            "line": 3,
+            "column": 18,
+            "severity": "error",
+            "message": "';' expected",
+        }])
+    );
+}
+
+#[actix_rt::test]
+async fn exec_reports_build_tool_diagnostics() {
+    let address = spawn_app(BROKEN_MAVEN_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let result: Value = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "src/main/java/com/example/App.java"}))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("exec result is not json");
+
+    assert_eq!(result["state"], "failed");
+    assert_eq!(result["error"], "Program failed to build with maven");
+    let diagnostic = &result["diagnostics"][0];
+    assert_eq!(diagnostic["file"], "src/main/java/com/example/App.java");
+    assert_eq!(diagnostic["message"], "';' expected");
+}
+
+#[actix_rt::test]
+async fn exec_stops_program_at_time_and_output_limits() {
+    let workspace = tempfile::tempdir().expect("couldn't create workspace");
+    fs::write(
+        workspace.path().join("Spin.java"),
+        "class Spin {\n    public static void main(String[] args) {\n        while (true) System.out.println(\"spinning\");\n    }\n}\n",
+    )
+    .expect("couldn't write program");
+    let address = spawn_app_with(
+        workspace.path().to_str().unwrap(),
+        COMMON_TEST_LANG,
+        |args| {
+            args.run_timeout = Some(2);
+            args.max_output_bytes = Some(40);
+        },
+    );
+    let client = reqwest::Client::new();
+
+    let result: Value = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "Spin.java"}))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("exec result is not json");
+
+    assert_eq!(result["state"], "timed_out");
+    assert_eq!(result["truncated"], true);
+    assert_eq!(result["stdout"], "spinning\n".repeat(4) + "spin\n");
+}
+
+#[actix_rt::test]
+async fn exec_rejects_input_with_stdin_file() {
+    let address = spawn_app(JAVA_ARGS_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "src/Echo.java", "input": "typed", "stdin": "input.txt"}))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn finished_run_is_kept_in_history_with_transcript() {
+    let address = spawn_app(JAVA_ARGS_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let result: Value = client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "src/Echo.java", "args": ["one"], "env": {"GREETING": "Hi"}}))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("exec result is not json");
+    assert_eq!(result["state"], "exited");
+
+    let history: Value = client
+        .get(&format!("{}/code/runs/history", address))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("run history is not json");
+    let run = &history[0];
+    assert_eq!(run["file"], "src/Echo.java");
+    assert_eq!(run["args"], json!(["one"]));
+    assert_eq!(run["state"], "exited");
+    assert_eq!(run["exit_code"], 0);
+    assert!(run["ended_at"].as_u64() >= run["started_at"].as_u64());
+
+    let transcript: Value = client
+        .get(&format!("{}/code/runs/{}/transcript", address, run["id"]))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("transcript is not json");
+    assert_eq!(
+        transcript,
+        json!([
+            {"stream": "stdout", "text": "args: one\n"},
+            {"stream": "stdout", "text": "greeting: Hi\n"},
+        ])
+    );
+
+    let missing = client
+        .get(&format!("{}/code/runs/999/transcript", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,109 @@
+use crate::test_helper::{spawn_app, spawn_app_with, COMMON_TEST_DIRECTORY, COMMON_TEST_LANG};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::{sync::mpsc, thread, time::Duration};
+use websocket::{ClientBuilder, OwnedMessage};
+
+#[actix_rt::test]
+async fn debug_without_adapter_is_an_error() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/debug", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[actix_rt::test]
+async fn launch_starts_suspended_program_and_attaches_adapter() {
+    // cat echoes every message back, standing in for a debug adapter
+    let address = spawn_app_with(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, |args| {
+        args.debug_adapter_cmd = Some("cat".to_string())
+    });
+    let client = reqwest::Client::new();
+    let debug_address = format!("{}/debug", address.replace("http", "ws"));
+
+    // the websocket client is blocking, so it can't share the server's thread
+    let (messages_tx, messages_rx) = mpsc::channel::<(Value, Value)>();
+    let (close_tx, close_rx) = mpsc::channel::<()>();
+    let session = thread::spawn(move || {
+        let mut client = ClientBuilder::new(&debug_address)
+            .expect("bad websocket address")
+            .connect_insecure()
+            .expect("failed to open debug websocket");
+        let mut request = |message: Value| {
+            client
+                .send_message(&OwnedMessage::Text(message.to_string()))
+                .expect("failed to send request");
+            loop {
+                match client.recv_message().expect("websocket closed early") {
+                    OwnedMessage::Text(text) => {
+                        let message: Value = serde_json::from_str(&text).expect("not json");
+                        if message["type"] == "request" {
+                            return message;
+                        }
+                    }
+                    OwnedMessage::Close(_) => panic!("websocket closed early"),
+                    _ => (),
+                }
+            }
+        };
+
+        let initialize = request(json!({
+            "seq": 1, "type": "request", "command": "initialize", "arguments": {"adapterID": "java"}
+        }));
+        let attach = request(json!({
+            "seq": 2, "type": "request", "command": "launch", "arguments": {"program": "src/Hello.java"}
+        }));
+        messages_tx.send((initialize, attach)).ok();
+        close_rx.recv().ok();
+        client.send_message(&OwnedMessage::Close(None)).ok();
+    });
+
+    let (initialize, attach) = loop {
+        if let Ok(messages) = messages_rx.try_recv() {
+            break messages;
+        }
+        assert!(!session.is_finished(), "debug session ended early");
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    };
+    assert_eq!(initialize["command"], "initialize");
+    assert_eq!(attach["seq"], 2);
+    assert_eq!(attach["command"], "attach");
+    assert_eq!(attach["arguments"]["program"], "src/Hello.java");
+    assert_eq!(attach["arguments"]["hostName"], "127.0.0.1");
+    assert!(attach["arguments"]["port"].is_u64());
+
+    let status =
+        |client: &reqwest::Client| client.get(&format!("{}/code/run/status", address)).send();
+    let running: Value = status(&client)
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("status is not json");
+    assert_eq!(running["state"], "running");
+    assert_eq!(running["file"], "src/Hello.java");
+
+    // closing the debug session stops the debuggee
+    close_tx.send(()).ok();
+    let mut stopped = Value::Null;
+    for _ in 0..50 {
+        stopped = status(&client)
+            .await
+            .expect("failed to execute request")
+            .json()
+            .await
+            .expect("status is not json");
+        if stopped["state"] != "running" {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+    }
+    assert_eq!(stopped["state"], "stopped");
+    assert_eq!(stopped["pid"], running["pid"]);
+}
@@ -0,0 +1,14 @@
+import java.util.Scanner;
+
+class Echo {
+    public static void main(String[] args) {
+        System.out.println("args: " + String.join(",", args));
+        System.out.println("greeting: " + System.getenv("GREETING"));
+
+        Scanner in = new Scanner(System.in);
+        while (in.hasNextLine()) {
+            System.out.println("stdin: " + in.nextLine());
+        }
+        in.close();
+    }
+}
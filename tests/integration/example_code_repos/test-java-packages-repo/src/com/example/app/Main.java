@@ -0,0 +1,9 @@
+package com.example.app;
+
+import com.example.util.Greeter;
+
+public class Main {
+    public static void main(String[] args) {
+        System.out.println(Greeter.greet("packages"));
+    }
+}
@@ -0,0 +1,7 @@
+package com.example.util;
+
+public class Greeter {
+    public static String greet(String name) {
+        return "Hello " + name + "!";
+    }
+}
@@ -0,0 +1,17 @@
+package com.example;
+
+import static org.junit.jupiter.api.Assertions.assertEquals;
+
+import org.junit.jupiter.api.Test;
+
+class GreetingTest {
+    @Test
+    void sourceIsMaven() {
+        assertEquals("maven", Greeting.source());
+    }
+
+    @Test
+    void sourceIsGradle() {
+        assertEquals("gradle", Greeting.source());
+    }
+}
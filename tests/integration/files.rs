@@ -52,3 +52,21 @@ async fn check_directory_endpoint_returns_correct_json() {
     println!("{:#}", expected);
     assert_json_eq!(response, expected);
 }
+
+#[actix_rt::test]
+async fn root_uri_is_an_absolute_file_uri() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/code/directory/root", address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let uri = response.text().await.unwrap();
+    assert!(uri.starts_with("file:///"));
+    assert!(!uri.starts_with("file:////"));
+    assert!(uri.ends_with("/test-java-repo"));
+}
@@ -1,4 +1,10 @@
-use crate::test_helper::{spawn_app, COMMON_TEST_DIRECTORY, COMMON_TEST_LANG};
+use crate::test_helper::{
+    spawn_app, spawn_app_with, COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, C_TEST_DIRECTORY,
+};
+use lsp_proxy::config::Lang;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::time::Duration;
 
 #[actix_rt::test]
 async fn health_check_works() {
@@ -17,3 +23,71 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[actix_rt::test]
+async fn not_ready_until_language_server_is_initialized() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/ready", address))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let report: Value = response.json().await.expect("readiness report is not json");
+    assert_eq!(report["ready"], false);
+    assert_eq!(report["initialized"], false);
+    assert_eq!(report["capabilities"], Value::Null);
+    assert_eq!(report["program_running"], false);
+}
+
+#[actix_rt::test]
+async fn no_requests_are_in_flight_without_a_language_server_session() {
+    let address = spawn_app(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG);
+    let client = reqwest::Client::new();
+
+    let requests: Value = client
+        .get(&format!("{}/admin/requests", address))
+        .send()
+        .await
+        .expect("failed to execute request")
+        .json()
+        .await
+        .expect("in flight requests are not json");
+
+    assert_eq!(requests, json!([]));
+}
+
+#[actix_rt::test]
+async fn metrics_count_finished_runs() {
+    let address = spawn_app_with(C_TEST_DIRECTORY, Lang::Custom, |args| {
+        args.run_template = Some("echo hi".to_string());
+    });
+    let client = reqwest::Client::new();
+
+    client
+        .post(&format!("{}/code/exec", address))
+        .json(&json!({"file": "hello.c"}))
+        .send()
+        .await
+        .expect("failed to execute request");
+
+    // runs are counted once the registry sees them finish, which can be just after the response
+    let mut metrics = String::new();
+    for _ in 0..20 {
+        let response = client
+            .get(&format!("{}/metrics", address))
+            .send()
+            .await
+            .expect("failed to execute request");
+        assert!(response.status().is_success());
+        metrics = response.text().await.expect("no metrics");
+        if metrics.contains("lsp_proxy_program_runs_total{outcome=\"exited\"} 1") {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+    }
+    assert!(metrics.contains("lsp_proxy_program_runs_total{outcome=\"exited\"} 1"));
+}
@@ -0,0 +1,199 @@
+use crate::test_helper::{spawn_app_with, COMMON_TEST_DIRECTORY, COMMON_TEST_LANG};
+use lsp_proxy::language_server::{
+    recorder::{read_recording, Direction},
+    server::wrap_lsp_message,
+};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::{sync::mpsc, thread, time::Duration};
+use websocket::{ClientBuilder, OwnedMessage};
+
+#[actix_rt::test]
+async fn messages_are_recorded_as_the_language_server_sees_them() {
+    let record_dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+    let record_path = record_dir.path().to_string_lossy().to_string();
+    // cat echoes every message back, standing in for a language server
+    let address = spawn_app_with(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, |args| {
+        args.custom_lang_server_cmd = Some("cat".to_string());
+        args.record_lsp_dir = Some(record_path);
+        args.uri_map = vec!["inmemory://model/=file:///srv/ws/".parse().unwrap()];
+    });
+    let message = json!({
+        "jsonrpc": "2.0",
+        "method": "custom/notify",
+        "params": {"uri": "inmemory://model/Hello.java"},
+    });
+    let echo = echo_of(&address, message).await;
+    assert_eq!(
+        echo.expect("message was not echoed")["params"]["uri"],
+        "inmemory://model/Hello.java"
+    );
+
+    let mut recorded = Vec::new();
+    for _ in 0..50 {
+        let recordings: Vec<_> = std::fs::read_dir(record_dir.path())
+            .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+            .unwrap_or_default();
+        if let Some(recording) = recordings.first() {
+            recorded = read_recording(recording).expect("recording is not readable");
+            if recorded.len() == 2 {
+                break;
+            }
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+    assert_eq!(recorded.len(), 2);
+    // both directions are recorded with the server's URI, as the client's was rewritten before it was sent
+    for (message, direction) in recorded
+        .iter()
+        .zip(&[Direction::ClientToServer, Direction::ServerToClient])
+    {
+        assert_eq!(message.direction, *direction);
+        let message: Value = serde_json::from_str(&message.message).expect("not json");
+        assert_eq!(message["params"]["uri"], "file:///srv/ws/Hello.java");
+    }
+}
+
+#[actix_rt::test]
+async fn client_request_ids_are_kept_apart_from_other_sessions() {
+    let address = spawn_app_with(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, |args| {
+        args.custom_lang_server_cmd = Some("cat".to_string());
+    });
+    let message = json!({"jsonrpc": "2.0", "id": 7, "method": "custom/request", "params": {}});
+
+    // the echo is the request as the language server got it
+    let echo = echo_of(&address, message)
+        .await
+        .expect("message was not echoed");
+    let id = echo["id"].as_str().expect("request id was not rewritten");
+    assert!(id.starts_with("lsp-proxy/session-"));
+    assert!(id.ends_with("/7"));
+}
+
+#[actix_rt::test]
+async fn rest_requests_for_missing_documents_are_not_found() {
+    // a language server that never answers, which the request shouldn't get as far as
+    let address = spawn_app_with(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, |args| {
+        args.custom_lang_server_cmd = Some("cat > /dev/null".to_string());
+    });
+
+    let response = reqwest::get(&format!(
+        "{}/lsp/hover?path=src/Missing.java&line=0&col=0",
+        address
+    ))
+    .await
+    .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn rest_requests_denied_by_the_policy_are_forbidden() {
+    let policy_dir = tempfile::tempdir().expect("couldn't create directory for testing!");
+    let policy_path = policy_dir.path().join("policy.json");
+    let policy = json!({"rules": [{"method": "textDocument/hover", "action": "deny"}]});
+    std::fs::write(&policy_path, policy.to_string()).unwrap();
+    let address = spawn_app_with(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, |args| {
+        args.custom_lang_server_cmd = Some("cat > /dev/null".to_string());
+        args.message_policy = Some(policy_path.to_string_lossy().to_string());
+    });
+
+    let response = reqwest::get(&format!(
+        "{}/lsp/hover?path=src/Hello.java&line=0&col=0",
+        address
+    ))
+    .await
+    .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_rt::test]
+async fn rest_requests_the_language_server_does_not_answer_time_out() {
+    let address = spawn_app_with(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, |args| {
+        args.custom_lang_server_cmd = Some("cat > /dev/null".to_string());
+        args.lsp_request_timeout_ms = 200;
+    });
+
+    let response = reqwest::get(&format!("{}/lsp/symbols?path=src/Hello.java", address))
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[actix_rt::test]
+async fn server_requests_without_a_session_are_refused() {
+    // cat echoes the proxy's initialize request back as a request of the server's, whose refusal it then echoes as the response
+    let address = spawn_app_with(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, |args| {
+        args.custom_lang_server_cmd = Some("cat".to_string());
+    });
+
+    let response = reqwest::get(&format!("{}/lsp/symbols?path=src/Hello.java", address))
+        .await
+        .expect("failed to execute request");
+
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let body = response.text().await.expect("response has no body");
+    assert!(body.contains("No client is connected"));
+}
+
+#[actix_rt::test]
+async fn sessions_initialising_during_a_rest_request_are_answered_from_its_result() {
+    // a language server that answers the proxy's initialize a second after it is sent, and nothing else
+    let response = wrap_lsp_message(
+        r#"{"jsonrpc":"2.0","id":"lsp-proxy/rest-0","result":{"capabilities":{"hoverProvider":true}}}"#,
+    );
+    let server_cmd = format!(
+        "head -c 1 > /dev/null; sleep 1; printf '{}'; cat > /dev/null",
+        response.replace("\r\n", "\\r\\n")
+    );
+    let address = spawn_app_with(COMMON_TEST_DIRECTORY, COMMON_TEST_LANG, |args| {
+        args.custom_lang_server_cmd = Some(server_cmd);
+        args.lsp_request_timeout_ms = 2000;
+    });
+
+    let hover_address = format!("{}/lsp/hover?path=src/Hello.java&line=0&col=0", address);
+    let hover = reqwest::get(&hover_address);
+    let initialize = async {
+        tokio::time::delay_for(Duration::from_millis(300)).await;
+        let message = json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}});
+        echo_of(&address, message).await
+    };
+    let (_, answer) = tokio::join!(hover, initialize);
+
+    let answer = answer.expect("initialize was not answered");
+    assert_eq!(answer["id"], 1);
+    assert_eq!(answer["result"]["capabilities"]["hoverProvider"], true);
+}
+
+/// Sends a message over a language server session and returns the first message the session gets back
+async fn echo_of(address: &str, message: Value) -> Option<Value> {
+    let ls_address = format!("{}/ls", address.replace("http", "ws"));
+    let (echo_tx, echo_rx) = mpsc::channel::<Value>();
+    let session = thread::spawn(move || {
+        let mut client = ClientBuilder::new(&ls_address)
+            .expect("bad websocket address")
+            .connect_insecure()
+            .expect("failed to open language server websocket");
+        client
+            .send_message(&OwnedMessage::Text(message.to_string()))
+            .expect("failed to send message");
+        if let Ok(OwnedMessage::Text(echo)) = client.recv_message() {
+            echo_tx
+                .send(serde_json::from_str(&echo).expect("echo is not json"))
+                .ok();
+        }
+    });
+
+    let mut echo = None;
+    for _ in 0..50 {
+        echo = echo_rx.try_recv().ok();
+        if echo.is_some() {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+    session.join().expect("websocket thread panicked");
+    echo
+}
@@ -1,4 +1,10 @@
+// the baseline tests borrow their arguments to format!, which newer clippy flags
+#[allow(unknown_lints, clippy::useless_borrows_in_formatting)]
 mod code;
+mod debug;
+#[allow(unknown_lints, clippy::useless_borrows_in_formatting)]
 mod files;
+#[allow(unknown_lints, clippy::useless_borrows_in_formatting)]
 mod health;
+mod lsp;
 mod test_helper;
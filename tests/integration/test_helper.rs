@@ -1,38 +1,131 @@
-use std::{env, net::TcpListener};
+use std::{env, net::TcpListener, thread, time::Duration};
 
 use lsp_proxy::config::{Lang, LsArgs};
+use serde_json::Value;
+use websocket::{ClientBuilder, CloseData, OwnedMessage};
 
 pub const COMMON_TEST_DIRECTORY: &str = "./tests/integration/example_code_repos/test-java-repo";
 pub const COMMON_TEST_FILE: &str =
     "./tests/integration/example_code_repos/test-java-repo/src/Hello.java";
+pub const JAVA_PACKAGES_TEST_DIRECTORY: &str =
+    "./tests/integration/example_code_repos/test-java-packages-repo";
+pub const JAVA_ARGS_TEST_DIRECTORY: &str =
+    "./tests/integration/example_code_repos/test-java-args-repo";
+pub const MAVEN_TEST_DIRECTORY: &str = "./tests/integration/example_code_repos/test-maven-repo";
+pub const BROKEN_MAVEN_TEST_DIRECTORY: &str =
+    "./tests/integration/example_code_repos/test-maven-broken-repo";
+pub const C_TEST_DIRECTORY: &str = "./tests/integration/example_code_repos/test-c-repo";
 pub const COMMON_TEST_LANG: Lang = lsp_proxy::config::Lang::Java;
 const TEST_JAVA_SERVER_PATH: &str = "./";
 
 fn setup_program_args(args: &LsArgs) {
     env::set_var("CODEBASE_PATH", &args.codebase_path);
-    env::set_var("PORT", &args.port.to_string());
-    env::set_var("LANGUAGE", &args.language.to_string());
-    env::set_var("LANG_SERVER_PATH", &args.lang_server_path.to_string());
+    env::set_var("PORT", args.port.to_string());
+    env::set_var("LANGUAGE", args.language.to_string());
+    env::set_var("LANG_SERVER_PATH", &args.lang_server_path);
 }
 
 pub fn spawn_app(codebase_path: &str, language: Lang) -> String {
+    spawn_app_with(codebase_path, language, |_| ())
+}
+
+/// Spawns the app with extra configuration on top of the defaults used by `spawn_app`
+pub fn spawn_app_with(
+    codebase_path: &str,
+    language: Lang,
+    configure: impl FnOnce(&mut LsArgs),
+) -> String {
     let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind random port");
     // retrieve OS assigned port
     let port = listener.local_addr().unwrap().port();
-    let args = LsArgs {
+    let mut args = LsArgs {
         codebase_path: codebase_path.into(),
         port: port.into(),
         language,
         lang_server_path: TEST_JAVA_SERVER_PATH.into(),
         custom_lang_server_cmd: None,
+        build_repo_path: None,
+        junit_path: None,
+        test_cmd: None,
+        debug_adapter_cmd: None,
+        build_template: None,
+        run_template: None,
+        run_timeout: None,
+        max_output_bytes: None,
+        max_runs: 4,
+        data_dir: Some(
+            tempfile::tempdir()
+                .expect("couldn't create directory for testing!")
+                .into_path()
+                .to_string_lossy()
+                .to_string(),
+        ),
+        max_stored_runs: 100,
+        record_lsp_dir: None,
+        slow_request_ms: 5000,
+        lsp_request_timeout_ms: 10000,
+        initialization_options: None,
+        message_policy: None,
+        uri_map: Vec::new(),
+        answer_server_requests: false,
+        client_settings: None,
+        log_level: None,
+        log_json: false,
     };
+    configure(&mut args);
 
     // include_str!("./example_code_repos/test-java-repo/src/Hello.java");
     setup_program_args(&args);
 
     println!("test port: {}", port);
-    let server = lsp_proxy::test_run(listener).expect("failed to bind address");
-    let _ = tokio::spawn(server);
+    let server = lsp_proxy::test_run(listener, args).expect("failed to bind address");
+    tokio::spawn(server);
 
     format!("http://127.0.0.1:{}", port)
 }
+
+/// Runs a program over a websocket until the proxy closes it, returning the text of the output lines received and the close frame
+pub async fn run_to_completion(address: &str, path: &str) -> (Vec<String>, Option<CloseData>) {
+    let (messages, close) = run_messages(address, path).await;
+    let lines = messages
+        .iter()
+        .filter_map(|message| message["text"].as_str())
+        .map(String::from)
+        .collect();
+    (lines, close)
+}
+
+/// Runs a program over a websocket until the proxy closes it, returning the JSON text messages received and the close frame
+pub async fn run_messages(address: &str, path: &str) -> (Vec<Value>, Option<CloseData>) {
+    websocket_messages(address, &format!("code/run/{}", path)).await
+}
+
+/// Opens a websocket to the endpoint and reads it until the proxy closes it, returning the JSON text messages received and the close frame.
+/// The websocket client is blocking, so it runs on its own thread rather than the one serving the proxy.
+pub async fn websocket_messages(address: &str, endpoint: &str) -> (Vec<Value>, Option<CloseData>) {
+    let run_address = format!("{}/{}", address.replace("http", "ws"), endpoint);
+    let run = thread::spawn(move || {
+        let mut client = ClientBuilder::new(&run_address)
+            .expect("bad websocket address")
+            .connect_insecure()
+            .expect("failed to open run websocket");
+        let mut lines = Vec::new();
+        loop {
+            match client.recv_message() {
+                Ok(OwnedMessage::Text(line)) => {
+                    lines.push(serde_json::from_str(&line).expect("message is not json"))
+                }
+                Ok(OwnedMessage::Close(close)) => return (lines, close),
+                Ok(_) => (),
+                Err(_) => return (lines, None),
+            }
+        }
+    });
+
+    loop {
+        if run.is_finished() {
+            return run.join().expect("websocket thread panicked");
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+    }
+}